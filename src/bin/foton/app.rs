@@ -1,13 +1,23 @@
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fs,
+};
 
 use clap::Parser as _;
 use log::{info, warn};
 
-use foton::{Library, MediaType, TimeFormat, TimeSource};
+use foton::{
+    cluster_events, embedded_video_path, file_url, group_for_sheets, group_live_photos, known_tags,
+    plan_organize, tag_description, ContactSheets, EventCluster, EventNames, EventSources, Filter,
+    Gallery, Geocoder, GeotagTarget, Geotagger, Integrity, Library, LocationSource, MapExport,
+    MapFormat, Media, MediaItem, MediaType, Neighbors, TagValue, TimeFormat, TimeSource,
+    TimelineEntry, Track, WalkError,
+};
 
 use crate::{
-    cli::{Cli, Command, ConfigCommand, TagCommand},
-    config::Config,
+    cli::{Cli, Command, ConfigCommand, LibraryTagCommand, LiveCommand, TagArgs, TagCommand},
+    config::{Config, OrganizeConfig},
 };
 
 type AnyError = Box<dyn std::error::Error + Send + Sync>;
@@ -16,18 +26,530 @@ pub fn run() -> Result<(), AnyError> {
     let config = Config::load()?;
     info!("Loaded {:?}", config);
     let cli = Cli::parse();
+    let mut unreadable = UnreadablePaths::default();
 
     match cli.command {
-        Command::List { type_ } => {
+        Command::List {
+            type_,
+            sidecars,
+            filter,
+        } => {
+            if let Some(config) = config {
+                let lib = config.open_library()?;
+                let filter = MediaFilter::new(&config, filter)?;
+                let media = filter.media(&lib, &mut unreadable, type_.map(MediaType::from));
+                for f in media {
+                    println!("{}", f);
+                    if sidecars {
+                        for sidecar in f.sidecars() {
+                            println!("  {}", sidecar);
+                        }
+                    }
+                }
+            } else {
+                fallback_config_not_found()?;
+            }
+        }
+        Command::Find { filter } => {
             if let Some(config) = config {
-                let lib = Library::with_paths(config.library);
-                for f in lib.iter(type_.map(MediaType::from)) {
+                let lib = config.open_library()?;
+                let filter = MediaFilter::new(&config, Some(filter))?;
+                for f in filter.media(&lib, &mut unreadable, None) {
                     println!("{}", f);
                 }
             } else {
                 fallback_config_not_found()?;
             }
         }
+        Command::Events {
+            gap,
+            distance,
+            home,
+            filter,
+            media,
+        } => {
+            if let Some(config) = config {
+                let lib = config.open_library()?;
+                let mut options = config.events;
+                options.max_gap_minutes = gap.unwrap_or(options.max_gap_minutes);
+                options.max_distance_km = distance.unwrap_or(options.max_distance_km);
+                options.home = home.or(options.home);
+                let filter = MediaFilter::new(&config, filter)?;
+
+                let mut undated = 0;
+                let events = filter
+                    .media(&lib, &mut unreadable, None)
+                    .map(|f| {
+                        let event = f.get_event(&filter.sources);
+                        (f, event)
+                    })
+                    .filter(|(_, event)| {
+                        let dated = event.time().is_some();
+                        undated += usize::from(!dated);
+                        dated
+                    });
+                let print_media = |event: &EventCluster, indent: &str| {
+                    if media {
+                        for f in event.media() {
+                            println!("{}  {}", indent, f);
+                        }
+                    }
+                };
+                for entry in cluster_events(events, &options) {
+                    match entry {
+                        TimelineEntry::Event(event) => {
+                            println!("{}", event);
+                            print_media(&event, "");
+                        }
+                        TimelineEntry::Trip(trip) => {
+                            println!("{}", trip);
+                            for event in trip.events() {
+                                println!("  {}", event);
+                                print_media(event, "  ");
+                            }
+                        }
+                    }
+                }
+                if undated > 0 {
+                    println!("{} media without time", undated);
+                }
+            } else {
+                fallback_config_not_found()?;
+            }
+        }
+        Command::Organize {
+            template,
+            names,
+            out_dir,
+            filter,
+            move_,
+            dry_run,
+        } => {
+            if let Some(config) = config {
+                let lib = config.open_library()?;
+                let organize = config.organize.as_ref();
+                let template = match template {
+                    Some(template) => template,
+                    None => organize
+                        .map_or_else(OrganizeConfig::default_template, |o| o.template.clone())
+                        .parse()?,
+                };
+                let names = names.or_else(|| organize.and_then(|o| o.event_names.clone()));
+                let names = names
+                    .map(|path| EventNames::load(&path))
+                    .transpose()?
+                    .unwrap_or_default();
+                let out_dir = out_dir
+                    .or_else(|| config.foton_tagged_dir.clone())
+                    .ok_or("Either specify --out-dir or add foton_tagged_dir into config")?;
+                // the unnamed events are named by the place if the geocoding is configured
+                let filter = if template.uses_places() || template.uses_event_names() {
+                    MediaFilter::with_places(&config, filter, template.uses_places())?
+                } else {
+                    MediaFilter::new(&config, filter)?
+                };
+
+                // a Live Photo is selected as a whole if any of its parts matches,
+                // so the filter never separates them
+                let items: Vec<_> = group_live_photos(unreadable.skip(lib.try_iter(None)))
+                    .into_iter()
+                    .filter(|item| item.media().into_iter().any(|f| filter.matches(f)))
+                    .collect();
+                let media = items.iter().flat_map(MediaItem::media).map(|f| {
+                    let event = f.get_event(&filter.sources);
+                    (f.clone(), event)
+                });
+                let plan = plan_organize(
+                    media,
+                    &template,
+                    &config.events,
+                    &names,
+                    filter.geocoder.as_ref(),
+                );
+                let mut dirs: HashMap<_, _> = plan
+                    .iter()
+                    .map(|(f, dir)| (f.path().to_owned(), out_dir.join(dir)))
+                    .collect();
+                // the parts of a Live Photo are placed together by the photo
+                for item in items {
+                    let Some(dir) = dirs.remove(item.main().path()) else {
+                        continue;
+                    };
+                    if let Err(err) = item.check_targets(&dir, move_) {
+                        warn!("{}, skipping {}", err, item);
+                        continue;
+                    }
+                    if dry_run {
+                        println!("{} -> {}", item, dir.display());
+                        continue;
+                    }
+                    let result = if move_ {
+                        item.move_to(&dir)
+                    } else {
+                        item.copy_to(&dir)
+                    };
+                    match result {
+                        Ok(()) => println!("{} -> {}", item, dir.display()),
+                        Err(err) => warn!("{}: {}", item, err),
+                    }
+                }
+            } else {
+                fallback_config_not_found()?;
+            }
+        }
+        Command::Geotag {
+            track: tracks,
+            neighbors,
+            utc_offset,
+            max_gap,
+            filter,
+            overwrite,
+            exif,
+            dry_run,
+        } => {
+            if let Some(config) = config {
+                let lib = config.open_library()?;
+                let sources = config.event_sources();
+                if sources.time.is_empty() {
+                    return Err("Add metadata.time_source into config to geotag the media".into());
+                }
+                let mut options = config.geotag.clone();
+                options.utc_offset = utc_offset.or(options.utc_offset);
+                options.max_gap_minutes = max_gap.unwrap_or(options.max_gap_minutes);
+                let geotagger = if tracks.is_empty() {
+                    None
+                } else {
+                    let mut track = Track::default();
+                    for path in &tracks {
+                        let loaded = Track::load(path)
+                            .map_err(|err| format!("{}: {}", path.display(), err))?;
+                        info!(
+                            "Loaded {} points from {}",
+                            loaded.points().len(),
+                            path.display()
+                        );
+                        track.extend(loaded.points().iter().copied());
+                    }
+                    if track.points().is_empty() {
+                        return Err("No points with time found in the tracks".into());
+                    }
+                    Some(Geotagger::new(track, &options)?)
+                };
+                let target = if exif {
+                    GeotagTarget::Exif
+                } else {
+                    GeotagTarget::Sidecar
+                };
+                let filter = MediaFilter::new(&config, filter)?;
+
+                // the neighbors are searched among all the media, not only the filtered ones
+                let media: Vec<_> = unreadable
+                    .skip(lib.try_iter(None))
+                    .map(|f| {
+                        let tags = f
+                            .get_tags()
+                            .map_err(|err| warn!("Failed to get tags for {}: {:?}", f, err))
+                            .ok();
+                        let event = f.get_event_with_tags(&sources, tags.as_ref());
+                        // the UTC offset is found while the tags are at hand
+                        let utc_time = geotagger
+                            .as_ref()
+                            .and_then(|g| g.utc_time(&f, event.time()?, tags.as_ref()));
+                        (f, event, utc_time)
+                    })
+                    .collect();
+                let neighbors = neighbors.then(|| {
+                    let events = media.iter().map(|(f, event, _)| (f, event));
+                    Neighbors::new(events, &options)
+                });
+
+                let (mut matched, mut unmatched, mut undated, mut located) = (0, 0, 0, 0);
+                for (f, event, utc_time) in &media {
+                    if !filter.matches(f) {
+                        continue;
+                    }
+                    if event.location().is_some() && !overwrite {
+                        located += 1;
+                        continue;
+                    }
+                    let Some(time) = event.time() else {
+                        undated += 1;
+                        continue;
+                    };
+                    let found = geotagger
+                        .as_ref()
+                        .zip(*utc_time)
+                        .and_then(|(g, utc_time)| g.locate_at(utc_time))
+                        .map(|point| (point.location(), "track".to_owned()))
+                        .or_else(|| {
+                            let (other, location) = neighbors.as_ref()?.locate(f, time)?;
+                            Some((location, other.to_string()))
+                        });
+                    let Some((location, origin)) = found else {
+                        unmatched += 1;
+                        println!("{}: no location found near {}", f, time.datetime());
+                        continue;
+                    };
+                    matched += 1;
+                    if dry_run {
+                        println!("{}: {} from {}", f, location, origin);
+                        continue;
+                    }
+                    match f.write_location(&location, target) {
+                        Ok(path) => {
+                            println!("{}: {} from {} -> {}", f, location, origin, path.display());
+                        }
+                        Err(err) => warn!("{}: {:?}", f, err),
+                    }
+                }
+                println!(
+                    "{} matched, {} not matched, {} without time, {} already located",
+                    matched, unmatched, undated, located
+                );
+            } else {
+                fallback_config_not_found()?;
+            }
+        }
+        Command::Export {
+            format,
+            output,
+            tracks,
+            thumbnails,
+            filter,
+        } => {
+            if let Some(config) = config {
+                let format = format
+                    .or_else(|| output.as_deref().and_then(MapFormat::from_path))
+                    .unwrap_or(MapFormat::GeoJson);
+                let lib = config.open_library()?;
+                let filter = MediaFilter::new(&config, filter)?;
+
+                let media = filter.media(&lib, &mut unreadable, None).map(|f| {
+                    let event = f.get_event(&filter.sources);
+                    (f, event)
+                });
+                let mut cache = if thumbnails {
+                    Some(config.thumbnail_cache(&config.thumbnails.options)?)
+                } else {
+                    None
+                };
+                let map = MapExport::new(media, tracks, &config.events, |f| {
+                    let thumbnails = cache
+                        .as_mut()?
+                        .get(f, false)
+                        .map_err(|err| warn!("No thumbnail of {}: {}", f, err))
+                        .ok()?;
+                    // the smallest one is enough for a map popup
+                    let path = thumbnails.at_least(0)?;
+                    let path = fs::canonicalize(path).unwrap_or_else(|_| path.into());
+                    Some(file_url(&path))
+                });
+                if let Some(cache) = &mut cache {
+                    cache.save_index()?;
+                }
+                let rendered = map.render(format);
+                if let Some(output) = output {
+                    fs::write(&output, rendered)?;
+                    println!(
+                        "{} points, {} tracks -> {}",
+                        map.points().len(),
+                        map.tracks().len(),
+                        output.display()
+                    );
+                } else {
+                    println!("{}", rendered);
+                }
+            } else {
+                fallback_config_not_found()?;
+            }
+        }
+        Command::Thumbs {
+            type_,
+            size,
+            format,
+            filter,
+            force,
+            prune,
+        } => {
+            if let Some(config) = config {
+                let lib = config.open_library()?;
+                let mut options = config.thumbnails.options.clone();
+                if !size.is_empty() {
+                    options.sizes = size;
+                }
+                options.format = format.unwrap_or(options.format);
+                let mut cache = config.thumbnail_cache(&options)?;
+                let filter = MediaFilter::new(&config, filter)?;
+
+                let media = filter.media(&lib, &mut unreadable, type_.map(MediaType::from));
+                let (mut generated, mut fresh, mut failed) = (0, 0, 0);
+                let mut seen = HashSet::new();
+                for f in media {
+                    match cache.get(&f, force) {
+                        Ok(thumbs) if thumbs.generated => {
+                            generated += 1;
+                            if let Some(path) = thumbs.at_least(0) {
+                                println!("{} -> {}", f, path.display());
+                            }
+                        }
+                        Ok(_) => fresh += 1,
+                        Err(err) => {
+                            failed += 1;
+                            warn!("{}: {}", f, err);
+                        }
+                    }
+                    seen.insert(f.path().to_owned());
+                }
+                // keep the hashes of a long run even if the pruning fails
+                cache.save_index()?;
+                if prune {
+                    let removed = cache.prune(|path| seen.contains(path));
+                    cache.save_index()?;
+                    println!("{} unused thumbnails removed", removed?);
+                }
+                println!(
+                    "{} generated, {} fresh, {} failed in {}",
+                    generated,
+                    fresh,
+                    failed,
+                    cache.dir().display()
+                );
+            } else {
+                fallback_config_not_found()?;
+            }
+        }
+        Command::Gallery {
+            out_dir,
+            title,
+            names,
+            filter,
+        } => {
+            if let Some(config) = config {
+                // the thumbnails inside the gallery should not get into the library
+                let lib = config.open_library()?.exclude_dir(&out_dir);
+                let mut options = config.gallery.clone();
+                options.title = title.unwrap_or(options.title);
+                let names = names
+                    .or_else(|| config.organize.as_ref().and_then(|o| o.event_names.clone()))
+                    .map(|path| EventNames::load(&path))
+                    .transpose()?;
+                // the places are shown in the gallery if the geocoding is configured
+                let filter = MediaFilter::with_places(&config, filter, false)?;
+                let mut thumbnail_options = config.thumbnails.options.clone();
+                thumbnail_options
+                    .sizes
+                    .extend([options.thumbnail_size, options.preview_size]);
+                let mut cache = config.thumbnail_cache(&thumbnail_options)?;
+
+                let media = filter.media(&lib, &mut unreadable, None).map(|f| {
+                    let event = f.get_event(&filter.sources);
+                    (f, event)
+                });
+                let mut gallery = Gallery::new(&out_dir, &options, &config.events);
+                if let Some(names) = &names {
+                    gallery = gallery.with_names(names);
+                }
+                if let Some(geocoder) = &filter.geocoder {
+                    gallery = gallery.with_geocoder(geocoder);
+                }
+                let report = gallery.generate(media, &mut cache);
+                cache.save_index()?;
+                let report = report?;
+                println!(
+                    "{} media, {} files written, {} thumbnails copied, {} stale files removed",
+                    report.media, report.written, report.thumbnails_copied, report.removed
+                );
+                if report.failed > 0 {
+                    println!("{} media without thumbnails", report.failed);
+                }
+                println!("Open {}", out_dir.join("index.html").display());
+            } else {
+                fallback_config_not_found()?;
+            }
+        }
+        Command::ContactSheets {
+            out_dir,
+            by,
+            columns,
+            rows,
+            cell,
+            font,
+            filter,
+        } => {
+            if let Some(config) = config {
+                // the sheets should not get into the library
+                let lib = config.open_library()?.exclude_dir(&out_dir);
+                let mut options = config.contact_sheets.clone();
+                options.columns = columns.unwrap_or(options.columns);
+                options.rows = rows.unwrap_or(options.rows);
+                options.cell_size = cell.unwrap_or(options.cell_size);
+                options.font = font.or(options.font);
+                let sheets = ContactSheets::new(&options)?;
+                let filter = MediaFilter::new(&config, filter)?;
+                let mut thumbnail_options = config.thumbnails.options.clone();
+                thumbnail_options.sizes.push(options.cell_size);
+                let mut cache = config.thumbnail_cache(&thumbnail_options)?;
+
+                let media = filter.media(&lib, &mut unreadable, None).map(|f| {
+                    let event = f.get_event(&filter.sources);
+                    (f, event)
+                });
+                let written = group_for_sheets(media, by, &config.events)
+                    .iter()
+                    .try_for_each(|group| {
+                        for path in sheets.write(group, &mut cache, &out_dir)? {
+                            println!("{}", path.display());
+                        }
+                        Ok::<_, AnyError>(())
+                    });
+                cache.save_index()?;
+                written?;
+            } else {
+                fallback_config_not_found()?;
+            }
+        }
+        Command::Verify {
+            type_,
+            filter,
+            quarantine,
+        } => {
+            if let Some(config) = config {
+                let mut lib = config.open_library()?;
+                if let Some(quarantine) = &quarantine {
+                    // the quarantined media should not be checked again
+                    lib = lib.exclude_dir(quarantine);
+                }
+                let filter = MediaFilter::new(&config, filter)?;
+
+                let media = filter.media(&lib, &mut unreadable, type_.map(MediaType::from));
+                let (mut intact, mut unsupported, mut damaged) = (0, 0, 0);
+                for f in media {
+                    match f.verify() {
+                        Ok(Integrity::Intact) => intact += 1,
+                        Ok(Integrity::Unsupported) => {
+                            info!("{}: the format is not supported, skipping", f);
+                            unsupported += 1;
+                        }
+                        Err(defect) => {
+                            damaged += 1;
+                            println!("{}: {}", f, defect);
+                            if let Some(quarantine) = &quarantine {
+                                match f.quarantine(quarantine) {
+                                    Ok(moved) => println!("  -> {}", moved),
+                                    Err(err) => warn!("{}: {:?}", f, err),
+                                }
+                            }
+                        }
+                    }
+                }
+                println!(
+                    "{} intact, {} damaged, {} not supported",
+                    intact, damaged, unsupported
+                );
+            } else {
+                fallback_config_not_found()?;
+            }
+        }
         Command::Config(ca) => match ca.command {
             ConfigCommand::Print => {
                 if let Some(config) = config {
@@ -46,25 +568,68 @@ pub fn run() -> Result<(), AnyError> {
                 println!("{}", Config::stub());
             }
         },
-        Command::Tags(ta) => {
+        Command::Tags(TagArgs {
+            command: TagCommand::Known { filter, context },
+        }) => {
+            let filter: Vec<_> = filter.iter().map(|f| f.to_lowercase()).collect();
+            for known in known_tags() {
+                if context.as_ref().is_some_and(|ctx| known.context() != ctx) {
+                    continue;
+                }
+                let number = format!("0x{:04x}", known.number());
+                let matches = filter.is_empty()
+                    || filter.iter().any(|f| {
+                        known.id().to_lowercase().contains(f)
+                            || known.description().to_lowercase().contains(f)
+                            || number == *f
+                    });
+                if matches {
+                    println!("{}", known);
+                }
+            }
+        }
+        Command::Tags(TagArgs {
+            command: TagCommand::Library(command),
+        }) => {
             if let Some(config) = config {
-                let lib = Library::with_paths(config.library);
-                match ta.command {
-                    TagCommand::List { type_, tag_prefix } => {
-                        for resource in lib.iter(type_.map(MediaType::from)) {
+                let lib = config.open_library()?;
+                match command {
+                    LibraryTagCommand::List {
+                        type_,
+                        tag_prefix,
+                        describe,
+                    } => {
+                        // the place names are shown along with the tags if possible
+                        let geocoder = config.optional_geocoder();
+                        let sources = config.event_sources();
+                        for resource in unreadable.skip(lib.try_iter(type_.map(MediaType::from))) {
                             match resource.get_tags() {
-                                Ok(map) => {
+                                Ok(mut map) => {
+                                    if let Some(geocoder) = &geocoder {
+                                        add_place_tags(&mut map, &resource, &sources, geocoder);
+                                    }
                                     println!("--- {} ---", resource);
-                                    map.iter()
-                                        .filter(|(k, _)| {
+                                    let mut tags: Vec<_> = map
+                                        .iter()
+                                        .map(|(k, v)| (k, tag_description(k), v))
+                                        .filter(|(k, description, _)| {
                                             tag_prefix.is_empty()
-                                                || tag_prefix
-                                                    .iter()
-                                                    .any(|prefix| k.starts_with(prefix))
+                                                || tag_prefix.iter().any(|prefix| {
+                                                    k.starts_with(prefix)
+                                                        || description
+                                                            .is_some_and(|d| d.starts_with(prefix))
+                                                })
                                         })
-                                        .for_each(|(k, v)| {
-                                            println!("{}: {}", k, v);
-                                        });
+                                        .collect();
+                                    tags.sort_by_key(|(k, _, _)| *k);
+                                    for (k, description, v) in tags {
+                                        match description {
+                                            Some(description) if describe => {
+                                                println!("{} ({}): {}", k, description, v);
+                                            }
+                                            _ => println!("{}: {}", k, v),
+                                        }
+                                    }
                                     println!();
                                 }
                                 Err(err) => {
@@ -73,8 +638,16 @@ pub fn run() -> Result<(), AnyError> {
                             }
                         }
                     }
-                    TagCommand::GetTime { format, tag } => {
-                        let sources = if let Some(format) = format {
+                    LibraryTagCommand::GetTime {
+                        format,
+                        tag,
+                        takeout,
+                    } => {
+                        let sources = if let Some(utc_offset) = takeout {
+                            Cow::Owned(vec![TimeSource::Takeout {
+                                utc_offset: Some(utc_offset),
+                            }])
+                        } else if let Some(format) = format {
                             let format = TimeFormat::from(format);
                             let source = if let Some(name) = tag {
                                 TimeSource::Tag { name, format }
@@ -95,7 +668,7 @@ pub fn run() -> Result<(), AnyError> {
                                     .into(),
                             );
                         } else {
-                            for f in lib.iter_all() {
+                            for f in unreadable.skip(lib.try_iter(None)) {
                                 if let Some(time) = f.get_datetime(&sources) {
                                     println!("{}: {:?}", f, time);
                                 } else {
@@ -104,6 +677,91 @@ pub fn run() -> Result<(), AnyError> {
                             }
                         }
                     }
+                    LibraryTagCommand::GetLocation { type_ } => {
+                        let sources = config
+                            .metadata
+                            .as_ref()
+                            .map_or_else(LocationSource::all, |md| md.location_source.clone());
+                        let geocoder = config.optional_geocoder();
+                        for f in unreadable.skip(lib.try_iter(type_.map(MediaType::from))) {
+                            if let Some(location) = f.get_location(&sources) {
+                                let place = geocoder.as_ref().and_then(|g| g.locate(&location));
+                                if let Some(place) = place {
+                                    println!(
+                                        "{}: {} ({:?}) in {}",
+                                        f,
+                                        location,
+                                        location.source(),
+                                        place
+                                    );
+                                    continue;
+                                }
+                                println!("{}: {} ({:?})", f, location, location.source());
+                            } else {
+                                println!("{}: UNDEFINED", f);
+                            }
+                        }
+                    }
+                    LibraryTagCommand::GetTechnical { type_ } => {
+                        for f in unreadable.skip(lib.try_iter(type_.map(MediaType::from))) {
+                            println!("{}: {}", f, f.technical());
+                        }
+                    }
+                    LibraryTagCommand::GetVideo { streams } => {
+                        for f in unreadable.skip(lib.try_iter(None)) {
+                            if f.type_() == MediaType::Photo {
+                                continue;
+                            }
+                            match f.video_info() {
+                                Ok(info) => {
+                                    println!("{}: {}", f, info);
+                                    if streams {
+                                        for stream in &info.streams {
+                                            println!("  {}", stream);
+                                        }
+                                    }
+                                }
+                                Err(err) => warn!("{}: {:?}", f, err),
+                            }
+                        }
+                    }
+                }
+            } else {
+                fallback_config_not_found()?;
+            }
+        }
+        Command::Live(la) => {
+            if let Some(config) = config {
+                let lib = config.open_library()?;
+                let items = group_live_photos(unreadable.skip(lib.try_iter(None)));
+                match la.command {
+                    LiveCommand::List { only_live } => {
+                        for item in items {
+                            if !only_live || matches!(item, MediaItem::Live(_)) {
+                                println!("{}", item);
+                            }
+                        }
+                    }
+                    LiveCommand::Extract { out_dir, force } => {
+                        for item in items {
+                            let MediaItem::Live(live) = item else {
+                                continue;
+                            };
+                            let mut dest = embedded_video_path(live.photo().path());
+                            if let (Some(dir), Some(name)) = (&out_dir, dest.file_name()) {
+                                dest = dir.join(name);
+                            }
+                            if dest.exists() && !force {
+                                warn!("{} already exists, skipping", dest.display());
+                                continue;
+                            }
+                            match live.extract_video(&dest) {
+                                Ok(true) => println!("{} -> {}", live, dest.display()),
+                                Ok(false) => {}
+                                Err(err) => warn!("{}: {:?}", live, err),
+                            }
+                        }
+                    }
                 }
             } else {
                 fallback_config_not_found()?;
@@ -111,9 +769,127 @@ pub fn run() -> Result<(), AnyError> {
         }
     }
 
+    unreadable.report();
     Ok(())
 }
 
+/// The optional `--filter` of a command along with
+/// everything needed to match the media against it.
+struct MediaFilter {
+    filter: Option<Filter>,
+    sources: EventSources,
+    geocoder: Option<Geocoder>,
+}
+
+impl MediaFilter {
+    /// Load the geocoder only if the filter needs the place names.
+    fn new(config: &Config, filter: Option<Filter>) -> Result<Self, AnyError> {
+        let geocoder = config.geocoder(filter.as_ref().is_some_and(Filter::uses_places))?;
+        Ok(Self {
+            filter,
+            sources: config.event_sources(),
+            geocoder,
+        })
+    }
+
+    /// Load the geocoder for the command showing the place names
+    /// if it is configured, failing only if the places are `required`
+    /// (by the command itself or by the filter).
+    fn with_places(
+        config: &Config,
+        filter: Option<Filter>,
+        required: bool,
+    ) -> Result<Self, AnyError> {
+        let geocoder = if required || filter.as_ref().is_some_and(Filter::uses_places) {
+            config.geocoder(true)?
+        } else {
+            config.optional_geocoder()
+        };
+        Ok(Self {
+            filter,
+            sources: config.event_sources(),
+            geocoder,
+        })
+    }
+
+    fn matches(&self, media: &Media) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.matches(media, &self.sources, self.geocoder.as_ref()))
+    }
+
+    /// The readable media of the library (of the given type) matching the filter.
+    fn media<'a>(
+        &'a self,
+        lib: &'a Library,
+        unreadable: &'a mut UnreadablePaths,
+        type_: Option<MediaType>,
+    ) -> impl Iterator<Item = Media> + 'a {
+        unreadable
+            .skip(lib.try_iter(type_))
+            .filter(|f| self.matches(f))
+    }
+}
+
+#[derive(Debug, Default)]
+/// Collects the library entries failed to be read
+/// to report them at the end of a run.
+struct UnreadablePaths(Vec<WalkError>);
+
+impl UnreadablePaths {
+    fn skip<'a>(
+        &'a mut self,
+        entries: impl Iterator<Item = Result<Media, WalkError>> + 'a,
+    ) -> impl Iterator<Item = Media> + 'a {
+        entries.filter_map(|entry| {
+            entry
+                .map_err(|err| {
+                    warn!("Skipping unreadable entry: {}", err);
+                    self.0.push(err);
+                })
+                .ok()
+        })
+    }
+
+    fn report(&self) {
+        if self.0.is_empty() {
+            return;
+        }
+
+        eprintln!();
+        eprintln!("Failed to read {} path(s):", self.0.len());
+        for err in &self.0 {
+            let path = err
+                .path()
+                .map_or_else(|| "<unknown>".into(), |p| p.display().to_string());
+            eprintln!(" - {}: {}", path, err.kind());
+        }
+    }
+}
+
+/// Add the place names of the media location as the `place:` tags.
+fn add_place_tags(
+    tags: &mut HashMap<String, TagValue>,
+    media: &Media,
+    sources: &EventSources,
+    geocoder: &Geocoder,
+) {
+    let place = media
+        .get_location_with_tags(&sources.location, Some(tags))
+        .and_then(|location| geocoder.locate(&location));
+    if let Some(place) = place {
+        let mut add = |name: &str, value: String| {
+            tags.insert(format!("place:{}", name), TagValue::Text(value));
+        };
+        add("City", place.city);
+        if let Some(region) = place.region {
+            add("Region", region);
+        }
+        add("Country", place.country);
+        add("CountryCode", place.country_code);
+    }
+}
+
 fn fallback_config_not_found() -> Result<(), AnyError> {
     use std::fmt::Write as _;
 
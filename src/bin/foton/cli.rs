@@ -1,6 +1,24 @@
+use std::path::PathBuf;
+
 use clap::{builder::PossibleValue, Args, Parser, Subcommand, ValueEnum};
 
-use foton::MediaType;
+use foton::{
+    Filter, Home, MapFormat, MediaType, PathTemplate, SheetGrouping, ThumbnailFormat, TrackGrouping,
+};
+
+const FILTER_HELP: &str = r#"Select the media matching the expression.
+
+Examples:
+- 'type = video and duration > 60s'
+- 'year = 2022 month = jul..aug not has gps'
+- 'time = 2022-07-01..2022-07-15 or time_source = takeout'
+- 'camera ~ "pixel 7" iso >= 800 exposure > 1/60'
+- 'near(50.45, 30.52, 5km) or within(45, 22, 52, 40)'
+- 'has tag:xmp:Rating and tag:xmp:Rating >= 4'
+- 'path = "*/Vacation/*" ext != heic'
+- 'country = UA and city != Kyiv' (needs the geocoding config)
+
+See the README for the full list of fields."#;
 
 #[derive(Debug, Clone, Parser)]
 /// Manage the photos and videos collection.
@@ -15,6 +33,261 @@ pub(crate) enum Command {
     List {
         /// Type of the resource to find.
         type_: Option<PrivateMediaType>,
+
+        #[arg(long, short)]
+        /// Show the sidecar files of every media.
+        sidecars: bool,
+
+        #[arg(long, short, long_help = FILTER_HELP)]
+        /// Show only the media matching the filter expression.
+        filter: Option<Filter>,
+    },
+
+    /// Find the media matching the filter expression.
+    Find {
+        #[arg(long_help = FILTER_HELP)]
+        /// The filter expression, e.g. 'type = video and year = 2022'.
+        filter: Filter,
+    },
+
+    /// Group the media into events by their time and location.
+    ///
+    /// The defaults are taken from the `events` config section.
+    Events {
+        #[arg(long, value_name = "MINUTES")]
+        /// The longest pause between the media of a single event.
+        gap: Option<u32>,
+
+        #[arg(long, value_name = "KM")]
+        /// The longest distance between the consecutive media of a single event.
+        distance: Option<f64>,
+
+        #[arg(long, value_name = "LAT,LON[,RADIUS_KM]")]
+        /// Group the events away from home into trips.
+        home: Option<Home>,
+
+        #[arg(long, short, long_help = FILTER_HELP)]
+        /// Group only the media matching the filter expression.
+        filter: Option<Filter>,
+
+        #[arg(long, short)]
+        /// Show the media of every event.
+        media: bool,
+    },
+
+    /// Copy (or move) the media into the directories named by their time and events.
+    ///
+    /// The defaults are taken from the `organize` config section.
+    Organize {
+        #[arg(
+            long,
+            short,
+            long_help = r#"The template of the directories.
+
+Placeholders:
+- {year}, {month}, {day}, {time:%Y-%m-%d} of the media;
+- {type}, {make}, {model}, {camera};
+- {country}, {region}, {city} (needs the geocoding config);
+- {event_start:%Y-%m-%d}, {event_end:%Y-%m-%d}, {event_name}.
+
+Example: '{year}/{event_start:%Y-%m-%d} {event_name}'"#
+        )]
+        template: Option<PathTemplate>,
+
+        #[arg(long, value_name = "FILE")]
+        /// The TOML file with the names of the events.
+        names: Option<PathBuf>,
+
+        #[arg(long, short)]
+        /// Where to place the directories instead of the `foton_tagged_dir`.
+        out_dir: Option<PathBuf>,
+
+        #[arg(long, short, long_help = FILTER_HELP)]
+        /// Organize only the media matching the filter expression.
+        filter: Option<Filter>,
+
+        #[arg(long = "move")]
+        /// Move the files instead of copying.
+        move_: bool,
+
+        #[arg(long, short('n'))]
+        /// Only print where the media would be placed.
+        dry_run: bool,
+    },
+
+    /// Find the locations of the media shot without GPS in the recorded tracks
+    /// or borrow them from the media shot at about the same time.
+    ///
+    /// The time of the media is converted into UTC to match the track points.
+    /// The locations are stored into the XMP sidecars unless `--exif` is given.
+    /// The defaults are taken from the `geotag` config section.
+    Geotag {
+        #[arg(
+            long,
+            short,
+            required_unless_present = "neighbors",
+            value_name = "FILE"
+        )]
+        /// The track in the GPX or KML format (could be repeated).
+        track: Vec<PathBuf>,
+
+        #[arg(long)]
+        /// Borrow the location of the closest in time media having it
+        /// (if not found in the tracks), the location is marked as inferred.
+        neighbors: bool,
+
+        #[arg(long, value_name = "+HH:MM")]
+        /// The UTC offset of the cameras' clock for the media without the time zone tags.
+        utc_offset: Option<String>,
+
+        #[arg(long, value_name = "MINUTES")]
+        /// The longest time between the media and the track points (or the neighbors).
+        max_gap: Option<u32>,
+
+        #[arg(long, short, long_help = FILTER_HELP)]
+        /// Geotag only the media matching the filter expression.
+        filter: Option<Filter>,
+
+        #[arg(long)]
+        /// Replace the existing locations too.
+        overwrite: bool,
+
+        #[arg(long)]
+        /// Write the EXIF GPS tags into the JPEG files instead of the XMP sidecars.
+        exif: bool,
+
+        #[arg(long, short('n'))]
+        /// Only report the found locations.
+        dry_run: bool,
+    },
+
+    /// Export the media locations for viewing on a map.
+    ///
+    /// The tracks per event use the `events` config section.
+    Export {
+        #[arg(long, value_name = "geojson|kml")]
+        /// The format of the map (guessed by the output extension by default).
+        format: Option<MapFormat>,
+
+        #[arg(long, short, value_name = "FILE")]
+        /// Write the map into the file instead of the standard output.
+        output: Option<PathBuf>,
+
+        #[arg(long, value_name = "event|day")]
+        /// Connect the media locations of every event (or day) into the tracks ordered by time.
+        tracks: Option<TrackGrouping>,
+
+        #[arg(long)]
+        /// Add the link to the cached thumbnail to every point
+        /// (generating the missing ones).
+        thumbnails: bool,
+
+        #[arg(long, short, long_help = FILTER_HELP)]
+        /// Export only the media matching the filter expression.
+        filter: Option<Filter>,
+    },
+
+    /// Generate the thumbnails of the photos and videos into the cache directory.
+    ///
+    /// The unchanged media are skipped.
+    /// The defaults are taken from the `thumbnails` config section.
+    Thumbs {
+        /// Type of the media to process.
+        type_: Option<PrivateMediaType>,
+
+        #[arg(long, short, value_name = "PIXELS")]
+        /// The longest side of the thumbnails (could be repeated).
+        size: Vec<u32>,
+
+        #[arg(long, value_name = "jpeg|webp")]
+        /// The image format of the thumbnails.
+        format: Option<ThumbnailFormat>,
+
+        #[arg(long, short, long_help = FILTER_HELP)]
+        /// Process only the media matching the filter expression.
+        filter: Option<Filter>,
+
+        #[arg(long)]
+        /// Regenerate the thumbnails even if they are fresh.
+        force: bool,
+
+        #[arg(long, conflicts_with_all = ["type_", "filter"])]
+        /// Delete the thumbnails of the media not found in the library anymore.
+        prune: bool,
+    },
+
+    /// Generate a static HTML gallery viewable offline in a browser.
+    ///
+    /// The media are grouped by years, months and events.
+    /// Running again only updates the changed pages.
+    /// The defaults are taken from the `gallery` config section.
+    Gallery {
+        /// The directory of the site.
+        out_dir: PathBuf,
+
+        #[arg(long)]
+        /// The title of the site.
+        title: Option<String>,
+
+        #[arg(long, value_name = "FILE")]
+        /// The TOML file with the names of the events.
+        names: Option<PathBuf>,
+
+        #[arg(long, short, long_help = FILTER_HELP)]
+        /// Show only the media matching the filter expression.
+        filter: Option<Filter>,
+    },
+
+    /// Render the contact sheets: the grids of thumbnails
+    /// captioned with the file names and times.
+    ///
+    /// A large group is split into several numbered sheets.
+    /// The defaults are taken from the `contact_sheets` config section.
+    ContactSheets {
+        /// The directory to write the JPEG sheets into.
+        out_dir: PathBuf,
+
+        #[arg(long, short, value_name = "folder|day|event", default_value = "day")]
+        /// How to group the media into the sheets.
+        by: SheetGrouping,
+
+        #[arg(long)]
+        /// Number of the thumbnails in a row.
+        columns: Option<u32>,
+
+        #[arg(long)]
+        /// Number of the rows on a single sheet.
+        rows: Option<u32>,
+
+        #[arg(long, value_name = "PIXELS")]
+        /// The longest side of every thumbnail.
+        cell: Option<u32>,
+
+        #[arg(long, value_name = "FILE")]
+        /// The TrueType font of the captions.
+        font: Option<PathBuf>,
+
+        #[arg(long, short, long_help = FILTER_HELP)]
+        /// Include only the media matching the filter expression.
+        filter: Option<Filter>,
+    },
+
+    /// Find the damaged media: empty, truncated or corrupt files.
+    ///
+    /// The photos are decoded completely and the videos are demuxed,
+    /// so it takes a while for a large library.
+    Verify {
+        /// Type of the media to check.
+        type_: Option<PrivateMediaType>,
+
+        #[arg(long, short, long_help = FILTER_HELP)]
+        /// Check only the media matching the filter expression.
+        filter: Option<Filter>,
+
+        #[arg(long, value_name = "DIR")]
+        /// Move the damaged media along with their sidecars into the directory
+        /// (keeping their original directories inside).
+        quarantine: Option<PathBuf>,
     },
 
     /// View or create a configuration file.
@@ -22,6 +295,9 @@ pub(crate) enum Command {
 
     /// Show metadata.
     Tags(TagArgs),
+
+    /// Manage Live Photos and Motion Photos.
+    Live(LiveArgs),
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -81,6 +357,24 @@ pub(crate) struct TagArgs {
 
 #[derive(Debug, Clone, Subcommand)]
 pub(crate) enum TagCommand {
+    #[command(flatten)]
+    Library(LibraryTagCommand),
+
+    /// Browse the EXIF tags known to foton.
+    Known {
+        /// Show only the tags whose identifier, description
+        /// or number (e.g. `0x9003`) contains any of the given strings.
+        filter: Vec<String>,
+
+        #[arg(long, short)]
+        /// Show only the tags from the IFD: tiff, exif, gps or interop.
+        context: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+/// The tag commands reading the media of the library (so requiring a config).
+pub(crate) enum LibraryTagCommand {
     /// Extract all tags for every media file in the photos' collection.
     List {
         #[arg(long, short)]
@@ -88,8 +382,12 @@ pub(crate) enum TagCommand {
         type_: Option<PrivateMediaType>,
 
         #[arg(long, short('p'), value_name = "PREFIX")]
-        /// Filter tags by prefix.
+        /// Filter tags by the prefix of their identifiers or descriptions.
         tag_prefix: Vec<String>,
+
+        #[arg(long, short)]
+        /// Show the human-readable description of the known tags.
+        describe: bool,
     },
 
     /// Extract date and time information
@@ -112,11 +410,73 @@ If the format not specified, try various `metadata.time_source` from config"#)]
             long_help = r#"The metadata tag will be used as the source.
 
 Examples:
-- 'Date and time of original data generation' (EXIF tag);
-- 'creation_time' (mp4 tag);
+- 'exif:DateTimeOriginal' (EXIF tag);
+- 'ffmpeg:creation_time' (mp4 tag);
+- 'xmp:CreateDate' (XMP property);
+
+The EXIF tag description (e.g. 'Date and time of original data generation')
+is accepted as well.
 
 If the tag is missing, the file name will be used as the source."#
         )]
         tag: Option<String>,
+
+        #[arg(long, conflicts_with_all = ["format", "tag"])]
+        /// Use the time from Google Takeout JSON sidecars
+        /// converting it with the given UTC offset (e.g. `+03:00`).
+        takeout: Option<String>,
+    },
+
+    /// Extract the location information.
+    ///
+    /// The sources are taken from the `metadata.location_source` config option.
+    GetLocation {
+        #[arg(long, short)]
+        /// Type of the resource to find.
+        type_: Option<PrivateMediaType>,
+    },
+
+    /// Extract the technical information: dimensions, camera, lens and exposure.
+    GetTechnical {
+        #[arg(long, short)]
+        /// Type of the resource to find.
+        type_: Option<PrivateMediaType>,
+    },
+
+    /// Extract the technical information of the videos and animations:
+    /// duration, resolution, frame rate, codecs.
+    GetVideo {
+        #[arg(long, short)]
+        /// Show the properties of every stream.
+        streams: bool,
+    },
+}
+
+#[derive(Debug, Clone, Args)]
+pub(crate) struct LiveArgs {
+    #[command(subcommand)]
+    pub command: LiveCommand,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub(crate) enum LiveCommand {
+    /// List the media files combining Live Photos and Motion Photos into single items.
+    List {
+        #[arg(long)]
+        /// Show only Live Photos and Motion Photos.
+        only_live: bool,
+    },
+
+    /// Save the videos embedded into Motion Photos as separate files.
+    Extract {
+        #[arg(long, short)]
+        /// Where to save the videos.
+        ///
+        /// If not specified, the video is saved near the image.
+        out_dir: Option<PathBuf>,
+
+        #[arg(long)]
+        /// Overwrite the existing video files.
+        force: bool,
     },
 }
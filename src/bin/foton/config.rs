@@ -1,24 +1,101 @@
-use std::{env, fmt, fs, path::PathBuf};
+use std::{collections::BTreeMap, env, fmt, fs, path::PathBuf};
 
-use log::info;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 
-use foton::{TimeFormat, TimeSource};
+use foton::{
+    ClusterOptions, ContactSheetOptions, EventSources, ExtensionMap, ExtensionsOverride,
+    GalleryOptions, Geocoder, GeocoderConfig, GeotagOptions, Home, IgnoreRules, Library,
+    LocationSource, MediaType, ThumbnailCache, ThumbnailOptions, TimeFormat, TimeSource,
+    WalkOptions,
+};
 
 use super::AnyError;
 
 /// Configuration values
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
-    pub library: Vec<PathBuf>,
+    pub library: Vec<LibraryRoot>,
     pub foton_tagged_dir: Option<PathBuf>,
     pub metadata: Option<MetadataExtractorConfig>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub media_types: BTreeMap<MediaType, ExtensionsOverride>,
+    #[serde(default)]
+    pub ignore: IgnoreRules,
+    #[serde(default)]
+    pub events: ClusterOptions,
+    pub organize: Option<OrganizeConfig>,
+    pub geocoding: Option<GeocoderConfig>,
+    #[serde(default)]
+    pub geotag: GeotagOptions,
+    #[serde(default)]
+    pub thumbnails: ThumbnailsConfig,
+    #[serde(default)]
+    pub gallery: GalleryOptions,
+    #[serde(default)]
+    pub contact_sheets: ContactSheetOptions,
+}
+
+/// Single root directory of the library.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LibraryRoot {
+    /// Just a path walked with the default options.
+    Path(PathBuf),
+    /// A path with custom walking options.
+    WithOptions {
+        path: PathBuf,
+        #[serde(flatten)]
+        options: WalkOptions,
+    },
+}
+
+impl LibraryRoot {
+    fn to_parts(&self) -> (PathBuf, WalkOptions) {
+        match self {
+            Self::Path(path) => (path.clone(), WalkOptions::default()),
+            Self::WithOptions { path, options } => (path.clone(), *options),
+        }
+    }
 }
 
 /// Settings for getting relevant metadata from media.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetadataExtractorConfig {
     pub time_source: Vec<TimeSource>,
+    #[serde(default = "LocationSource::all")]
+    pub location_source: Vec<LocationSource>,
+}
+
+/// How to lay out the organized copies of the media.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrganizeConfig {
+    /// The template of the directories, e.g. `{year}/{month}`.
+    #[serde(default = "OrganizeConfig::default_template")]
+    pub template: String,
+    /// The file with the names of the events.
+    pub event_names: Option<PathBuf>,
+}
+
+impl OrganizeConfig {
+    pub fn default_template() -> String {
+        "{year}/{month}".into()
+    }
+}
+
+/// Where and how to store the thumbnails.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ThumbnailsConfig {
+    /// The cache directory (`~/.cache/foton/thumbnails` by default).
+    pub cache_dir: Option<PathBuf>,
+    #[serde(flatten)]
+    pub options: ThumbnailOptions,
+}
+
+impl ThumbnailsConfig {
+    fn default_cache_dir() -> Option<PathBuf> {
+        home::home_dir().map(|hd| hd.join(".cache").join("foton").join("thumbnails"))
+    }
 }
 
 const CONFIG_NAME: &str = "foton.toml";
@@ -40,8 +117,16 @@ impl Config {
     pub fn stub() -> Self {
         Self {
             library: home::home_dir()
-                .map(|hd| hd.join("Photos"))
+                .map(|hd| LibraryRoot::Path(hd.join("Photos")))
                 .into_iter()
+                .chain(home::home_dir().map(|hd| LibraryRoot::WithOptions {
+                    path: hd.join("Videos"),
+                    options: WalkOptions {
+                        follow_links: true,
+                        max_depth: Some(3),
+                        same_file_system: true,
+                    },
+                }))
                 .collect(),
             foton_tagged_dir: home::home_dir().map(|hd| hd.join("Photos").join("tagged")),
             metadata: Some(MetadataExtractorConfig {
@@ -67,23 +152,40 @@ impl Config {
                         format: TimeFormat::from("%Y%m%d_%H%M%S.jpg"),
                     },
                     TimeSource::Tag {
-                        name: "Date and time of original data generation".into(),
+                        name: "exif:DateTimeOriginal".into(),
                         format: TimeFormat::from("%F %T"),
                     },
                     TimeSource::Tag {
-                        name: "creation_time".into(),
+                        name: "xmp:CreateDate".into(),
+                        format: TimeFormat {
+                            fmt: "%Y-%m-%dT%H:%M:%S".into(),
+                            take_prefix: Some(19),
+                            only_date: false,
+                        },
+                    },
+                    TimeSource::Tag {
+                        name: "iptc:DateTimeCreated".into(),
+                        format: TimeFormat {
+                            fmt: "%Y%m%d%H%M%S".into(),
+                            take_prefix: Some(14),
+                            only_date: false,
+                        },
+                    },
+                    TimeSource::Tag {
+                        name: "ffmpeg:creation_time".into(),
                         format: TimeFormat::from("%+"),
                     },
+                    TimeSource::Takeout { utc_offset: None },
                     TimeSource::Tag {
-                        name: "Date and time of digital data generation".into(),
+                        name: "exif:DateTimeDigitized".into(),
                         format: TimeFormat::from("%F %T"),
                     },
                     TimeSource::Tag {
-                        name: "File change date and time".into(),
+                        name: "exif:DateTime".into(),
                         format: TimeFormat::from("%F %T"),
                     },
                     TimeSource::Tag {
-                        name: "GPS date".into(),
+                        name: "exif:GPSDateStamp".into(),
                         format: TimeFormat {
                             fmt: "%F".into(),
                             take_prefix: None,
@@ -105,8 +207,116 @@ impl Config {
                         },
                     },
                 ],
+                location_source: LocationSource::all(),
+            }),
+            media_types: [(
+                MediaType::Photo,
+                ExtensionsOverride {
+                    add: vec!["mpo".into()],
+                    remove: vec![],
+                },
+            )]
+            .into_iter()
+            .collect(),
+            ignore: IgnoreRules {
+                hidden: true,
+                exclude: vec!["@eaDir".into(), "#recycle".into()],
+            },
+            events: ClusterOptions {
+                home: Some(Home {
+                    latitude: 50.4501,
+                    longitude: 30.5234,
+                    radius_km: 50.0,
+                }),
+                ..ClusterOptions::default()
+            },
+            organize: Some(OrganizeConfig {
+                template: "{year}/{event_start:%Y-%m-%d} {event_name}".into(),
+                event_names: home::home_dir().map(|hd| hd.join("Photos").join("events.toml")),
             }),
+            // the GeoNames dumps should be downloaded first
+            geocoding: None,
+            geotag: GeotagOptions {
+                utc_offset: Some("+03:00".into()),
+                ..GeotagOptions::default()
+            },
+            thumbnails: ThumbnailsConfig {
+                cache_dir: ThumbnailsConfig::default_cache_dir(),
+                options: ThumbnailOptions::default(),
+            },
+            gallery: GalleryOptions {
+                title: "Family photos".into(),
+                ..GalleryOptions::default()
+            },
+            contact_sheets: ContactSheetOptions::default(),
+        }
+    }
+
+    /// Create a [`Library`] from the configured paths
+    /// honoring the media types' and ignore settings.
+    ///
+    /// The [tagged directory][Self::foton_tagged_dir] is always excluded
+    /// to prevent processing the organized copies again.
+    pub fn open_library(&self) -> Result<Library, AnyError> {
+        let lib = Library::with_roots(self.library.iter().map(LibraryRoot::to_parts))
+            .with_extensions(ExtensionMap::with_overrides(&self.media_types))
+            .with_ignore_rules(&self.ignore)?;
+        Ok(if let Some(tagged_dir) = &self.foton_tagged_dir {
+            lib.exclude_dir(tagged_dir)
+        } else {
+            lib
+        })
+    }
+
+    /// Where to look for the time and location of the media
+    /// according to the `metadata` settings.
+    pub fn event_sources(&self) -> EventSources {
+        self.metadata.as_ref().map_or_else(
+            || EventSources {
+                time: vec![],
+                location: LocationSource::all(),
+            },
+            |md| EventSources {
+                time: md.time_source.clone(),
+                location: md.location_source.clone(),
+            },
+        )
+    }
+
+    /// Load the reverse geocoder if it is `required`
+    /// (e.g. the filter or the template uses the place names).
+    pub fn geocoder(&self, required: bool) -> Result<Option<Geocoder>, AnyError> {
+        if !required {
+            return Ok(None);
         }
+        let config = self
+            .geocoding
+            .as_ref()
+            .ok_or("Add the geocoding section into config to use the place names")?;
+        Geocoder::load(config).map(Some)
+    }
+
+    /// Load the reverse geocoder if it is configured
+    /// to show the place names where possible.
+    ///
+    /// Unlike the [required][Self::geocoder] one, the failure to load it
+    /// is only reported.
+    pub fn optional_geocoder(&self) -> Option<Geocoder> {
+        let config = self.geocoding.as_ref()?;
+        Geocoder::load(config)
+            .map_err(|err| warn!("The place names are not available: {}", err))
+            .ok()
+    }
+
+    /// Open the [thumbnails cache][Self::thumbnails] with the given options.
+    pub fn thumbnail_cache(&self, options: &ThumbnailOptions) -> Result<ThumbnailCache, AnyError> {
+        let dir = self
+            .thumbnails
+            .cache_dir
+            .clone()
+            .or_else(ThumbnailsConfig::default_cache_dir)
+            .ok_or("Add thumbnails.cache_dir into config")?;
+        ThumbnailCache::open(&dir, options)
     }
 
     /// Load the config file from
@@ -0,0 +1,636 @@
+//! Contact sheets: the images with a grid of thumbnails captioned
+//! by the file name and the time, one (or several) per folder, day or event.
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use ab_glyph::{point, Font as _, FontArc, PxScale, ScaleFont as _};
+use chrono::NaiveDateTime;
+use image::{codecs::jpeg::JpegEncoder, imageops, Rgb, RgbImage};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    event::{
+        cluster::{cluster_events, ClusterOptions, TimelineEntry},
+        Event,
+    },
+    file_types::Media,
+    thumbnail::ThumbnailCache,
+    AnyError,
+};
+
+const BACKGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+const TEXT: Rgb<u8> = Rgb([0, 0, 0]);
+const CAPTION: Rgb<u8> = Rgb([90, 90, 90]);
+const PLACEHOLDER: Rgb<u8> = Rgb([225, 225, 225]);
+
+/// The largest width or height of a JPEG image.
+const MAX_SHEET_SIDE: u32 = u16::MAX as u32;
+/// The largest sheet to allocate (768 MiB of RGB pixels).
+const MAX_SHEET_PIXELS: u64 = 1 << 28;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// How to split the media into the contact sheets.
+pub enum SheetGrouping {
+    /// A sheet per directory.
+    Folder,
+    /// A sheet per calendar day.
+    Day,
+    /// A sheet per [event][crate::EventCluster].
+    Event,
+}
+
+impl FromStr for SheetGrouping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "folder" => Ok(Self::Folder),
+            "day" => Ok(Self::Day),
+            "event" => Ok(Self::Event),
+            _ => Err(format!(
+                "unknown grouping {:?}, expected folder, day or event",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The layout of the contact sheets.
+pub struct ContactSheetOptions {
+    #[serde(default = "ContactSheetOptions::default_columns")]
+    /// Number of the thumbnails in a row.
+    pub columns: u32,
+
+    #[serde(default = "ContactSheetOptions::default_rows")]
+    /// Number of the rows on a single sheet (the rest go to the next sheets).
+    pub rows: u32,
+
+    #[serde(default = "ContactSheetOptions::default_cell_size")]
+    /// The longest side of every thumbnail in pixels.
+    pub cell_size: u32,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// The TrueType (or OpenType) font of the captions instead of the built-in Noto Sans.
+    pub font: Option<PathBuf>,
+}
+
+impl ContactSheetOptions {
+    const fn default_columns() -> u32 {
+        5
+    }
+
+    const fn default_rows() -> u32 {
+        6
+    }
+
+    const fn default_cell_size() -> u32 {
+        240
+    }
+}
+
+impl Default for ContactSheetOptions {
+    fn default() -> Self {
+        Self {
+            columns: Self::default_columns(),
+            rows: Self::default_rows(),
+            cell_size: Self::default_cell_size(),
+            font: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The media to place onto the sheets with the same title.
+pub struct SheetGroup {
+    /// The title printed on the sheets.
+    pub title: String,
+    /// The base name of the sheet files.
+    pub file_stem: String,
+    /// The media with their time.
+    pub media: Vec<(Media, Option<NaiveDateTime>)>,
+}
+
+/// Split the media into the groups ordered by time (or by path for the folders).
+///
+/// The media without time get into the separate `undated` group
+/// unless grouped by the [folder][SheetGrouping::Folder].
+///
+/// The file stems are unique (case-insensitively): the clashing ones
+/// (e.g. of the folders `a/b_c` and `a_b/c`) get the numeric suffixes.
+pub fn group_for_sheets(
+    media: impl IntoIterator<Item = (Media, Event)>,
+    grouping: SheetGrouping,
+    options: &ClusterOptions,
+) -> Vec<SheetGroup> {
+    let mut media: Vec<_> = media
+        .into_iter()
+        .map(|(media, event)| {
+            let time = event.time().map(|time| time.datetime());
+            (media, time, event)
+        })
+        .collect();
+    media.sort_by(|(a, a_time, _), (b, b_time, _)| {
+        (a_time.is_none(), a_time, a.path()).cmp(&(b_time.is_none(), b_time, b.path()))
+    });
+
+    let (dated, undated): (Vec<_>, Vec<_>) = media
+        .into_iter()
+        .partition(|(_, time, _)| time.is_some() || grouping == SheetGrouping::Folder);
+    let mut groups: Vec<_> = match grouping {
+        SheetGrouping::Folder => {
+            let mut folders: BTreeMap<PathBuf, Vec<_>> = BTreeMap::new();
+            for (media, time, _) in dated {
+                let folder = media.path().parent().unwrap_or(Path::new("")).to_owned();
+                folders.entry(folder).or_default().push((media, time));
+            }
+            folders
+                .into_iter()
+                .map(|(folder, media)| SheetGroup {
+                    title: folder.display().to_string(),
+                    file_stem: sanitize(&folder.to_string_lossy()),
+                    media,
+                })
+                .collect()
+        }
+        SheetGrouping::Day => {
+            let mut days: BTreeMap<_, Vec<_>> = BTreeMap::new();
+            for (media, time, _) in dated {
+                if let Some(time) = time {
+                    days.entry(time.date())
+                        .or_default()
+                        .push((media, Some(time)));
+                }
+            }
+            days.into_iter()
+                .map(|(day, media)| SheetGroup {
+                    title: day.format("%A, %-d %B %Y").to_string(),
+                    file_stem: day.to_string(),
+                    media,
+                })
+                .collect()
+        }
+        SheetGrouping::Event => {
+            let times: BTreeMap<_, _> = dated
+                .iter()
+                .map(|(media, time, _)| (media.path().to_owned(), *time))
+                .collect();
+            let dated = dated.into_iter().map(|(media, _, event)| (media, event));
+            cluster_events(dated, options)
+                .into_iter()
+                .flat_map(|entry| match entry {
+                    TimelineEntry::Event(event) => vec![event],
+                    TimelineEntry::Trip(trip) => trip.events().to_vec(),
+                })
+                .map(|event| SheetGroup {
+                    title: format!(
+                        "{} - {}",
+                        event.start().format("%Y-%m-%d %H:%M"),
+                        event.end().format("%Y-%m-%d %H:%M")
+                    ),
+                    file_stem: event.start().format("%Y-%m-%d_%H%M%S").to_string(),
+                    media: event
+                        .media()
+                        .iter()
+                        .map(|media| (media.clone(), times.get(media.path()).copied().flatten()))
+                        .collect(),
+                })
+                .collect()
+        }
+    };
+    if !undated.is_empty() {
+        groups.push(SheetGroup {
+            title: "Undated".into(),
+            file_stem: "undated".into(),
+            media: undated
+                .into_iter()
+                .map(|(media, time, _)| (media, time))
+                .collect(),
+        });
+    }
+
+    let mut stems = HashSet::new();
+    for group in &mut groups {
+        let stem = group.file_stem.clone();
+        let mut n = 1;
+        while !stems.insert(group.file_stem.to_lowercase()) {
+            n += 1;
+            group.file_stem = format!("{}_{}", stem, n);
+        }
+    }
+    groups
+}
+
+/// Replace the characters unsafe for a file name.
+fn sanitize(name: &str) -> String {
+    let sanitized: String = name
+        .trim_start_matches(['/', '\\'])
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if sanitized.is_empty() {
+        "root".into()
+    } else {
+        sanitized
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+/// The sizes derived from the cell size.
+struct Layout {
+    cell: u32,
+    gap: u32,
+    caption_size: f32,
+    title_size: f32,
+    caption_height: u32,
+    header: u32,
+}
+
+impl Layout {
+    fn new(cell: u32) -> Self {
+        #[allow(clippy::cast_precision_loss)]
+        let caption_size = (cell as f32 / 14.0).max(11.0);
+        let title_size = caption_size * 1.8;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let caption_height = (caption_size * 2.6).ceil() as u32;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let header = (title_size * 2.0).ceil() as u32;
+        Self {
+            cell,
+            gap: cell / 10,
+            caption_size,
+            title_size,
+            caption_height,
+            header,
+        }
+    }
+
+    /// The width and height of the sheet
+    /// or `None` if it does not fit into [`MAX_SHEET_SIDE`] and [`MAX_SHEET_PIXELS`].
+    fn sheet_size(&self, columns: u32, rows: u32) -> Option<(u32, u32)> {
+        let width = columns
+            .checked_mul(self.cell.checked_add(self.gap)?)?
+            .checked_add(self.gap)?;
+        let row_height = self
+            .cell
+            .checked_add(self.caption_height)?
+            .checked_add(self.gap)?;
+        let height = rows
+            .checked_mul(row_height)?
+            .checked_add(self.header)?
+            .checked_add(self.gap)?;
+        let fits = width <= MAX_SHEET_SIDE
+            && height <= MAX_SHEET_SIDE
+            && u64::from(width) * u64::from(height) <= MAX_SHEET_PIXELS;
+        fits.then_some((width, height))
+    }
+}
+
+#[derive(Debug)]
+/// Renderer of the contact sheets.
+pub struct ContactSheets {
+    options: ContactSheetOptions,
+    font: FontArc,
+}
+
+impl ContactSheets {
+    /// Load the font and check the layout.
+    pub fn new(options: &ContactSheetOptions) -> Result<Self, AnyError> {
+        if options.columns == 0 || options.rows == 0 || options.cell_size < 32 {
+            return Err("The contact sheet needs at least one row and column of 32+ pixels".into());
+        }
+        if Layout::new(options.cell_size)
+            .sheet_size(options.columns, options.rows)
+            .is_none()
+        {
+            return Err(format!(
+                "The contact sheet of {}x{} cells of {} pixels is too large",
+                options.columns, options.rows, options.cell_size
+            )
+            .into());
+        }
+        let font = match &options.font {
+            Some(path) => FontArc::try_from_vec(fs::read(path)?)
+                .map_err(|err| format!("Invalid font {}: {}", path.display(), err))?,
+            None => FontArc::try_from_slice(notosans::REGULAR_TTF)?,
+        };
+        Ok(Self {
+            options: options.clone(),
+            font,
+        })
+    }
+
+    /// Render the sheets of the group into the directory as JPEG files
+    /// (`{file_stem}.jpg` or `{file_stem}-{n}.jpg` if there are several sheets).
+    pub fn write(
+        &self,
+        group: &SheetGroup,
+        cache: &mut ThumbnailCache,
+        out_dir: &Path,
+    ) -> Result<Vec<PathBuf>, AnyError> {
+        fs::create_dir_all(out_dir)?;
+        // checked in the constructor to fit into the sheet size
+        let per_sheet = (self.options.columns * self.options.rows) as usize;
+        let pages: Vec<_> = group.media.chunks(per_sheet).collect();
+        let mut written = vec![];
+        for (i, page) in pages.iter().enumerate() {
+            let (title, name) = if pages.len() > 1 {
+                (
+                    format!("{} ({}/{})", group.title, i + 1, pages.len()),
+                    format!("{}-{}.jpg", group.file_stem, i + 1),
+                )
+            } else {
+                (group.title.clone(), format!("{}.jpg", group.file_stem))
+            };
+            let sheet = self.render(&title, page, cache)?;
+            let path = out_dir.join(name);
+            let mut writer = BufWriter::new(File::create(&path)?);
+            sheet.write_with_encoder(JpegEncoder::new_with_quality(&mut writer, 90))?;
+            writer
+                .into_inner()
+                .map_err(io::IntoInnerError::into_error)?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+
+    /// Draw a single sheet with the title and the media.
+    ///
+    /// Fails if the media do not fit into a sheet of the allowed size.
+    pub fn render(
+        &self,
+        title: &str,
+        media: &[(Media, Option<NaiveDateTime>)],
+        cache: &mut ThumbnailCache,
+    ) -> Result<RgbImage, AnyError> {
+        let layout = Layout::new(self.options.cell_size);
+        let Layout {
+            cell,
+            gap,
+            caption_size,
+            title_size,
+            caption_height,
+            header,
+        } = layout;
+        #[allow(clippy::cast_precision_loss)]
+        let cell_width = cell as f32;
+
+        let columns = self.options.columns;
+        let rows = u32::try_from(media.len())
+            .unwrap_or(u32::MAX)
+            .div_ceil(columns)
+            .max(1);
+        let (width, height) = layout
+            .sheet_size(columns, rows)
+            .ok_or_else(|| format!("Too many media ({}) for a single sheet", media.len()))?;
+        let mut sheet = RgbImage::from_pixel(width, height, BACKGROUND);
+        #[allow(clippy::cast_precision_loss)]
+        let max_title = (width - 2 * gap) as f32;
+        self.draw_text(&mut sheet, title, title_size, gap, gap, max_title, TEXT);
+
+        for (i, (media, time)) in media.iter().enumerate() {
+            let i = u32::try_from(i).unwrap_or(u32::MAX);
+            let x = gap + (i % columns) * (cell + gap);
+            let y = header + (i / columns) * (cell + caption_height + gap);
+            match self.thumbnail(media, cache) {
+                Ok(thumbnail) => {
+                    // centered inside the square cell
+                    let dx = (cell - thumbnail.width()) / 2;
+                    let dy = (cell - thumbnail.height()) / 2;
+                    imageops::replace(&mut sheet, &thumbnail, i64::from(x + dx), i64::from(y + dy));
+                }
+                Err(err) => {
+                    warn!("No thumbnail of {}: {}", media, err);
+                    let placeholder = RgbImage::from_pixel(cell, cell, PLACEHOLDER);
+                    imageops::replace(&mut sheet, &placeholder, i64::from(x), i64::from(y));
+                }
+            }
+
+            let name = media
+                .path()
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let caption_y = y + cell + gap / 3;
+            self.draw_text(
+                &mut sheet,
+                &name,
+                caption_size,
+                x,
+                caption_y,
+                cell_width,
+                TEXT,
+            );
+            if let Some(time) = time {
+                let time = time.format("%Y-%m-%d %H:%M:%S").to_string();
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let time_y = caption_y + (caption_size * 1.25) as u32;
+                self.draw_text(
+                    &mut sheet,
+                    &time,
+                    caption_size,
+                    x,
+                    time_y,
+                    cell_width,
+                    CAPTION,
+                );
+            }
+        }
+        debug!("Rendered the sheet {:?} of {} media", title, media.len());
+        Ok(sheet)
+    }
+
+    /// The thumbnail fitting into the cell.
+    fn thumbnail(&self, media: &Media, cache: &mut ThumbnailCache) -> Result<RgbImage, AnyError> {
+        let cell = self.options.cell_size;
+        let thumbnails = cache.get(media, false)?;
+        let path = thumbnails.at_least(cell).ok_or("no thumbnails")?;
+        let image = image::open(path)?;
+        let image = if image.width() > cell || image.height() > cell {
+            image.thumbnail(cell, cell)
+        } else {
+            image
+        };
+        Ok(image.to_rgb8())
+    }
+
+    /// Draw the single line of text with the top left corner at (`x`, `y`)
+    /// truncating it with an ellipsis to fit into the `max_width`.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_text(
+        &self,
+        image: &mut RgbImage,
+        text: &str,
+        size: f32,
+        x: u32,
+        y: u32,
+        max_width: f32,
+        color: Rgb<u8>,
+    ) {
+        let font = self.font.as_scaled(PxScale::from(size));
+        let width =
+            |text: &str| -> f32 { text.chars().map(|c| font.h_advance(font.glyph_id(c))).sum() };
+        let mut text = text.to_owned();
+        if width(&text) > max_width {
+            while !text.is_empty() && width(&text) + width("…") > max_width {
+                text.pop();
+            }
+            text.push('…');
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let (mut caret, baseline) = (x as f32, y as f32 + font.ascent());
+        let mut previous = None;
+        for c in text.chars() {
+            let id = font.glyph_id(c);
+            if let Some(previous) = previous {
+                caret += font.kern(previous, id);
+            }
+            previous = Some(id);
+            let glyph = id.with_scale_and_position(size, point(caret, baseline));
+            caret += font.h_advance(id);
+            let Some(outlined) = self.font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                #[allow(clippy::cast_possible_truncation)]
+                let (px, py) = (
+                    bounds.min.x as i64 + i64::from(gx),
+                    bounds.min.y as i64 + i64::from(gy),
+                );
+                let (Ok(px), Ok(py)) = (u32::try_from(px), u32::try_from(py)) else {
+                    return;
+                };
+                if px >= image.width() || py >= image.height() {
+                    return;
+                }
+                let pixel = image.get_pixel_mut(px, py);
+                for (channel, target) in pixel.0.iter_mut().zip(color.0) {
+                    let blended =
+                        f32::from(*channel) * (1.0 - coverage) + f32::from(target) * coverage;
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                    {
+                        *channel = blended.round().clamp(0.0, 255.0) as u8;
+                    }
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        event::{space, time, EventSources},
+        file_types::MediaType,
+        tags::TagValue,
+    };
+
+    fn shot(path: &str, time: &str) -> (Media, Event) {
+        let media = Media {
+            type_: MediaType::Photo,
+            path: PathBuf::from(path),
+        };
+        let mut tags = HashMap::new();
+        if !time.is_empty() {
+            let time = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap();
+            tags.insert("exif:DateTimeOriginal".to_owned(), TagValue::DateTime(time));
+        }
+        let sources = EventSources {
+            time: vec![time::InfoSource::Tag {
+                name: "exif:DateTimeOriginal".to_owned(),
+                format: "%Y".into(),
+            }],
+            location: vec![space::InfoSource::Embedded],
+        };
+        let event = media.get_event_with_tags(&sources, Some(&tags));
+        (media, event)
+    }
+
+    fn stems(groups: &[SheetGroup]) -> Vec<&str> {
+        groups
+            .iter()
+            .map(|group| group.file_stem.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn folder_stems_do_not_clash() {
+        let media = [
+            shot("/photos/a_b/c/1.jpg", "2023-01-01 10:00"),
+            shot("/photos/a/b_c/2.jpg", ""),
+            shot("/photos/X/3.jpg", ""),
+            shot("/photos/x/4.jpg", ""),
+            shot("/photos/a/b_c/5.jpg", ""),
+        ];
+        let groups = group_for_sheets(media, SheetGrouping::Folder, &ClusterOptions::default());
+        assert_eq!(
+            stems(&groups),
+            ["photos_X", "photos_a_b_c", "photos_a_b_c_2", "photos_x_2"]
+        );
+        assert_eq!(groups[1].title, "/photos/a/b_c");
+        assert_eq!(groups[1].media.len(), 2);
+        // the media without time are not separated from their folder
+        assert!(groups.iter().all(|group| group.title != "Undated"));
+    }
+
+    #[test]
+    fn day_groups() {
+        let media = [
+            shot("/photos/2.jpg", "2023-01-02 09:00"),
+            shot("/photos/undated.jpg", ""),
+            shot("/photos/1b.jpg", "2023-01-01 18:00"),
+            shot("/photos/1a.jpg", "2023-01-01 10:00"),
+        ];
+        let groups = group_for_sheets(media, SheetGrouping::Day, &ClusterOptions::default());
+        assert_eq!(stems(&groups), ["2023-01-01", "2023-01-02", "undated"]);
+        assert_eq!(groups[0].title, "Sunday, 1 January 2023");
+        let names: Vec<_> = groups[0]
+            .media
+            .iter()
+            .map(|(media, _)| media.path().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["/photos/1a.jpg", "/photos/1b.jpg"]);
+        assert_eq!(groups[2].media[0].1, None);
+    }
+
+    #[test]
+    fn event_groups() {
+        let media = [
+            shot("/photos/1.jpg", "2023-01-01 10:00"),
+            shot("/photos/2.jpg", "2023-01-01 11:00"),
+            shot("/photos/3.jpg", "2023-01-01 20:00"),
+        ];
+        let groups = group_for_sheets(media, SheetGrouping::Event, &ClusterOptions::default());
+        assert_eq!(stems(&groups), ["2023-01-01_100000", "2023-01-01_200000"]);
+        assert_eq!(groups[0].title, "2023-01-01 10:00 - 2023-01-01 11:00");
+        assert_eq!(groups[0].media.len(), 2);
+    }
+
+    #[test]
+    fn sanitize_names() {
+        assert_eq!(
+            sanitize("/photos/2023 Paris/day 1"),
+            "photos_2023_Paris_day_1"
+        );
+        assert_eq!(sanitize("Київ.jpg"), "Київ.jpg");
+        assert_eq!(sanitize("/"), "root");
+        assert_eq!(sanitize(""), "root");
+    }
+}
@@ -1,14 +1,229 @@
 //! Operations with filesystem.
-use std::{path::PathBuf, rc::Rc};
+use std::{
+    error::Error as StdError,
+    fmt, io,
+    path::{Path, PathBuf},
+};
 
-use walkdir::WalkDir;
+use ignore::{
+    overrides::{Override, OverrideBuilder},
+    WalkBuilder,
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
 
-use crate::file_types::{Media, MediaType};
+use crate::{
+    file_types::{ExtensionMap, Media, MediaType},
+    AnyError,
+};
+
+/// Name of the files containing gitignore-style patterns
+/// for the entries to skip while scanning a [`Library`].
+///
+/// The patterns apply to the directory the file located in
+/// and all of its subdirectories.
+pub const IGNORE_FILE_NAME: &str = ".fotonignore";
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+/// Rules to skip some entries while scanning a [`Library`].
+pub struct IgnoreRules {
+    #[serde(default = "IgnoreRules::default_hidden")]
+    /// Skip hidden files and directories (the ones starting with a dot).
+    pub hidden: bool,
+
+    #[serde(default)]
+    /// Gitignore-style glob patterns of the entries to skip.
+    ///
+    /// Relative patterns are matched against the paths
+    /// inside every library's root.
+    pub exclude: Vec<String>,
+}
+
+impl IgnoreRules {
+    const fn default_hidden() -> bool {
+        true
+    }
+}
+
+impl Default for IgnoreRules {
+    fn default() -> Self {
+        Self {
+            hidden: Self::default_hidden(),
+            exclude: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// The reason some entry of a [`Library`] could not be read.
+pub enum WalkErrorKind {
+    /// Not enough permissions to read a file or list a directory.
+    PermissionDenied,
+    /// The entry disappeared while scanning or it is a broken symbolic link.
+    NotFound,
+    /// The symbolic link points to one of its own ancestors.
+    Loop,
+    /// Any other I/O error.
+    Io,
+    /// Non-I/O error, e.g. an invalid pattern in an [ignore file][IGNORE_FILE_NAME].
+    Other,
+}
+
+impl fmt::Display for WalkErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let desc = match self {
+            Self::PermissionDenied => "permission denied",
+            Self::NotFound => "not found or broken link",
+            Self::Loop => "filesystem loop",
+            Self::Io => "I/O error",
+            Self::Other => "error",
+        };
+        f.write_str(desc)
+    }
+}
+
+#[derive(Debug)]
+/// Failure to read some entry while scanning a [`Library`].
+pub struct WalkError {
+    path: Option<PathBuf>,
+    kind: WalkErrorKind,
+    inner: ignore::Error,
+}
+
+impl WalkError {
+    /// The path of an entry that failed to be read (if known).
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The reason of the failure.
+    pub fn kind(&self) -> WalkErrorKind {
+        self.kind
+    }
+}
+
+impl From<ignore::Error> for WalkError {
+    fn from(inner: ignore::Error) -> Self {
+        fn find_path(err: &ignore::Error) -> Option<&Path> {
+            match err {
+                ignore::Error::WithPath { path, .. } => Some(path),
+                ignore::Error::Loop { child, .. } => Some(child),
+                ignore::Error::WithDepth { err, .. }
+                | ignore::Error::WithLineNumber { err, .. } => find_path(err),
+                ignore::Error::Partial(errors) => errors.iter().find_map(find_path),
+                _ => None,
+            }
+        }
+
+        fn is_loop(err: &ignore::Error) -> bool {
+            match err {
+                ignore::Error::Loop { .. } => true,
+                ignore::Error::WithPath { err, .. }
+                | ignore::Error::WithDepth { err, .. }
+                | ignore::Error::WithLineNumber { err, .. } => is_loop(err),
+                _ => false,
+            }
+        }
+
+        let kind = if is_loop(&inner) {
+            WalkErrorKind::Loop
+        } else if let Some(io_err) = inner.io_error() {
+            match io_err.kind() {
+                io::ErrorKind::PermissionDenied => WalkErrorKind::PermissionDenied,
+                io::ErrorKind::NotFound => WalkErrorKind::NotFound,
+                _ => WalkErrorKind::Io,
+            }
+        } else {
+            WalkErrorKind::Other
+        };
+
+        Self {
+            path: find_path(&inner).map(Path::to_path_buf),
+            kind,
+            inner,
+        }
+    }
+}
+
+impl fmt::Display for WalkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}: ", path.display())?;
+        }
+        write!(f, "{} ({})", self.kind, self.inner)
+    }
+}
+
+impl StdError for WalkError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.inner)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+/// How to walk a single root directory of a [`Library`].
+pub struct WalkOptions {
+    #[serde(default)]
+    /// Follow symbolic links.
+    ///
+    /// The links pointing to their own ancestors
+    /// are reported as [loops][WalkErrorKind::Loop].
+    pub follow_links: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Do not descend deeper than the given number of directories.
+    pub max_depth: Option<usize>,
+
+    #[serde(default)]
+    /// Do not cross the filesystem boundaries (e.g. mount points).
+    pub same_file_system: bool,
+}
+
+#[derive(Debug)]
+struct Root {
+    path: PathBuf,
+    canonical: Option<PathBuf>,
+    options: WalkOptions,
+    exclude: Override,
+}
+
+impl Root {
+    fn new(path: PathBuf, options: WalkOptions) -> Self {
+        Self {
+            canonical: path.canonicalize().ok(),
+            path,
+            options,
+            exclude: Override::empty(),
+        }
+    }
+
+    /// Whether the other root is located strictly inside the current one.
+    fn contains(&self, other: &Self) -> bool {
+        match (&self.canonical, &other.canonical) {
+            (Some(this), Some(other)) => other != this && other.starts_with(this),
+            _ => false,
+        }
+    }
+
+    fn set_exclude(&mut self, patterns: &[String]) -> Result<(), AnyError> {
+        let mut builder = OverrideBuilder::new(&self.path);
+        for pattern in patterns {
+            // in overrides the plain glob means 'only include',
+            // so it should be negated to exclude the matched entries
+            builder.add(&format!("!{pattern}"))?;
+        }
+        self.exclude = builder.build()?;
+        Ok(())
+    }
+}
 
 #[derive(Debug)]
 /// Filesystem entry point(s) for your photo collection.
 pub struct Library {
-    paths: Vec<PathBuf>,
+    roots: Vec<Root>,
+    extensions: ExtensionMap,
+    skip_hidden: bool,
+    excluded_dirs: Vec<PathBuf>,
 }
 
 impl Library {
@@ -19,52 +234,174 @@ impl Library {
 
     /// Create a [`Library`] given multiple paths.
     pub fn with_paths(paths: Vec<PathBuf>) -> Self {
-        Self { paths }
+        Self::with_roots(paths.into_iter().map(|path| (path, WalkOptions::default())))
+    }
+
+    /// Create a [`Library`] given multiple paths
+    /// with their own [walking options][WalkOptions].
+    ///
+    /// The same directory listed multiple times gets walked only once.
+    /// If some root is nested inside another one, its entries
+    /// are walked with the options of the nested (more specific) root.
+    pub fn with_roots(roots: impl IntoIterator<Item = (PathBuf, WalkOptions)>) -> Self {
+        let mut unique_roots: Vec<Root> = vec![];
+        for (path, options) in roots {
+            let root = Root::new(path, options);
+            let is_duplicate = unique_roots.iter().any(|seen| {
+                (root.canonical.is_some() && seen.canonical == root.canonical)
+                    || seen.path == root.path
+            });
+            if is_duplicate {
+                info!("Skipping duplicate library root {}", root.path.display());
+            } else {
+                unique_roots.push(root);
+            }
+        }
+
+        Self {
+            roots: unique_roots,
+            extensions: ExtensionMap::default(),
+            skip_hidden: IgnoreRules::default().hidden,
+            excluded_dirs: vec![],
+        }
     }
 
-    /// Iter all files with given extensions in a [`Library`].
+    /// Use the custom mapping of file extensions to [`MediaType`]s
+    /// instead of the [default one][MediaType::supported_extensions].
+    pub fn with_extensions(self, extensions: ExtensionMap) -> Self {
+        Self { extensions, ..self }
+    }
+
+    /// Skip the entries matching the [rules][IgnoreRules]
+    /// in addition to the ones listed in the [ignore files][IGNORE_FILE_NAME].
+    pub fn with_ignore_rules(mut self, rules: &IgnoreRules) -> Result<Self, AnyError> {
+        for root in &mut self.roots {
+            root.set_exclude(&rules.exclude)?;
+        }
+        self.skip_hidden = rules.hidden;
+        Ok(self)
+    }
+
+    /// Never descend into the given directory, e.g. when it is
+    /// the output directory nested inside the [`Library`].
+    pub fn exclude_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.excluded_dirs.push(dir.into());
+        self
+    }
+
+    fn walker(&self, root: &Root) -> WalkBuilder {
+        let nested_roots = self
+            .roots
+            .iter()
+            .filter(|other| root.contains(other))
+            .filter_map(|other| other.canonical.clone());
+        let excluded_dirs: Vec<_> = self
+            .excluded_dirs
+            .iter()
+            .filter_map(|dir| dir.canonicalize().ok())
+            .chain(nested_roots)
+            .collect();
+        // the excluded directories as they are seen while walking the root,
+        // so the entries themselves are never resolved
+        let excluded_paths: Vec<_> = root
+            .canonical
+            .as_ref()
+            .map(|canonical| {
+                excluded_dirs
+                    .iter()
+                    .filter_map(|dir| Some(root.path.join(dir.strip_prefix(canonical).ok()?)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let all_roots: Vec<_> = self
+            .roots
+            .iter()
+            .filter_map(|other| other.canonical.clone())
+            .collect();
+        let this_root = root.canonical.clone();
+
+        let mut builder = WalkBuilder::new(&root.path);
+        builder
+            .standard_filters(false)
+            .follow_links(root.options.follow_links)
+            .max_depth(root.options.max_depth)
+            .same_file_system(root.options.same_file_system)
+            .hidden(self.skip_hidden)
+            .add_custom_ignore_filename(IGNORE_FILE_NAME)
+            .overrides(root.exclude.clone())
+            .filter_entry(move |entry| {
+                let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+                if is_dir && excluded_paths.iter().any(|ex| ex == entry.path()) {
+                    return false;
+                }
+                if !entry.path_is_symlink() {
+                    return true;
+                }
+                // only the followed links can lead outside of the root
+                let Ok(target) = entry.path().canonicalize() else {
+                    return true;
+                };
+                let owner = all_roots
+                    .iter()
+                    .filter(|root| target.starts_with(root))
+                    .max_by_key(|root| root.components().count());
+                // the entries of another root are walked there
+                let elsewhere = owner.is_some_and(|owner| Some(owner) != this_root.as_ref());
+                !elsewhere && !excluded_dirs.iter().any(|ex| target.starts_with(ex))
+            });
+        builder
+    }
+
+    /// Iter all files with known extensions in a [`Library`],
+    /// optionally restricting them to a single [`MediaType`].
     fn iter_extensions(
         &self,
-        extensions: Vec<(MediaType, &'static str)>,
-    ) -> impl Iterator<Item = Media> + '_ {
-        let extensions_shared = Rc::new(extensions);
-        self.paths.iter().flat_map(move |root| {
-            let extensions = Rc::clone(&extensions_shared);
-            WalkDir::new(root).into_iter().filter_map(move |entry| {
-                let entry = entry.ok()?.into_path();
-                if entry.is_dir() {
+        only_type: Option<MediaType>,
+    ) -> impl Iterator<Item = Result<Media, WalkError>> + '_ {
+        self.roots.iter().flat_map(move |root| {
+            self.walker(root).build().filter_map(move |entry| {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(err) => return Some(Err(WalkError::from(err))),
+                };
+                if entry.file_type().is_some_and(|ft| ft.is_dir()) {
                     return None;
                 }
-                let ext = entry.extension()?.to_str()?.to_ascii_lowercase();
-                let type_ = extensions
-                    .iter()
-                    .find_map(|(type_, extension)| (extension == &ext).then_some(*type_));
-                type_.map(|type_| Media { type_, path: entry })
+                let entry = entry.into_path();
+                let ext = entry.extension()?.to_str()?;
+                let type_ = self.extensions.media_type(ext)?;
+                if only_type.is_none_or(|only| only == type_) {
+                    Some(Ok(Media { type_, path: entry }))
+                } else {
+                    None
+                }
             })
         })
     }
 
+    /// Iter files of a particular [`MediaType`]s or all supported [`MediaType`]s in a [`Library`]
+    /// reporting the entries that could not be read.
+    pub fn try_iter(
+        &self,
+        resource_type: impl Into<Option<MediaType>>,
+    ) -> impl Iterator<Item = Result<Media, WalkError>> + '_ {
+        self.iter_extensions(resource_type.into())
+    }
+
     /// Iter all files of a given [`MediaType`] in a [`Library`].
+    ///
+    /// The entries that could not be read are only logged.
+    /// Use the [`Self::try_iter`] to handle them.
     pub fn iter_type(&self, resource_type: MediaType) -> impl Iterator<Item = Media> + '_ {
-        let extensions = resource_type
-            .supported_extensions()
-            .into_iter()
-            .map(|ext| (resource_type, ext))
-            .collect();
-        self.iter_extensions(extensions)
+        self.try_iter(resource_type).filter_map(log_walk_error)
     }
 
     /// Iter files of all supported [`MediaType`]s in a [`Library`].
+    ///
+    /// The entries that could not be read are only logged.
+    /// Use the [`Self::try_iter`] to handle them.
     pub fn iter_all(&self) -> impl Iterator<Item = Media> + '_ {
-        let extensions: Vec<_> = enum_iterator::all::<MediaType>()
-            .flat_map(|resource_type| {
-                resource_type
-                    .supported_extensions()
-                    .into_iter()
-                    .map(move |ext| (resource_type, ext))
-            })
-            .collect();
-        self.iter_extensions(extensions)
+        self.try_iter(None).filter_map(log_walk_error)
     }
 
     /// Iter files of a particular [`MediaType`]s or all supported [`MediaType`]s in a [`Library`].
@@ -79,3 +416,191 @@ impl Library {
         }
     }
 }
+
+fn log_walk_error(entry: Result<Media, WalkError>) -> Option<Media> {
+    entry
+        .map_err(|err| {
+            warn!("Skipping unreadable entry: {}", err);
+        })
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Create the files (and their directories) inside an empty temporary directory.
+    fn temp_tree(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("foton-dir-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        dir
+    }
+
+    /// The sorted paths of the media relative to the directory.
+    fn found(lib: &Library, dir: &Path) -> Vec<String> {
+        let mut paths: Vec<_> = lib
+            .iter_all()
+            .map(|media| {
+                let path = media.path().strip_prefix(dir).unwrap();
+                path.to_string_lossy().replace('\\', "/")
+            })
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn ignore_file_and_exclude_patterns() {
+        let dir = temp_tree(
+            "ignore",
+            &[
+                "a.jpg",
+                "c.png",
+                "d.mov",
+                "skip/b.jpg",
+                "sub/e.jpg",
+                "sub/f.jpg",
+            ],
+        );
+        fs::write(dir.join(IGNORE_FILE_NAME), "skip/\n*.png\n").unwrap();
+        fs::write(dir.join("sub").join(IGNORE_FILE_NAME), "f.jpg\n").unwrap();
+        let rules = IgnoreRules {
+            exclude: vec!["*.mov".into()],
+            ..IgnoreRules::default()
+        };
+
+        let lib = Library::new(&dir).with_ignore_rules(&rules).unwrap();
+        assert_eq!(found(&lib, &dir), ["a.jpg", "sub/e.jpg"]);
+
+        let rules = IgnoreRules {
+            exclude: vec!["[".into()],
+            ..IgnoreRules::default()
+        };
+        assert!(Library::new(&dir).with_ignore_rules(&rules).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn hidden_entries() {
+        let dir = temp_tree("hidden", &["a.jpg", ".b.jpg", ".thumbs/c.jpg"]);
+        assert_eq!(found(&Library::new(&dir), &dir), ["a.jpg"]);
+
+        let rules = IgnoreRules {
+            hidden: false,
+            ..IgnoreRules::default()
+        };
+        let lib = Library::new(&dir).with_ignore_rules(&rules).unwrap();
+        assert_eq!(found(&lib, &dir), [".b.jpg", ".thumbs/c.jpg", "a.jpg"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn nested_and_duplicate_roots() {
+        let dir = temp_tree("nested", &["a.jpg", "sub/b.jpg", "sub/deep/c.jpg"]);
+        let shallow = WalkOptions {
+            max_depth: Some(1),
+            ..WalkOptions::default()
+        };
+        let lib = Library::with_roots([
+            (dir.clone(), shallow),
+            (dir.join("sub"), WalkOptions::default()),
+            (dir.join("sub/../sub"), shallow),
+            (dir.clone(), WalkOptions::default()),
+        ]);
+        assert_eq!(lib.roots.len(), 2);
+        // the nested root is walked once with its own options
+        assert_eq!(found(&lib, &dir), ["a.jpg", "sub/b.jpg", "sub/deep/c.jpg"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn excluded_dir() {
+        let dir = temp_tree("excluded", &["a.jpg", "out/b.jpg", "other/out/c.jpg"]);
+        let lib = Library::new(&dir).exclude_dir(dir.join("out"));
+        assert_eq!(found(&lib, &dir), ["a.jpg", "other/out/c.jpg"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_into_another_root() {
+        let dir = temp_tree("symlink", &["one/a.jpg", "two/b.jpg", "out/c.jpg"]);
+        std::os::unix::fs::symlink(dir.join("two"), dir.join("one/two")).unwrap();
+        std::os::unix::fs::symlink(dir.join("two/b.jpg"), dir.join("one/b.jpg")).unwrap();
+        std::os::unix::fs::symlink(dir.join("out"), dir.join("one/out")).unwrap();
+        let follow = WalkOptions {
+            follow_links: true,
+            ..WalkOptions::default()
+        };
+
+        let lib = Library::with_roots([(dir.join("one"), follow), (dir.join("two"), follow)])
+            .exclude_dir(dir.join("out"));
+        assert_eq!(found(&lib, &dir), ["one/a.jpg", "two/b.jpg"]);
+
+        // without the second root the link is the only way to the media
+        let lib = Library::with_roots([(dir.join("one"), follow)]);
+        assert_eq!(
+            found(&lib, &dir),
+            ["one/a.jpg", "one/b.jpg", "one/out/c.jpg", "one/two/b.jpg"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loop() {
+        let dir = temp_tree("loop", &["sub/a.jpg"]);
+        std::os::unix::fs::symlink(&dir, dir.join("sub/up")).unwrap();
+        let lib = Library::with_roots([(
+            dir.clone(),
+            WalkOptions {
+                follow_links: true,
+                ..WalkOptions::default()
+            },
+        )]);
+
+        let (media, errors): (Vec<_>, Vec<_>) = lib.try_iter(None).partition(Result::is_ok);
+        assert_eq!(media.len(), 1);
+        let errors: Vec<_> = errors.into_iter().filter_map(Result::err).collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind(), WalkErrorKind::Loop);
+        assert_eq!(errors[0].path(), Some(dir.join("sub/up").as_path()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn error_kinds() {
+        let io_error = |kind| ignore::Error::WithPath {
+            path: "/photos/a.jpg".into(),
+            err: Box::new(ignore::Error::Io(io::Error::from(kind))),
+        };
+
+        let err = WalkError::from(io_error(io::ErrorKind::PermissionDenied));
+        assert_eq!(err.kind(), WalkErrorKind::PermissionDenied);
+        assert_eq!(err.path(), Some(Path::new("/photos/a.jpg")));
+
+        let err = WalkError::from(ignore::Error::WithDepth {
+            depth: 2,
+            err: Box::new(io_error(io::ErrorKind::NotFound)),
+        });
+        assert_eq!(err.kind(), WalkErrorKind::NotFound);
+        assert_eq!(err.path(), Some(Path::new("/photos/a.jpg")));
+
+        let err = WalkError::from(io_error(io::ErrorKind::InvalidData));
+        assert_eq!(err.kind(), WalkErrorKind::Io);
+
+        let err = WalkError::from(ignore::Error::Glob {
+            glob: Some("[".into()),
+            err: "unclosed character class".into(),
+        });
+        assert_eq!(err.kind(), WalkErrorKind::Other);
+        assert_eq!(err.path(), None);
+    }
+}
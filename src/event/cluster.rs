@@ -0,0 +1,520 @@
+//! Grouping the media into events (the media shot close in time and space)
+//! and the events into trips away from home.
+use std::{fmt, str::FromStr};
+
+use chrono::{NaiveDateTime, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    space::{centroid, distance},
+    Event,
+};
+use crate::file_types::Media;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+/// How to group the media into events and trips.
+pub struct ClusterOptions {
+    /// The longest pause (in minutes) between the consecutive media of a single event.
+    pub max_gap_minutes: u32,
+
+    /// The longest distance (in kilometers) between the consecutive media of a single event.
+    pub max_distance_km: f64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The place to detect the trips away from.
+    ///
+    /// If not specified, the events are not grouped into trips.
+    pub home: Option<Home>,
+
+    /// The longest pause (in days) between the events of a single trip.
+    pub trip_max_gap_days: u32,
+}
+
+impl Default for ClusterOptions {
+    fn default() -> Self {
+        Self {
+            max_gap_minutes: 180,
+            max_distance_km: 25.0,
+            home: None,
+            trip_max_gap_days: 2,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+/// The area around the home location.
+pub struct Home {
+    /// Latitude in degrees.
+    pub latitude: f64,
+    /// Longitude in degrees.
+    pub longitude: f64,
+    #[serde(default = "Home::default_radius")]
+    /// Everything closer than the radius (in kilometers) is considered home.
+    pub radius_km: f64,
+}
+
+impl Home {
+    const fn default_radius() -> f64 {
+        50.0
+    }
+
+    fn contains(&self, point: (f64, f64)) -> bool {
+        distance((self.latitude, self.longitude), point) <= self.radius_km * 1000.0
+    }
+}
+
+impl FromStr for Home {
+    type Err = String;
+
+    /// Parse the `LATITUDE,LONGITUDE[,RADIUS_KM]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let numbers = s
+            .split(',')
+            .map(|n| n.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("invalid number in {:?}: {}", s, err))?;
+        let (latitude, longitude, radius_km) = match numbers.as_slice() {
+            [latitude, longitude] => (*latitude, *longitude, Self::default_radius()),
+            [latitude, longitude, radius] => (*latitude, *longitude, *radius),
+            _ => return Err(format!("expected LATITUDE,LONGITUDE[,RADIUS_KM]: {:?}", s)),
+        };
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return Err(format!("invalid coordinates: {:?}", s));
+        }
+        Ok(Self {
+            latitude,
+            longitude,
+            radius_km,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The media shot close to each other in time and space.
+pub struct EventCluster {
+    media: Vec<Media>,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    /// Locations of the media having it.
+    points: Vec<(f64, f64)>,
+    centroid: Option<(f64, f64)>,
+}
+
+impl EventCluster {
+    fn new(media: Media, time: NaiveDateTime, point: Option<(f64, f64)>) -> Self {
+        Self {
+            media: vec![media],
+            start: time,
+            end: time,
+            points: point.into_iter().collect(),
+            centroid: None,
+        }
+    }
+
+    fn push(&mut self, media: Media, time: NaiveDateTime, point: Option<(f64, f64)>) {
+        self.media.push(media);
+        self.end = time;
+        self.points.extend(point);
+    }
+
+    /// All the media of the event ordered by their time.
+    pub fn media(&self) -> &[Media] {
+        &self.media
+    }
+
+    /// The time of the first media.
+    pub fn start(&self) -> NaiveDateTime {
+        self.start
+    }
+
+    /// The time of the last media.
+    pub fn end(&self) -> NaiveDateTime {
+        self.end
+    }
+
+    /// The midpoint (latitude, longitude) of all the media having the location.
+    pub fn centroid(&self) -> Option<(f64, f64)> {
+        self.centroid
+    }
+}
+
+impl fmt::Display for EventCluster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} .. {}: {} media",
+            self.start,
+            self.end,
+            self.media.len()
+        )?;
+        if let Some((latitude, longitude)) = self.centroid {
+            write!(f, " at {:.6}, {:.6}", latitude, longitude)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The consecutive events away from home spanning multiple days.
+pub struct Trip {
+    events: Vec<EventCluster>,
+}
+
+impl Trip {
+    /// The events of the trip ordered by their time.
+    pub fn events(&self) -> &[EventCluster] {
+        &self.events
+    }
+
+    /// The start of the first event.
+    pub fn start(&self) -> NaiveDateTime {
+        self.events
+            .first()
+            .map(EventCluster::start)
+            .unwrap_or_default()
+    }
+
+    /// The end of the last event.
+    pub fn end(&self) -> NaiveDateTime {
+        self.events
+            .last()
+            .map(EventCluster::end)
+            .unwrap_or_default()
+    }
+
+    /// The number of media in all the events.
+    pub fn media_count(&self) -> usize {
+        self.events.iter().map(|event| event.media.len()).sum()
+    }
+
+    /// The midpoint (latitude, longitude) of all the media having the location.
+    pub fn centroid(&self) -> Option<(f64, f64)> {
+        centroid(
+            self.events
+                .iter()
+                .flat_map(|event| event.points.iter().copied()),
+        )
+    }
+}
+
+impl fmt::Display for Trip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Trip {} .. {}: {} events, {} media",
+            self.start().date(),
+            self.end().date(),
+            self.events.len(),
+            self.media_count()
+        )?;
+        if let Some((latitude, longitude)) = self.centroid() {
+            write!(f, " at {:.6}, {:.6}", latitude, longitude)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The top-level item of the events timeline.
+pub enum TimelineEntry {
+    /// A standalone event.
+    Event(EventCluster),
+    /// Multiple events away from home.
+    Trip(Trip),
+}
+
+impl TimelineEntry {
+    /// The start of the entry.
+    pub fn start(&self) -> NaiveDateTime {
+        match self {
+            Self::Event(event) => event.start(),
+            Self::Trip(trip) => trip.start(),
+        }
+    }
+
+    /// The end of the entry.
+    pub fn end(&self) -> NaiveDateTime {
+        match self {
+            Self::Event(event) => event.end(),
+            Self::Trip(trip) => trip.end(),
+        }
+    }
+
+    /// The midpoint (latitude, longitude) of all the media having the location.
+    pub fn centroid(&self) -> Option<(f64, f64)> {
+        match self {
+            Self::Event(event) => event.centroid(),
+            Self::Trip(trip) => trip.centroid(),
+        }
+    }
+}
+
+/// Group the media into events ordered by their time.
+///
+/// The consecutive media (sorted by time) belong to the same event
+/// unless the pause between them is longer than the [gap][ClusterOptions::max_gap_minutes]
+/// or they are [farther][ClusterOptions::max_distance_km] from each other.
+/// The media without the location are only split by the time.
+///
+/// If the [home][ClusterOptions::home] is specified, the consecutive events away from it
+/// spanning multiple days are grouped into [trips][Trip].
+///
+/// The media without the time are skipped.
+pub fn cluster_events(
+    media: impl IntoIterator<Item = (Media, Event)>,
+    options: &ClusterOptions,
+) -> Vec<TimelineEntry> {
+    let mut media: Vec<_> = media
+        .into_iter()
+        .filter_map(|(media, event)| {
+            let time = event.time()?.datetime();
+            let point = event
+                .location()
+                .map(|location| (location.latitude(), location.longitude()));
+            Some((media, time, point))
+        })
+        .collect();
+    media.sort_by_key(|(_, time, _)| *time);
+
+    let max_gap = TimeDelta::minutes(i64::from(options.max_gap_minutes));
+    let max_distance = options.max_distance_km * 1000.0;
+    let mut events: Vec<EventCluster> = vec![];
+    for (media, time, point) in media {
+        if let Some(event) = events.last_mut() {
+            let close_in_time = time - event.end <= max_gap;
+            let close_in_space = match (event.points.last(), point) {
+                (Some(last), Some(point)) => distance(*last, point) <= max_distance,
+                _ => true,
+            };
+            if close_in_time && close_in_space {
+                event.push(media, time, point);
+                continue;
+            }
+        }
+        events.push(EventCluster::new(media, time, point));
+    }
+    for event in &mut events {
+        event.centroid = centroid(event.points.iter().copied());
+    }
+
+    match options.home {
+        Some(home) => group_trips(events, &home, options.trip_max_gap_days),
+        None => events.into_iter().map(TimelineEntry::Event).collect(),
+    }
+}
+
+/// Combine the consecutive events away from home into trips.
+///
+/// The events without the location do not interrupt the trip,
+/// but they are not included at its edges.
+fn group_trips(events: Vec<EventCluster>, home: &Home, max_gap_days: u32) -> Vec<TimelineEntry> {
+    fn finish(
+        entries: &mut Vec<TimelineEntry>,
+        trip: &mut Vec<EventCluster>,
+        unknown: &mut Vec<EventCluster>,
+    ) {
+        let spans_days = match (trip.first(), trip.last()) {
+            (Some(first), Some(last)) => last.end.date() > first.start.date(),
+            _ => false,
+        };
+        if spans_days {
+            entries.push(TimelineEntry::Trip(Trip {
+                events: std::mem::take(trip),
+            }));
+        } else {
+            entries.extend(trip.drain(..).map(TimelineEntry::Event));
+        }
+        entries.extend(unknown.drain(..).map(TimelineEntry::Event));
+    }
+
+    let max_gap = TimeDelta::days(i64::from(max_gap_days));
+    let mut entries = vec![];
+    let mut trip = vec![];
+    // the events without location after the last one away from home
+    let mut unknown = vec![];
+    for event in events {
+        let previous_end = unknown.last().or(trip.last()).map(EventCluster::end);
+        if previous_end.is_some_and(|end| event.start - end > max_gap) {
+            finish(&mut entries, &mut trip, &mut unknown);
+        }
+
+        match event.centroid.map(|point| home.contains(point)) {
+            Some(false) => {
+                trip.append(&mut unknown);
+                trip.push(event);
+            }
+            Some(true) => {
+                finish(&mut entries, &mut trip, &mut unknown);
+                entries.push(TimelineEntry::Event(event));
+            }
+            None if trip.is_empty() => entries.push(TimelineEntry::Event(event)),
+            None => unknown.push(event),
+        }
+    }
+    finish(&mut entries, &mut trip, &mut unknown);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::PathBuf};
+
+    use super::{
+        super::{space, time, EventSources},
+        *,
+    };
+    use crate::{file_types::MediaType, tags::TagValue};
+
+    const HOME: (f64, f64) = (50.45, 30.52);
+    const AWAY: (f64, f64) = (48.85, 2.35);
+
+    fn shot(name: &str, time: &str, point: Option<(f64, f64)>) -> (Media, Event) {
+        let media = Media {
+            type_: MediaType::Photo,
+            path: PathBuf::from(name),
+        };
+        let mut tags = HashMap::new();
+        if !time.is_empty() {
+            let time = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap();
+            tags.insert("exif:DateTimeOriginal".to_owned(), TagValue::DateTime(time));
+        }
+        if let Some((latitude, longitude)) = point {
+            tags.insert("exif:GPSLatitude".to_owned(), TagValue::Gps(latitude));
+            tags.insert("exif:GPSLongitude".to_owned(), TagValue::Gps(longitude));
+        }
+        let sources = EventSources {
+            time: vec![time::InfoSource::Tag {
+                name: "exif:DateTimeOriginal".to_owned(),
+                format: "%Y".into(),
+            }],
+            location: vec![space::InfoSource::Embedded],
+        };
+        let event = media.get_event_with_tags(&sources, Some(&tags));
+        (media, event)
+    }
+
+    /// The names of the media of every entry (the trips are prefixed with `trip:`).
+    fn names(entries: &[TimelineEntry]) -> Vec<String> {
+        let event_names = |event: &EventCluster| {
+            event
+                .media()
+                .iter()
+                .map(|m| m.path().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        entries
+            .iter()
+            .map(|entry| match entry {
+                TimelineEntry::Event(event) => event_names(event),
+                TimelineEntry::Trip(trip) => {
+                    let events: Vec<_> = trip.events().iter().map(event_names).collect();
+                    format!("trip:{}", events.join("|"))
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn time_gap_boundary() {
+        let media = [
+            shot("c", "2022-07-08 16:01", None),
+            shot("a", "2022-07-08 10:00", None),
+            // exactly the max gap
+            shot("b", "2022-07-08 13:00", None),
+            shot("no-time", "", None),
+        ];
+        let entries = cluster_events(media, &ClusterOptions::default());
+        assert_eq!(names(&entries), ["a,b", "c"]);
+
+        let TimelineEntry::Event(event) = &entries[0] else {
+            panic!("expected an event");
+        };
+        assert_eq!(event.start().to_string(), "2022-07-08 10:00:00");
+        assert_eq!(event.end().to_string(), "2022-07-08 13:00:00");
+        assert_eq!(event.centroid(), None);
+    }
+
+    #[test]
+    fn distance_boundary() {
+        // 0.2 degrees of latitude are about 22 km, 0.3 degrees are about 33 km
+        let media = [
+            shot("a", "2022-07-08 10:00", Some((50.0, 30.0))),
+            shot("b", "2022-07-08 10:10", Some((50.2, 30.0))),
+            shot("no-location", "2022-07-08 10:15", None),
+            shot("c", "2022-07-08 10:20", Some((50.5, 30.0))),
+        ];
+        let entries = cluster_events(media, &ClusterOptions::default());
+        assert_eq!(names(&entries), ["a,b,no-location", "c"]);
+
+        let (latitude, longitude) = entries[0].centroid().unwrap();
+        assert!((latitude - 50.1).abs() < 1e-3 && (longitude - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn trips() {
+        let options = ClusterOptions {
+            home: Some(Home {
+                latitude: HOME.0,
+                longitude: HOME.1,
+                radius_km: 50.0,
+            }),
+            ..ClusterOptions::default()
+        };
+        let media = [
+            shot("home1", "2022-07-01 10:00", Some(HOME)),
+            // a single day away is not a trip
+            shot("day-trip", "2022-07-02 10:00", Some(AWAY)),
+            shot("home2", "2022-07-02 20:00", Some(HOME)),
+            shot("away1", "2022-07-05 10:00", Some(AWAY)),
+            shot("unknown1", "2022-07-06 10:00", None),
+            shot("away2", "2022-07-07 10:00", Some(AWAY)),
+            // the events without location are not included at the edges
+            shot("unknown2", "2022-07-07 20:00", None),
+            // longer than the trip gap
+            shot("away3", "2022-07-10 10:00", Some(AWAY)),
+            shot("away4", "2022-07-11 10:00", Some(AWAY)),
+            shot("home3", "2022-07-12 10:00", Some(HOME)),
+        ];
+        let entries = cluster_events(media, &options);
+        assert_eq!(
+            names(&entries),
+            [
+                "home1",
+                "day-trip",
+                "home2",
+                "trip:away1|unknown1|away2",
+                "unknown2",
+                "trip:away3|away4",
+                "home3",
+            ]
+        );
+
+        let TimelineEntry::Trip(trip) = &entries[3] else {
+            panic!("expected a trip");
+        };
+        assert_eq!(trip.media_count(), 3);
+        assert_eq!(trip.start().to_string(), "2022-07-05 10:00:00");
+        assert_eq!(trip.end().to_string(), "2022-07-07 10:00:00");
+    }
+
+    #[test]
+    fn parse_home() {
+        assert_eq!(
+            "50.45, 30.52".parse(),
+            Ok(Home {
+                latitude: 50.45,
+                longitude: 30.52,
+                radius_km: 50.0,
+            })
+        );
+        assert_eq!(
+            "50.45,30.52,10".parse::<Home>().map(|home| home.radius_km),
+            Ok(10.0)
+        );
+        assert!("50.45".parse::<Home>().is_err());
+        assert!("95,30".parse::<Home>().is_err());
+        assert!("a,b".parse::<Home>().is_err());
+    }
+}
@@ -1,13 +1,68 @@
 //! Space and time coordinates (called also _event_)
 //! for media files.
 
-mod space;
+pub(crate) mod cluster;
+pub(crate) mod space;
 pub(crate) mod time;
 
+use std::collections::HashMap;
+
+use log::warn;
+
+use crate::{file_types::Media, tags::TagValue};
+
 /// [`Event`] is a combination
 /// of space (location) and time coordinates.
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Event {
     time: Option<time::Time>,
     location: Option<space::Location>,
 }
+
+impl Event {
+    /// When the media was shot.
+    pub fn time(&self) -> Option<&time::Time> {
+        self.time.as_ref()
+    }
+
+    /// Where the media was shot.
+    pub fn location(&self) -> Option<space::Location> {
+        self.location
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Where to look for the time and location of the media.
+pub struct EventSources {
+    /// Sources of the time in the order of preference.
+    pub time: Vec<time::InfoSource>,
+    /// Sources of the location in the order of preference.
+    pub location: Vec<space::InfoSource>,
+}
+
+impl Media {
+    /// Retrieves both the time and the location of the media
+    /// reading its tags only once.
+    pub fn get_event(&self, sources: &EventSources) -> Event {
+        let all_tags = self
+            .get_tags()
+            .map_err(|err| {
+                warn!("Failed to get event tags for {}: {:?}", self, err);
+            })
+            .ok();
+        self.get_event_with_tags(sources, all_tags.as_ref())
+    }
+
+    /// Retrieves both the time and the location of the media
+    /// from the cached tags collection.
+    pub fn get_event_with_tags(
+        &self,
+        sources: &EventSources,
+        cached_tags: Option<&HashMap<String, TagValue>>,
+    ) -> Event {
+        Event {
+            time: self.get_datetime_with_tags(&sources.time, cached_tags),
+            location: self.get_location_with_tags(&sources.location, cached_tags),
+        }
+    }
+}
@@ -1,4 +1,375 @@
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+use std::{collections::HashMap, fmt};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    file_types::{Media, MediaType},
+    tags::TagValue,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+/// Describes the place a media file was shot.
 pub struct Location {
-    // TODO: longitude, latitude, altitude
+    latitude: f64,
+    longitude: f64,
+    altitude: Option<f64>,
+    source: InfoSource,
+    inferred: bool,
+}
+
+impl Location {
+    pub(crate) fn new(
+        latitude: f64,
+        longitude: f64,
+        altitude: Option<f64>,
+        source: InfoSource,
+    ) -> Self {
+        Self {
+            latitude,
+            longitude,
+            altitude,
+            source,
+            inferred: false,
+        }
+    }
+
+    /// The same location marked as [inferred][Self::is_inferred].
+    pub(crate) fn into_inferred(self) -> Self {
+        Self {
+            inferred: true,
+            ..self
+        }
+    }
+
+    /// Latitude in degrees (positive for the Northern hemisphere).
+    pub fn latitude(&self) -> f64 {
+        self.latitude
+    }
+
+    /// Longitude in degrees (positive for the Eastern hemisphere).
+    pub fn longitude(&self) -> f64 {
+        self.longitude
+    }
+
+    /// Altitude in meters above the sea level.
+    pub fn altitude(&self) -> Option<f64> {
+        self.altitude
+    }
+
+    /// Where the location was found.
+    pub fn source(&self) -> InfoSource {
+        self.source
+    }
+
+    /// Whether the location was not measured for the media itself
+    /// but borrowed from the media shot at about the same time.
+    pub fn is_inferred(&self) -> bool {
+        self.inferred
+    }
+
+    /// The great-circle distance to another location in meters
+    /// (the altitude is not taken into account).
+    pub fn distance(&self, other: &Self) -> f64 {
+        distance(
+            (self.latitude, self.longitude),
+            (other.latitude, other.longitude),
+        )
+    }
+}
+
+/// Mean radius of the Earth in meters.
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// The great-circle distance in meters between two points
+/// given as (latitude, longitude) in degrees (the haversine formula).
+pub(crate) fn distance(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lat2) = (from.0.to_radians(), to.0.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (to.1 - from.1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+}
+
+/// The geographic midpoint of the points given as (latitude, longitude) in degrees.
+///
+/// The points are averaged as the vectors in 3D,
+/// so the result is correct near the poles and the antimeridian.
+pub(crate) fn centroid(points: impl IntoIterator<Item = (f64, f64)>) -> Option<(f64, f64)> {
+    let (mut x, mut y, mut z, mut count) = (0.0, 0.0, 0.0, 0_u32);
+    for (latitude, longitude) in points {
+        let (lat, lon) = (latitude.to_radians(), longitude.to_radians());
+        x += lat.cos() * lon.cos();
+        y += lat.cos() * lon.sin();
+        z += lat.sin();
+        count += 1;
+    }
+    if count == 0 {
+        return None;
+    }
+    let count = f64::from(count);
+    let (x, y, z) = (x / count, y / count, z / count);
+    let latitude = z.atan2(x.hypot(y)).to_degrees();
+    let longitude = y.atan2(x).to_degrees();
+    Some((latitude, longitude))
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.6}, {:.6}", self.latitude, self.longitude)?;
+        if let Some(alt) = self.altitude {
+            write!(f, " ({:.1} m)", alt)?;
+        }
+        if self.inferred {
+            f.write_str(" (inferred)")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+/// Source of Location for a media file.
+pub enum InfoSource {
+    /// GPS data embedded into the media file (or its XMP sidecar):
+    /// EXIF GPS tags for images and ISO 6709 location for videos
+    /// (or the EXIF GPS tags of their sidecars written by the geotagging).
+    Embedded,
+
+    /// Google Photos export the location into the JSON sidecars
+    /// stripping it from the EXIF data.
+    Takeout,
+}
+
+impl InfoSource {
+    /// All the supported sources in the order of preference.
+    pub fn all() -> Vec<Self> {
+        vec![Self::Embedded, Self::Takeout]
+    }
+}
+
+/// The tags containing the ISO 6709 location of a video.
+const VIDEO_LOCATION_TAGS: &[&str] = &[
+    "ffmpeg:com.apple.quicktime.location.ISO6709",
+    "ffmpeg:location",
+];
+
+/// The EXIF tags holding the coordinates of an image
+/// already converted into the decimal degrees.
+const EXIF_LATITUDE_TAG: &str = "exif:GPSLatitude";
+const EXIF_LONGITUDE_TAG: &str = "exif:GPSLongitude";
+const EXIF_ALTITUDE_TAG: &str = "exif:GPSAltitude";
+
+/// The tag describing how the location was found.
+const EXIF_METHOD_TAG: &str = "exif:GPSProcessingMethod";
+
+/// The [processing method][EXIF_METHOD_TAG] of the [inferred][Location::is_inferred] locations.
+pub(crate) const INFERRED_METHOD: &str = "INFERRED";
+
+/// Fields of the Google Takeout JSON sidecar
+/// holding the location (in the order of preference).
+const TAKEOUT_LOCATION_PREFIXES: &[&str] = &["takeout:geoData", "takeout:geoDataExif"];
+
+impl Media {
+    /// Retrieves the [`Location`] from the media metadata.
+    ///
+    /// For better performance, the cached tags collection could be provided.
+    pub fn get_location_from_source(
+        &self,
+        source: &InfoSource,
+        cached_tags: Option<&HashMap<String, TagValue>>,
+    ) -> Option<Location> {
+        let mut inferred = false;
+        let (latitude, longitude, altitude) = match source {
+            InfoSource::Embedded => match self.type_ {
+                MediaType::Photo | MediaType::Animation => {
+                    inferred = self.has_inferred_method(cached_tags);
+                    self.exif_coordinates(cached_tags)?
+                }
+                MediaType::Video => {
+                    match self.get_tag_value(VIDEO_LOCATION_TAGS, cached_tags) {
+                        Some(value) => parse_iso6709(&value.to_string())?,
+                        // the location written by the geotagging into the XMP sidecar
+                        None => {
+                            inferred = self.has_inferred_method(cached_tags);
+                            self.exif_coordinates(cached_tags)?
+                        }
+                    }
+                }
+            },
+            InfoSource::Takeout => TAKEOUT_LOCATION_PREFIXES
+                .iter()
+                .find_map(|prefix| self.takeout_coordinates(prefix, cached_tags))?,
+        };
+
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            warn!(
+                "Invalid coordinates for {}: {}, {}",
+                self, latitude, longitude
+            );
+            return None;
+        }
+
+        Some(Location {
+            latitude,
+            longitude,
+            altitude,
+            source: *source,
+            inferred,
+        })
+    }
+
+    /// Retrieves the [`Location`] from the media metadata
+    /// using multiple sources till success.
+    pub fn get_location(&self, sources: &[InfoSource]) -> Option<Location> {
+        let all_tags = self
+            .get_tags()
+            .map_err(|err| {
+                warn!("Failed to get location tags for {}: {:?}", self, err);
+            })
+            .ok();
+        self.get_location_with_tags(sources, all_tags.as_ref())
+    }
+
+    /// Retrieves the [`Location`] from the media metadata
+    /// using multiple sources till success.
+    ///
+    /// For better performance, the cached tags collection could be provided.
+    pub fn get_location_with_tags(
+        &self,
+        sources: &[InfoSource],
+        cached_tags: Option<&HashMap<String, TagValue>>,
+    ) -> Option<Location> {
+        sources
+            .iter()
+            .find_map(|source| self.get_location_from_source(source, cached_tags))
+    }
+
+    pub(crate) fn exif_coordinates(
+        &self,
+        cached_tags: Option<&HashMap<String, TagValue>>,
+    ) -> Option<(f64, f64, Option<f64>)> {
+        let get = |name: &str| self.get_tag_value(&[name], cached_tags);
+
+        let latitude = get(EXIF_LATITUDE_TAG)?;
+        let longitude = get(EXIF_LONGITUDE_TAG)?;
+        let (TagValue::Gps(latitude), TagValue::Gps(longitude)) = (latitude, longitude) else {
+            debug!("Non-decimal GPS coordinates for {}", self);
+            return None;
+        };
+        let altitude = get(EXIF_ALTITUDE_TAG).and_then(|alt| alt.as_f64());
+        Some((latitude, longitude, altitude))
+    }
+
+    /// Whether the GPS tags were written for the [inferred][Location::is_inferred] location.
+    fn has_inferred_method(&self, cached_tags: Option<&HashMap<String, TagValue>>) -> bool {
+        // the EXIF value is prefixed with the character code
+        match self.get_tag_value(&[EXIF_METHOD_TAG], cached_tags) {
+            Some(TagValue::Binary(bytes)) => bytes.ends_with(INFERRED_METHOD.as_bytes()),
+            Some(TagValue::Text(text)) => text.ends_with(INFERRED_METHOD),
+            _ => false,
+        }
+    }
+
+    fn takeout_coordinates(
+        &self,
+        prefix: &str,
+        cached_tags: Option<&HashMap<String, TagValue>>,
+    ) -> Option<(f64, f64, Option<f64>)> {
+        let get = |field: &str| -> Option<f64> {
+            self.get_tag_value(&[format!("{prefix}.{field}")], cached_tags)?
+                .as_f64()
+        };
+
+        let latitude = get("latitude")?;
+        let longitude = get("longitude")?;
+        // the missing location is exported as zeros
+        if latitude == 0.0 && longitude == 0.0 {
+            return None;
+        }
+        let altitude = get("altitude").filter(|alt| *alt != 0.0);
+        Some((latitude, longitude, altitude))
+    }
+}
+
+/// Parse the ISO 6709 location string, e.g. `+50.4501+030.5234+150.000/`.
+fn parse_iso6709(value: &str) -> Option<(f64, f64, Option<f64>)> {
+    let value = value.trim().trim_end_matches('/');
+    let mut numbers = vec![];
+    let mut start = None;
+    for (i, c) in value.char_indices() {
+        if c == '+' || c == '-' {
+            if let Some(s) = start {
+                numbers.push(value[s..i].parse::<f64>().ok()?);
+            }
+            start = Some(i);
+        }
+    }
+    numbers.push(value[start?..].parse::<f64>().ok()?);
+
+    match numbers[..] {
+        [lat, lon] => Some((lat, lon, None)),
+        [lat, lon, alt, ..] => Some((lat, lon, Some(alt))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn iso6709() {
+        assert_eq!(
+            parse_iso6709("+12.34-056.78+010/"),
+            Some((12.34, -56.78, Some(10.0)))
+        );
+        assert_eq!(
+            parse_iso6709(" -33.8568+151.2153/ "),
+            Some((-33.8568, 151.2153, None))
+        );
+        assert_eq!(
+            parse_iso6709("+50.4501+030.5234+150.000/"),
+            Some((50.4501, 30.5234, Some(150.0)))
+        );
+        for invalid in ["", "/", "+12.34/", "12.34 56.78", "+12.x4-056.78/"] {
+            assert_eq!(parse_iso6709(invalid), None, "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn video_location() {
+        let video = Media {
+            type_: MediaType::Video,
+            path: PathBuf::from("VID_0001.mp4"),
+        };
+        let location = |value: &str| {
+            let tags = HashMap::from([("ffmpeg:location".to_owned(), TagValue::from_text(value))]);
+            video.get_location_from_source(&InfoSource::Embedded, Some(&tags))
+        };
+
+        let found = location("+12.34-056.78+010/").unwrap();
+        assert_eq!(
+            (found.latitude(), found.longitude(), found.altitude()),
+            (12.34, -56.78, Some(10.0))
+        );
+        assert!(!found.is_inferred());
+        assert_eq!(location("+95.0000+030.0000/"), None);
+    }
+
+    #[test]
+    fn distance_and_centroid() {
+        let kyiv = (50.4501, 30.5234);
+        let lviv = (49.8397, 24.0297);
+        let d = distance(kyiv, lviv);
+        assert!((d - 469_000.0).abs() < 2_000.0, "{}", d);
+        assert!(distance(kyiv, kyiv).abs() < 1e-6);
+
+        // the midpoint across the antimeridian
+        let (lat, lon) = centroid([(0.0, 179.0), (0.0, -179.0)]).unwrap();
+        assert!(lat.abs() < 1e-9 && (lon.abs() - 180.0).abs() < 1e-9);
+        assert_eq!(centroid([]), None);
+    }
 }
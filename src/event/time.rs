@@ -1,10 +1,13 @@
 use std::{borrow::Cow, collections::HashMap};
 
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime};
 use log::warn;
 use serde::{Deserialize, Serialize};
 
-use crate::file_types::Media;
+use crate::{
+    file_types::Media,
+    tags::{find_tag, TagValue},
+};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 /// Describes the time a media file was shot.
@@ -13,6 +16,18 @@ pub struct Time {
     source: InfoSource,
 }
 
+impl Time {
+    /// The local date and time.
+    pub fn datetime(&self) -> NaiveDateTime {
+        self.inner
+    }
+
+    /// Where the time was found.
+    pub fn source(&self) -> &InfoSource {
+        &self.source
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type")]
 /// Source of Time for a media file.
@@ -33,6 +48,18 @@ pub enum InfoSource {
         /// How to parse the datetime.
         format: Format,
     },
+
+    /// Google Photos export the time into the JSON sidecars
+    /// stripping it from the EXIF data.
+    ///
+    /// The time is stored as a UTC timestamp.
+    Takeout {
+        #[serde(default)]
+        /// The offset (e.g. `+03:00`) to convert the time into the local one.
+        ///
+        /// If not specified, the UTC time is used.
+        utc_offset: Option<String>,
+    },
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -69,48 +96,85 @@ impl From<&str> for Format {
     }
 }
 
+/// The tags holding the offset of the local time from UTC (in the order of preference).
+const UTC_OFFSET_TAGS: &[&str] = &[
+    "exif:OffsetTimeOriginal",
+    "exif:OffsetTime",
+    "canon:UtcOffset",
+    "nikon:UtcOffset",
+];
+
+/// The field of the Google Takeout JSON sidecar
+/// holding the capture time as a UTC timestamp.
+///
+/// The `creationTime` is the time of the upload, so it is never used.
+const TAKEOUT_TIME_TAG: &str = "takeout:photoTakenTime.timestamp";
+
 impl Media {
-    fn get_value<'s, 't>(
-        &'s self,
+    fn get_value(
+        &self,
         source: &InfoSource,
-        cached_tags: Option<&'t HashMap<String, String>>,
-    ) -> Option<Cow<str>>
-    where
-        't: 's,
-    {
+        cached_tags: Option<&HashMap<String, TagValue>>,
+    ) -> Option<TagValue> {
         match source {
-            InfoSource::FileName { .. } => self.path().file_name()?.to_str().map(Cow::Borrowed),
-            InfoSource::Tag { name, .. } => {
-                let all_tags = cached_tags.map(Cow::Borrowed).or_else(|| {
-                    self.get_tags()
-                        .map_err(|err| {
-                            warn!("Failed to get datetime tags for {}: {:?}", self, err);
-                        })
-                        .ok()
-                        .map(Cow::Owned)
-                })?;
-                match all_tags {
-                    Cow::Borrowed(cached) => {
-                        cached.get(name).map(String::as_ref).map(Cow::Borrowed)
-                    }
-                    Cow::Owned(mut tags) => tags.remove(name).map(Cow::Owned),
-                }
-            }
+            InfoSource::FileName { .. } => self.path().file_name()?.to_str().map(TagValue::from),
+            InfoSource::Tag { name, .. } => self.get_tag_value(&[name], cached_tags),
+            InfoSource::Takeout { .. } => self.get_tag_value(&[TAKEOUT_TIME_TAG], cached_tags),
         }
     }
 
+    /// Find the value of the first found tag.
+    ///
+    /// The tags are looked up [by their identifiers or descriptions][find_tag].
+    pub(crate) fn get_tag_value<S>(
+        &self,
+        names: &[S],
+        cached_tags: Option<&HashMap<String, TagValue>>,
+    ) -> Option<TagValue>
+    where
+        S: AsRef<str>,
+    {
+        let all_tags = cached_tags.map(Cow::Borrowed).or_else(|| {
+            self.get_tags()
+                .map_err(|err| {
+                    warn!("Failed to get tags for {}: {:?}", self, err);
+                })
+                .ok()
+                .map(Cow::Owned)
+        })?;
+        names
+            .iter()
+            .find_map(|name| find_tag(&all_tags, name.as_ref()))
+            .cloned()
+    }
+
     /// Retrieves DateTime from the media metadata.
     ///
+    /// If the tag already holds the [date and time][TagValue::DateTime],
+    /// the format is not used for parsing.
+    ///
     /// For better performance, the cached tags collection could be provided.
     pub fn get_datetime_from_source(
         &self,
         source: &InfoSource,
-        cached_tags: Option<&HashMap<String, String>>,
+        cached_tags: Option<&HashMap<String, TagValue>>,
     ) -> Option<NaiveDateTime> {
         let value = self.get_value(source, cached_tags)?;
         let format = match source {
             InfoSource::FileName { format } | InfoSource::Tag { format, .. } => format,
+            InfoSource::Takeout { utc_offset } => {
+                return parse_timestamp(&value, utc_offset.as_deref());
+            }
         };
+        if let Some(dt) = value.as_datetime() {
+            return if format.only_date {
+                dt.date().and_hms_opt(0, 0, 0)
+            } else {
+                Some(dt)
+            };
+        }
+
+        let value = value.to_string();
         let value = if let Some(n) = format.take_prefix {
             value.chars().take(n).collect()
         } else {
@@ -134,13 +198,151 @@ impl Media {
                 warn!("Failed to get datetime tags for {}: {:?}", self, err);
             })
             .ok();
+        self.get_datetime_with_tags(sources, all_tags.as_ref())
+    }
 
+    /// Retrieves DateTime from the media metadata
+    /// using multiple sources till success.
+    ///
+    /// For better performance, the cached tags collection could be provided.
+    pub fn get_datetime_with_tags(
+        &self,
+        sources: &[InfoSource],
+        cached_tags: Option<&HashMap<String, TagValue>>,
+    ) -> Option<Time> {
         sources.iter().find_map(|source| {
-            let dt = self.get_datetime_from_source(source, all_tags.as_ref())?;
+            let dt = self.get_datetime_from_source(source, cached_tags)?;
             Some(Time {
                 inner: dt,
                 source: source.clone(),
             })
         })
     }
+
+    /// Retrieves the offset of the local time from UTC (the time zone of the camera)
+    /// from the media metadata.
+    ///
+    /// For better performance, the cached tags collection could be provided.
+    pub fn get_utc_offset_with_tags(
+        &self,
+        cached_tags: Option<&HashMap<String, TagValue>>,
+    ) -> Option<FixedOffset> {
+        let value = self.get_tag_value(UTC_OFFSET_TAGS, cached_tags)?;
+        value.to_string().trim().parse().ok()
+    }
+}
+
+/// Convert the UNIX timestamp into the local time with the given UTC offset.
+fn parse_timestamp(value: &TagValue, utc_offset: Option<&str>) -> Option<NaiveDateTime> {
+    let utc = DateTime::from_timestamp(value.as_i64()?, 0)?;
+    let Some(offset) = utc_offset else {
+        return Some(utc.naive_utc());
+    };
+    match offset.parse::<FixedOffset>() {
+        Ok(offset) => Some(utc.with_timezone(&offset).naive_local()),
+        Err(err) => {
+            warn!("Invalid UTC offset {:?}: {}", offset, err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::file_types::MediaType;
+
+    use super::*;
+
+    fn media(name: &str) -> Media {
+        Media {
+            type_: MediaType::Photo,
+            path: PathBuf::from(name),
+        }
+    }
+
+    fn datetime(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn takeout(utc_offset: Option<&str>) -> InfoSource {
+        InfoSource::Takeout {
+            utc_offset: utc_offset.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn takeout_timestamp() {
+        // 2022-07-08 10:30:15 UTC
+        let tags = HashMap::from([(TAKEOUT_TIME_TAG.to_owned(), TagValue::from("1657276215"))]);
+        let photo = media("IMG_0001.jpg");
+        let get = |source| photo.get_datetime_from_source(&source, Some(&tags));
+
+        assert_eq!(get(takeout(None)), Some(datetime("2022-07-08 10:30:15")));
+        assert_eq!(
+            get(takeout(Some("+03:00"))),
+            Some(datetime("2022-07-08 13:30:15"))
+        );
+        assert_eq!(
+            get(takeout(Some("-11:00"))),
+            Some(datetime("2022-07-07 23:30:15"))
+        );
+        assert_eq!(get(takeout(Some("Europe/Kyiv"))), None);
+    }
+
+    #[test]
+    fn iso_tag_keeps_local_time() {
+        let source = InfoSource::Tag {
+            name: "xmp:CreateDate".to_owned(),
+            format: "%Y-%m-%d".into(),
+        };
+        let photo = media("IMG_0001.jpg");
+        for (text, expected) in [
+            ("2022-07-08T10:30:15+03:00", "2022-07-08 10:30:15"),
+            ("2022-07-08T10:30:15", "2022-07-08 10:30:15"),
+        ] {
+            let tags = HashMap::from([("xmp:CreateDate".to_owned(), TagValue::from_text(text))]);
+            assert_eq!(
+                photo.get_datetime_from_source(&source, Some(&tags)),
+                Some(datetime(expected)),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn file_name() {
+        let source = InfoSource::FileName {
+            format: Format {
+                fmt: "PXL_%Y%m%d_%H%M%S".to_owned(),
+                take_prefix: Some(19),
+                only_date: false,
+            },
+        };
+        let tags = HashMap::new();
+        assert_eq!(
+            media("PXL_20220708_103015123.jpg").get_datetime_from_source(&source, Some(&tags)),
+            Some(datetime("2022-07-08 10:30:15"))
+        );
+        assert_eq!(
+            media("IMG_0001.jpg").get_datetime_from_source(&source, Some(&tags)),
+            None
+        );
+    }
+
+    #[test]
+    fn utc_offset() {
+        let photo = media("IMG_0001.jpg");
+        let tags = HashMap::from([
+            ("nikon:UtcOffset".to_owned(), TagValue::from("+02:00")),
+            ("exif:OffsetTime".to_owned(), TagValue::from("-05:30")),
+        ]);
+        assert_eq!(
+            photo.get_utc_offset_with_tags(Some(&tags)),
+            FixedOffset::west_opt(5 * 3600 + 30 * 60)
+        );
+        assert_eq!(photo.get_utc_offset_with_tags(Some(&HashMap::new())), None);
+    }
 }
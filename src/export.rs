@@ -0,0 +1,558 @@
+//! Exporting the locations of the media for the map viewers (GeoJSON or KML).
+use std::{collections::HashMap, fmt::Write as _, path::Path, str::FromStr};
+
+use chrono::NaiveDateTime;
+use serde_json::{json, Map as JsonMap, Value as JsonValue};
+
+use crate::{
+    event::{
+        cluster::{cluster_events, ClusterOptions, TimelineEntry},
+        space::Location,
+        Event,
+    },
+    file_types::Media,
+};
+
+/// The format of the time in the exported files.
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// The format of the exported map.
+pub enum MapFormat {
+    /// [GeoJSON](https://geojson.org/) `FeatureCollection`.
+    GeoJson,
+    /// Keyhole Markup Language used by Google Earth.
+    Kml,
+}
+
+impl MapFormat {
+    /// Guess the format by the extension of the file.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl FromStr for MapFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "geojson" | "json" => Ok(Self::GeoJson),
+            "kml" => Ok(Self::Kml),
+            _ => Err(format!(
+                "unknown map format {:?}, expected geojson or kml",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// How to connect the media locations into the tracks.
+pub enum TrackGrouping {
+    /// A track per [event][crate::EventCluster].
+    Event,
+    /// A track per calendar day.
+    Day,
+}
+
+impl FromStr for TrackGrouping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "event" => Ok(Self::Event),
+            "day" => Ok(Self::Day),
+            _ => Err(format!(
+                "unknown tracks grouping {:?}, expected event or day",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A media placed on the map.
+pub struct MapPoint {
+    /// The media itself.
+    pub media: Media,
+    /// Where the media was shot.
+    pub location: Location,
+    /// When the media was shot.
+    pub time: Option<NaiveDateTime>,
+    /// The URL of the thumbnail to show along with the point.
+    pub thumbnail: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The path through the locations of the media of a single event (or day).
+pub struct MapTrack {
+    /// Title of the track.
+    pub name: String,
+    /// The time of the first media.
+    pub start: NaiveDateTime,
+    /// The time of the last media.
+    pub end: NaiveDateTime,
+    /// The (latitude, longitude) ordered by time.
+    pub points: Vec<(f64, f64)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// The media locations (and optionally the tracks) to render into a map file.
+pub struct MapExport {
+    points: Vec<MapPoint>,
+    tracks: Vec<MapTrack>,
+}
+
+impl MapExport {
+    /// Collect the media having the location ordered by time (the ones without time go last).
+    ///
+    /// If the `tracks` are requested, the media locations are connected into them.
+    /// The `thumbnail` is called for every located media to get the URL of its thumbnail.
+    pub fn new(
+        media: impl IntoIterator<Item = (Media, Event)>,
+        tracks: Option<TrackGrouping>,
+        options: &ClusterOptions,
+        mut thumbnail: impl FnMut(&Media) -> Option<String>,
+    ) -> Self {
+        let media: Vec<_> = media.into_iter().collect();
+        let mut points: Vec<_> = media
+            .iter()
+            .filter_map(|(media, event)| {
+                Some(MapPoint {
+                    media: media.clone(),
+                    location: event.location()?,
+                    time: event.time().map(|time| time.datetime()),
+                    thumbnail: thumbnail(media),
+                })
+            })
+            .collect();
+        points.sort_by_key(|point| (point.time.is_none(), point.time));
+
+        let tracks = match tracks {
+            None => vec![],
+            Some(TrackGrouping::Day) => day_tracks(&points),
+            Some(TrackGrouping::Event) => event_tracks(media, &points, options),
+        };
+        Self { points, tracks }
+    }
+
+    /// The media having the location.
+    pub fn points(&self) -> &[MapPoint] {
+        &self.points
+    }
+
+    /// The tracks having at least two points.
+    pub fn tracks(&self) -> &[MapTrack] {
+        &self.tracks
+    }
+
+    /// Render the map in the given format.
+    pub fn render(&self, format: MapFormat) -> String {
+        match format {
+            MapFormat::GeoJson => self.to_geojson(),
+            MapFormat::Kml => self.to_kml(),
+        }
+    }
+
+    /// Render the `FeatureCollection` of the `Point`s and the `LineString`s.
+    pub fn to_geojson(&self) -> String {
+        let points = self.points.iter().map(|point| {
+            let location = &point.location;
+            let mut coordinates = vec![location.longitude(), location.latitude()];
+            coordinates.extend(location.altitude());
+
+            let mut properties = JsonMap::new();
+            properties.insert(
+                "path".into(),
+                point.media.path().display().to_string().into(),
+            );
+            properties.insert("type".into(), point.media.type_().to_string().into());
+            if let Some(time) = point.time {
+                properties.insert("time".into(), time.format(TIME_FORMAT).to_string().into());
+            }
+            if location.is_inferred() {
+                properties.insert("inferred".into(), true.into());
+            }
+            if let Some(thumbnail) = &point.thumbnail {
+                properties.insert("thumbnail".into(), thumbnail.clone().into());
+            }
+            json!({
+                "type": "Feature",
+                "geometry": { "type": "Point", "coordinates": coordinates },
+                "properties": properties,
+            })
+        });
+        let tracks = self.tracks.iter().map(|track| {
+            let coordinates: Vec<_> = track
+                .points
+                .iter()
+                .map(|(latitude, longitude)| [*longitude, *latitude])
+                .collect();
+            json!({
+                "type": "Feature",
+                "geometry": { "type": "LineString", "coordinates": coordinates },
+                "properties": {
+                    "name": track.name,
+                    "start": track.start.format(TIME_FORMAT).to_string(),
+                    "end": track.end.format(TIME_FORMAT).to_string(),
+                },
+            })
+        });
+
+        let collection = json!({
+            "type": "FeatureCollection",
+            "features": points.chain(tracks).collect::<Vec<JsonValue>>(),
+        });
+        collection.to_string()
+    }
+
+    /// Render the KML document with the `Placemark`s.
+    pub fn to_kml(&self) -> String {
+        let mut kml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n",
+        );
+        for point in &self.points {
+            let location = &point.location;
+            let path = point.media.path();
+            let name = path.file_name().map_or_else(
+                || path.display().to_string(),
+                |n| n.to_string_lossy().into(),
+            );
+            let mut description = format!("{} {}", point.media.type_(), path.display());
+            if location.is_inferred() {
+                description.push_str(" (inferred location)");
+            }
+            if let Some(thumbnail) = &point.thumbnail {
+                let _ = write!(description, "<br/><img src=\"{}\" />", escape(thumbnail));
+            }
+
+            kml.push_str("<Placemark>\n");
+            let _ = writeln!(kml, "  <name>{}</name>", escape(&name));
+            let _ = writeln!(kml, "  <description>{}</description>", escape(&description));
+            if let Some(time) = point.time {
+                let _ = writeln!(
+                    kml,
+                    "  <TimeStamp><when>{}</when></TimeStamp>",
+                    time.format(TIME_FORMAT)
+                );
+            }
+            let mut coordinates = format!("{},{}", location.longitude(), location.latitude());
+            if let Some(altitude) = location.altitude() {
+                let _ = write!(coordinates, ",{}", altitude);
+            }
+            let _ = writeln!(
+                kml,
+                "  <Point><coordinates>{}</coordinates></Point>",
+                coordinates
+            );
+            kml.push_str("</Placemark>\n");
+        }
+        for track in &self.tracks {
+            let coordinates: Vec<_> = track
+                .points
+                .iter()
+                .map(|(latitude, longitude)| format!("{},{}", longitude, latitude))
+                .collect();
+            kml.push_str("<Placemark>\n");
+            let _ = writeln!(kml, "  <name>{}</name>", escape(&track.name));
+            let _ = writeln!(
+                kml,
+                "  <TimeSpan><begin>{}</begin><end>{}</end></TimeSpan>",
+                track.start.format(TIME_FORMAT),
+                track.end.format(TIME_FORMAT)
+            );
+            let _ = writeln!(
+                kml,
+                "  <LineString><tessellate>1</tessellate><coordinates>{}</coordinates></LineString>",
+                coordinates.join(" ")
+            );
+            kml.push_str("</Placemark>\n");
+        }
+        kml.push_str("</Document>\n</kml>\n");
+        kml
+    }
+}
+
+/// Build a track from the points ordered by time (if there are enough of them).
+fn track<'a>(name: String, points: impl IntoIterator<Item = &'a MapPoint>) -> Option<MapTrack> {
+    let points: Vec<_> = points
+        .into_iter()
+        .filter_map(|point| Some((point.time?, point.location)))
+        .collect();
+    let (start, end) = (points.first()?.0, points.last()?.0);
+    if points.len() < 2 {
+        return None;
+    }
+    Some(MapTrack {
+        name,
+        start,
+        end,
+        points: points
+            .iter()
+            .map(|(_, location)| (location.latitude(), location.longitude()))
+            .collect(),
+    })
+}
+
+fn day_tracks(points: &[MapPoint]) -> Vec<MapTrack> {
+    let mut tracks = vec![];
+    let mut rest = points;
+    while let Some(date) = rest.first().and_then(|point| Some(point.time?.date())) {
+        let len = rest
+            .iter()
+            .take_while(|point| point.time.is_some_and(|time| time.date() == date))
+            .count();
+        tracks.extend(track(date.to_string(), &rest[..len]));
+        rest = &rest[len..];
+    }
+    tracks
+}
+
+fn event_tracks(
+    media: Vec<(Media, Event)>,
+    points: &[MapPoint],
+    options: &ClusterOptions,
+) -> Vec<MapTrack> {
+    let by_path: HashMap<_, _> = points
+        .iter()
+        .map(|point| (point.media.path(), point))
+        .collect();
+    let events = cluster_events(media, options)
+        .into_iter()
+        .flat_map(|entry| match entry {
+            TimelineEntry::Event(event) => vec![event],
+            TimelineEntry::Trip(trip) => trip.events().to_vec(),
+        });
+    events
+        .filter_map(|event| {
+            let name = format!("{} .. {}", event.start(), event.end());
+            let points = event
+                .media()
+                .iter()
+                .filter_map(|media| by_path.get(media.path()).copied());
+            track(name, points)
+        })
+        .collect()
+}
+
+/// Escape the text for XML (or HTML).
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Percent-encode the path for a URL
+/// (the characters other than the unreserved ones and `/` are encoded).
+pub(crate) fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+    encoded
+}
+
+/// The `file://` URL of the absolute path.
+pub fn file_url(path: &Path) -> String {
+    format!("file://{}", encode_path(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        event::{space, time, EventSources},
+        file_types::MediaType,
+        tags::TagValue,
+    };
+
+    fn shot(name: &str, time: &str, point: Option<(f64, f64)>) -> (Media, Event) {
+        let media = Media {
+            type_: MediaType::Photo,
+            path: PathBuf::from(name),
+        };
+        let mut tags = HashMap::new();
+        if !time.is_empty() {
+            let time = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap();
+            tags.insert("exif:DateTimeOriginal".to_owned(), TagValue::DateTime(time));
+        }
+        if let Some((latitude, longitude)) = point {
+            tags.insert("exif:GPSLatitude".to_owned(), TagValue::Gps(latitude));
+            tags.insert("exif:GPSLongitude".to_owned(), TagValue::Gps(longitude));
+        }
+        let sources = EventSources {
+            time: vec![time::InfoSource::Tag {
+                name: "exif:DateTimeOriginal".to_owned(),
+                format: "%Y".into(),
+            }],
+            location: vec![space::InfoSource::Embedded],
+        };
+        let event = media.get_event_with_tags(&sources, Some(&tags));
+        (media, event)
+    }
+
+    fn export(tracks: Option<TrackGrouping>) -> MapExport {
+        let media = [
+            shot("/photos/b.jpg", "2023-06-10 12:00", Some((48.86, 2.35))),
+            shot(
+                "/photos/a&b <1>.jpg",
+                "2023-06-10 10:00",
+                Some((48.85, 2.34)),
+            ),
+            shot("/photos/no-location.jpg", "2023-06-10 11:00", None),
+            shot("/photos/no-time.jpg", "", Some((50.45, 30.52))),
+            shot("/photos/c.jpg", "2023-06-11 10:00", Some((45.76, 4.83))),
+        ];
+        MapExport::new(media, tracks, &ClusterOptions::default(), |media| {
+            Some(file_url(media.path()))
+        })
+    }
+
+    #[test]
+    fn points_and_day_tracks() {
+        let map = export(Some(TrackGrouping::Day));
+        let names: Vec<_> = map
+            .points()
+            .iter()
+            .map(|point| point.media.path().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "/photos/a&b <1>.jpg",
+                "/photos/b.jpg",
+                "/photos/c.jpg",
+                "/photos/no-time.jpg",
+            ]
+        );
+        // the single point of the second day does not make a track
+        assert_eq!(
+            map.tracks(),
+            [MapTrack {
+                name: "2023-06-10".to_owned(),
+                start: NaiveDateTime::parse_from_str("2023-06-10 10:00", "%Y-%m-%d %H:%M").unwrap(),
+                end: NaiveDateTime::parse_from_str("2023-06-10 12:00", "%Y-%m-%d %H:%M").unwrap(),
+                points: vec![(48.85, 2.34), (48.86, 2.35)],
+            }]
+        );
+        assert!(export(None).tracks().is_empty());
+    }
+
+    #[test]
+    fn event_tracks() {
+        let map = export(Some(TrackGrouping::Event));
+        let tracks: Vec<_> = map.tracks().iter().map(|track| &track.name).collect();
+        assert_eq!(tracks, ["2023-06-10 10:00:00 .. 2023-06-10 12:00:00"]);
+    }
+
+    #[test]
+    fn geojson() {
+        let map = export(Some(TrackGrouping::Day));
+        let json: JsonValue = serde_json::from_str(&map.to_geojson()).unwrap();
+        assert_eq!(json["type"], "FeatureCollection");
+        let features = json["features"].as_array().unwrap();
+        assert_eq!(features.len(), 5);
+
+        let first = &features[0];
+        assert_eq!(first["geometry"]["type"], "Point");
+        assert_eq!(first["geometry"]["coordinates"], json!([2.34, 48.85]));
+        assert_eq!(first["properties"]["path"], "/photos/a&b <1>.jpg");
+        assert_eq!(first["properties"]["type"], "PHOTO");
+        assert_eq!(first["properties"]["time"], "2023-06-10T10:00:00");
+        assert_eq!(
+            first["properties"]["thumbnail"],
+            "file:///photos/a%26b%20%3C1%3E.jpg"
+        );
+        assert!(features[3]["properties"].get("time").is_none());
+
+        let track = &features[4];
+        assert_eq!(track["geometry"]["type"], "LineString");
+        assert_eq!(
+            track["geometry"]["coordinates"],
+            json!([[2.34, 48.85], [2.35, 48.86]])
+        );
+        assert_eq!(track["properties"]["name"], "2023-06-10");
+        assert_eq!(track["properties"]["end"], "2023-06-10T12:00:00");
+    }
+
+    #[test]
+    fn kml() {
+        let kml = export(Some(TrackGrouping::Day)).to_kml();
+        assert!(kml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<kml "));
+        assert!(kml.ends_with("</Document>\n</kml>\n"));
+        assert_eq!(kml.matches("<Placemark>").count(), 5);
+        assert!(kml.contains("  <name>a&amp;b &lt;1&gt;.jpg</name>\n"));
+        assert!(kml.contains(
+            "  <description>PHOTO /photos/a&amp;b &lt;1&gt;.jpg\
+             &lt;br/&gt;&lt;img src=&quot;file:///photos/a%26b%20%3C1%3E.jpg&quot; /&gt;\
+             </description>\n"
+        ));
+        assert!(kml.contains("  <TimeStamp><when>2023-06-10T10:00:00</when></TimeStamp>\n"));
+        assert!(kml.contains("  <Point><coordinates>2.34,48.85</coordinates></Point>\n"));
+        assert!(kml.contains(
+            "<TimeSpan><begin>2023-06-10T10:00:00</begin><end>2023-06-10T12:00:00</end></TimeSpan>"
+        ));
+        assert!(kml.contains("<coordinates>2.34,48.85 2.35,48.86</coordinates></LineString>"));
+    }
+
+    #[test]
+    fn escape_text() {
+        assert_eq!(escape("plain text"), "plain text");
+        assert_eq!(
+            escape(r#"<a href="x">Tom & 'Jerry'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &apos;Jerry&apos;&lt;/a&gt;"
+        );
+        assert_eq!(escape("Київ"), "Київ");
+    }
+
+    #[test]
+    fn encode_paths() {
+        assert_eq!(
+            encode_path(Path::new("/photos/2023/IMG_0001-a.b~c.jpg")),
+            "/photos/2023/IMG_0001-a.b~c.jpg"
+        );
+        assert_eq!(
+            encode_path(Path::new("My Photos/#1 ?&%.jpg")),
+            "My%20Photos/%231%20%3F%26%25.jpg"
+        );
+        assert_eq!(encode_path(Path::new("Київ")), "%D0%9A%D0%B8%D1%97%D0%B2");
+        assert_eq!(file_url(Path::new("/a b")), "file:///a%20b");
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(
+            MapFormat::from_path(Path::new("map.GeoJSON")),
+            Some(MapFormat::GeoJson)
+        );
+        assert_eq!(
+            MapFormat::from_path(Path::new("map.json")),
+            Some(MapFormat::GeoJson)
+        );
+        assert_eq!(
+            MapFormat::from_path(Path::new("map.kml")),
+            Some(MapFormat::Kml)
+        );
+        assert_eq!(MapFormat::from_path(Path::new("map.gpx")), None);
+        assert_eq!(MapFormat::from_path(Path::new("map")), None);
+        assert_eq!("Day".parse(), Ok(TrackGrouping::Day));
+        assert!("week".parse::<TrackGrouping>().is_err());
+    }
+}
@@ -1,12 +1,17 @@
 use std::{
+    collections::BTreeMap,
     fmt,
     path::{Path, PathBuf},
 };
 
 use enum_iterator::Sequence;
+use serde::{Deserialize, Serialize};
 
 #[allow(missing_docs)]
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Sequence)]
+#[derive(
+    Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Sequence, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
 /// High-level type of the photo-video resource.
 pub enum MediaType {
     Photo,
@@ -19,13 +24,13 @@ impl MediaType {
     pub fn supported_extensions(self) -> Vec<&'static str> {
         match self {
             Self::Photo => {
-                vec!["jpg", "jpeg", "png"]
+                vec!["jpg", "jpeg", "png", "heic", "heif"]
             }
             Self::Animation => {
                 vec!["gif"]
             }
             Self::Video => {
-                vec!["mp4"]
+                vec!["mp4", "mov"]
             }
         }
     }
@@ -42,6 +47,82 @@ impl fmt::Display for MediaType {
     }
 }
 
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+/// Changes to the [default extensions][MediaType::supported_extensions]
+/// of a single [`MediaType`].
+pub struct ExtensionsOverride {
+    #[serde(default)]
+    /// Extensions to treat as the given type.
+    ///
+    /// If an extension belongs to another type by default,
+    /// it gets reassigned.
+    pub add: Vec<String>,
+
+    #[serde(default)]
+    /// Extensions to ignore for the given type.
+    pub remove: Vec<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// Mapping of file extensions to [`MediaType`]s.
+pub struct ExtensionMap {
+    inner: Vec<(MediaType, String)>,
+}
+
+impl Default for ExtensionMap {
+    fn default() -> Self {
+        let inner = enum_iterator::all::<MediaType>()
+            .flat_map(|type_| {
+                type_
+                    .supported_extensions()
+                    .into_iter()
+                    .map(move |ext| (type_, ext.to_owned()))
+            })
+            .collect();
+        Self { inner }
+    }
+}
+
+fn normalize_extension(ext: &str) -> String {
+    ext.trim_start_matches('.').to_ascii_lowercase()
+}
+
+impl ExtensionMap {
+    /// Create an [`ExtensionMap`] by applying the overrides
+    /// to the default set of extensions.
+    pub fn with_overrides(overrides: &BTreeMap<MediaType, ExtensionsOverride>) -> Self {
+        let mut map = Self::default();
+        for (&type_, change) in overrides {
+            for ext in &change.remove {
+                let ext = normalize_extension(ext);
+                map.inner.retain(|(t, e)| !(*t == type_ && *e == ext));
+            }
+        }
+        for (&type_, change) in overrides {
+            for ext in &change.add {
+                let ext = normalize_extension(ext);
+                map.inner.retain(|(_, e)| *e != ext);
+                map.inner.push((type_, ext));
+            }
+        }
+        map
+    }
+
+    /// Which file extensions are associated with the type.
+    pub fn extensions(&self, type_: MediaType) -> impl Iterator<Item = &str> + '_ {
+        self.inner
+            .iter()
+            .filter_map(move |(t, ext)| (*t == type_).then_some(ext.as_str()))
+    }
+
+    /// Detect the [`MediaType`] by a file extension (case-insensitive).
+    pub fn media_type(&self, extension: &str) -> Option<MediaType> {
+        self.inner
+            .iter()
+            .find_map(|(type_, ext)| ext.eq_ignore_ascii_case(extension).then_some(*type_))
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 /// Represents a single media resource.
 pub struct Media {
@@ -0,0 +1,791 @@
+//! Filter expressions over the media metadata.
+//!
+//! The expression is a combination of conditions with
+//! `and` (`&&`), `or` (`||`), `not` (`!`) and parentheses.
+//! The consecutive conditions without an operator are combined with `and`:
+//!
+//! ```text
+//! type = video duration > 1m year = 2022 model ~ "pixel 7" not has gps
+//! ```
+//!
+//! The conditions are:
+//! - `has FIELD` checks the field (or the tag `tag:ID`) is present;
+//! - `FIELD OP VALUE` compares the field with the value using one of
+//!   `=`, `!=`, `<`, `<=`, `>`, `>=` or the regular expression match `~`, `!~`;
+//! - `near(LATITUDE, LONGITUDE, RADIUS)` checks the location is inside the circle
+//!   (the radius is in meters unless the unit is given, e.g. `5km`);
+//! - `within(SOUTH, WEST, NORTH, EAST)` checks the location is inside the bounding box.
+//!
+//! The textual fields (`type`, `path`, `name`, `ext`, `time_source`, `location_source`,
+//! `make`, `model`, `camera`, `lens`, `codec`, `sidecar`) are compared case-insensitively
+//! and support the wildcards `*` and `?` with `=`. The place names (`country`, `region`, `city`)
+//! are the textual fields too, they need the [`Geocoder`].
+//!
+//! The numeric fields (`year`, `month`, `day`, `hour`, `latitude`, `longitude`, `altitude`,
+//! `width`, `height`, `megapixels`, `iso`, `aperture`, `exposure`, `focal`, `focal35`,
+//! `duration`, `fps`) accept fractions (`exposure < 1/250`), units
+//! (`duration > 90s`, `1.5m` or `2h`; `altitude > 1km`) and the inclusive ranges (`iso = 100..400`).
+//!
+//! The `time` is compared with a (partial) date and time denoting the whole period:
+//! `time = 2022-07` matches the whole July, `time < 2022` matches everything before 2022,
+//! `time = 2022-07-01..2022-07-15` matches the first half of July.
+//!
+//! The tags (`tag:exif:Model`, `tag:xmp:Rating`) are compared as numbers, as times
+//! or as text depending on the type of their values.
+//!
+//! A condition on a missing value is never satisfied,
+//! use `not has FIELD` to find such media.
+mod parser;
+
+use std::{collections::HashMap as Map, error::Error, fmt, str::FromStr};
+
+use chrono::{Datelike as _, NaiveDateTime, Timelike as _};
+use log::warn;
+use once_cell::unsync::OnceCell;
+use regex::Regex;
+
+use crate::{
+    event::{
+        space::{self, Location},
+        time::{InfoSource as TimeSource, Time},
+        EventSources,
+    },
+    file_types::Media,
+    geocode::{Geocoder, Place},
+    tags::{find_tag, TagValue},
+    technical::Technical,
+    video::VideoInfo,
+};
+
+#[derive(Debug, Clone)]
+/// Parsed filter expression to select the media by their metadata.
+///
+/// See the [module documentation][self] for the syntax.
+pub struct Filter {
+    source: String,
+    expr: Expr,
+}
+
+impl FromStr for Filter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            source: s.to_owned(),
+            expr: parser::parse(s)?,
+        })
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Filter {
+    /// Whether the media satisfies the filter.
+    ///
+    /// The metadata is only read if the expression needs it,
+    /// so the conditions on the `type` and `path`
+    /// should go first for the better performance.
+    ///
+    /// Without the geocoder the conditions on the place names are never satisfied.
+    pub fn matches(
+        &self,
+        media: &Media,
+        sources: &EventSources,
+        geocoder: Option<&Geocoder>,
+    ) -> bool {
+        Facts::new(media, sources, geocoder).eval(&self.expr)
+    }
+
+    /// Whether the expression has the conditions on the place names.
+    pub fn uses_places(&self) -> bool {
+        fn uses(expr: &Expr) -> bool {
+            match expr {
+                Expr::And(operands) | Expr::Or(operands) => operands.iter().any(uses),
+                Expr::Not(expr) => uses(expr),
+                Expr::Has(field) | Expr::Text(field, _) => field.is_place(),
+                Expr::Number(..)
+                | Expr::Time(..)
+                | Expr::Tag(..)
+                | Expr::Near { .. }
+                | Expr::Within { .. } => false,
+            }
+        }
+        uses(&self.expr)
+    }
+}
+
+/// Parse the (partial) date and time or their range like in the `time` condition
+/// (`2022-07`, `2022-07-01..2022-07-15`) into the half-open period `[start, end)`.
+pub(crate) fn parse_period(text: &str) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    let interval = parser::parse_time_interval(text)?;
+    Some((interval.start, interval.end))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Failed to parse the filter expression.
+pub struct FilterError {
+    position: usize,
+    message: String,
+}
+
+impl FilterError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+
+    /// The byte offset in the expression where the error occurred.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// What is wrong.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl Error for FilterError {}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+    NotMatch,
+}
+
+impl Op {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+            Self::Match => "~",
+            Self::NotMatch => "!~",
+        }
+    }
+
+    fn is_ordering(self) -> bool {
+        matches!(self, Self::Lt | Self::Le | Self::Gt | Self::Ge)
+    }
+
+    fn is_regex(self) -> bool {
+        matches!(self, Self::Match | Self::NotMatch)
+    }
+
+    fn is_negated(self) -> bool {
+        matches!(self, Self::Ne | Self::NotMatch)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Field {
+    Type,
+    Path,
+    Name,
+    Ext,
+    Time,
+    Year,
+    Month,
+    Day,
+    Hour,
+    TimeSource,
+    Location,
+    Latitude,
+    Longitude,
+    Altitude,
+    LocationSource,
+    Width,
+    Height,
+    Megapixels,
+    Make,
+    Model,
+    Camera,
+    Lens,
+    Iso,
+    Aperture,
+    Exposure,
+    Focal,
+    Focal35,
+    Duration,
+    Fps,
+    Codec,
+    Sidecar,
+    Country,
+    Region,
+    City,
+    Tag(String),
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        if let Some(id) = name.strip_prefix("tag:").filter(|id| !id.is_empty()) {
+            return Some(Self::Tag(id.to_owned()));
+        }
+        Some(match name.to_lowercase().as_str() {
+            "type" => Self::Type,
+            "path" => Self::Path,
+            "name" => Self::Name,
+            "ext" | "extension" => Self::Ext,
+            "time" | "date" => Self::Time,
+            "year" => Self::Year,
+            "month" => Self::Month,
+            "day" => Self::Day,
+            "hour" => Self::Hour,
+            "time_source" | "time.source" => Self::TimeSource,
+            "location" | "gps" => Self::Location,
+            "latitude" | "lat" => Self::Latitude,
+            "longitude" | "lon" => Self::Longitude,
+            "altitude" | "alt" => Self::Altitude,
+            "location_source" | "location.source" => Self::LocationSource,
+            "width" => Self::Width,
+            "height" => Self::Height,
+            "megapixels" | "mp" => Self::Megapixels,
+            "make" => Self::Make,
+            "model" => Self::Model,
+            "camera" => Self::Camera,
+            "lens" => Self::Lens,
+            "iso" => Self::Iso,
+            "aperture" => Self::Aperture,
+            "exposure" | "shutter" => Self::Exposure,
+            "focal" => Self::Focal,
+            "focal35" => Self::Focal35,
+            "duration" => Self::Duration,
+            "fps" => Self::Fps,
+            "codec" => Self::Codec,
+            "sidecar" => Self::Sidecar,
+            "country" => Self::Country,
+            "region" => Self::Region,
+            "city" => Self::City,
+            _ => return None,
+        })
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Self::Type => "type",
+            Self::Path => "path",
+            Self::Name => "name",
+            Self::Ext => "ext",
+            Self::Time => "time",
+            Self::Year => "year",
+            Self::Month => "month",
+            Self::Day => "day",
+            Self::Hour => "hour",
+            Self::TimeSource => "time_source",
+            Self::Location => "location",
+            Self::Latitude => "latitude",
+            Self::Longitude => "longitude",
+            Self::Altitude => "altitude",
+            Self::LocationSource => "location_source",
+            Self::Width => "width",
+            Self::Height => "height",
+            Self::Megapixels => "megapixels",
+            Self::Make => "make",
+            Self::Model => "model",
+            Self::Camera => "camera",
+            Self::Lens => "lens",
+            Self::Iso => "iso",
+            Self::Aperture => "aperture",
+            Self::Exposure => "exposure",
+            Self::Focal => "focal",
+            Self::Focal35 => "focal35",
+            Self::Duration => "duration",
+            Self::Fps => "fps",
+            Self::Codec => "codec",
+            Self::Sidecar => "sidecar",
+            Self::Country => "country",
+            Self::Region => "region",
+            Self::City => "city",
+            Self::Tag(id) => id,
+        }
+    }
+
+    fn is_text(&self) -> bool {
+        matches!(
+            self,
+            Self::Type
+                | Self::Path
+                | Self::Name
+                | Self::Ext
+                | Self::TimeSource
+                | Self::LocationSource
+                | Self::Make
+                | Self::Model
+                | Self::Camera
+                | Self::Lens
+                | Self::Codec
+                | Self::Sidecar
+                | Self::Country
+                | Self::Region
+                | Self::City
+        )
+    }
+
+    fn is_place(&self) -> bool {
+        matches!(self, Self::Country | Self::Region | Self::City)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+/// The range of values, either closed `[start, end]` or half-open `[start, end)`.
+struct Interval<T> {
+    start: T,
+    end: T,
+    closed: bool,
+}
+
+impl<T: PartialOrd> Interval<T> {
+    fn closed(start: T, end: T) -> Self {
+        Self {
+            start,
+            end,
+            closed: true,
+        }
+    }
+
+    fn half_open(start: T, end: T) -> Self {
+        Self {
+            start,
+            end,
+            closed: false,
+        }
+    }
+
+    /// Equality means being inside the interval,
+    /// while the ordering compares with its bounds.
+    fn compare(&self, op: Op, value: &T) -> bool {
+        let before_end = if self.closed {
+            *value <= self.end
+        } else {
+            *value < self.end
+        };
+        let inside = self.start <= *value && before_end;
+        match op {
+            Op::Eq => inside,
+            Op::Ne => !inside,
+            Op::Lt => *value < self.start,
+            Op::Le => before_end,
+            Op::Gt => !before_end,
+            Op::Ge => *value >= self.start,
+            Op::Match | Op::NotMatch => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TextPattern {
+    regex: Regex,
+    negate: bool,
+}
+
+impl TextPattern {
+    fn matches(&self, values: &[String]) -> bool {
+        values.iter().any(|value| self.regex.is_match(value)) != self.negate
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The value to compare a tag with in all the possible interpretations.
+struct TagLiteral {
+    pattern: Regex,
+    number: Option<Interval<f64>>,
+    time: Option<Interval<NaiveDateTime>>,
+}
+
+impl TagLiteral {
+    fn matches(&self, op: Op, value: &TagValue) -> bool {
+        if let TagValue::List(values) = value {
+            return if op.is_negated() {
+                values.iter().all(|value| self.matches(op, value))
+            } else {
+                values.iter().any(|value| self.matches(op, value))
+            };
+        }
+
+        if op.is_regex() {
+            return self.pattern.is_match(&value.to_string()) != op.is_negated();
+        }
+        if let (Some(dt), Some(time)) = (value.as_datetime(), &self.time) {
+            return time.compare(op, &dt);
+        }
+        if let (Some(number), Some(interval)) = (value.as_f64(), &self.number) {
+            return interval.compare(op, &number);
+        }
+        match op {
+            Op::Eq => self.pattern.is_match(&value.to_string()),
+            Op::Ne => !self.pattern.is_match(&value.to_string()),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Has(Field),
+    Text(Field, TextPattern),
+    Number(Field, Op, Interval<f64>),
+    Time(Op, Interval<NaiveDateTime>),
+    Tag(String, Op, TagLiteral),
+    /// The radius is in meters.
+    Near {
+        latitude: f64,
+        longitude: f64,
+        radius: f64,
+    },
+    Within {
+        south: f64,
+        west: f64,
+        north: f64,
+        east: f64,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum Fact {
+    Text(Vec<String>),
+    Number(f64),
+    Time(NaiveDateTime),
+}
+
+/// The metadata of a single media retrieved lazily.
+struct Facts<'a> {
+    media: &'a Media,
+    sources: &'a EventSources,
+    geocoder: Option<&'a Geocoder>,
+    tags: OnceCell<Map<String, TagValue>>,
+    time: OnceCell<Option<Time>>,
+    location: OnceCell<Option<Location>>,
+    technical: OnceCell<Technical>,
+    video: OnceCell<Option<VideoInfo>>,
+    place: OnceCell<Option<Place>>,
+}
+
+impl<'a> Facts<'a> {
+    fn new(media: &'a Media, sources: &'a EventSources, geocoder: Option<&'a Geocoder>) -> Self {
+        Self {
+            media,
+            sources,
+            geocoder,
+            tags: OnceCell::new(),
+            time: OnceCell::new(),
+            location: OnceCell::new(),
+            technical: OnceCell::new(),
+            video: OnceCell::new(),
+            place: OnceCell::new(),
+        }
+    }
+
+    fn tags(&self) -> &Map<String, TagValue> {
+        self.tags.get_or_init(|| {
+            self.media
+                .get_tags()
+                .map_err(|err| {
+                    warn!("Failed to get tags for {}: {:?}", self.media, err);
+                })
+                .unwrap_or_default()
+        })
+    }
+
+    fn time(&self) -> Option<&Time> {
+        self.time
+            .get_or_init(|| {
+                self.media
+                    .get_datetime_with_tags(&self.sources.time, Some(self.tags()))
+            })
+            .as_ref()
+    }
+
+    fn location(&self) -> Option<&Location> {
+        self.location
+            .get_or_init(|| {
+                self.media
+                    .get_location_with_tags(&self.sources.location, Some(self.tags()))
+            })
+            .as_ref()
+    }
+
+    fn technical(&self) -> &Technical {
+        self.technical
+            .get_or_init(|| self.media.technical_with_tags(self.tags()))
+    }
+
+    fn video(&self) -> Option<&VideoInfo> {
+        self.video
+            .get_or_init(|| self.media.video_info().ok())
+            .as_ref()
+    }
+
+    fn place(&self) -> Option<&Place> {
+        self.place
+            .get_or_init(|| self.geocoder?.locate(self.location()?))
+            .as_ref()
+    }
+
+    fn eval(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::And(operands) => operands.iter().all(|expr| self.eval(expr)),
+            Expr::Or(operands) => operands.iter().any(|expr| self.eval(expr)),
+            Expr::Not(expr) => !self.eval(expr),
+            Expr::Has(field) => self.fact(field).is_some(),
+            Expr::Text(field, pattern) => match self.fact(field) {
+                Some(Fact::Text(values)) => pattern.matches(&values),
+                _ => false,
+            },
+            Expr::Number(field, op, interval) => match self.fact(field) {
+                Some(Fact::Number(number)) => interval.compare(*op, &number),
+                _ => false,
+            },
+            Expr::Time(op, interval) => match self.fact(&Field::Time) {
+                Some(Fact::Time(dt)) => interval.compare(*op, &dt),
+                _ => false,
+            },
+            Expr::Tag(id, op, literal) => {
+                find_tag(self.tags(), id).is_some_and(|value| literal.matches(*op, value))
+            }
+            Expr::Near {
+                latitude,
+                longitude,
+                radius,
+            } => self.location().is_some_and(|location| {
+                let point = (location.latitude(), location.longitude());
+                space::distance(point, (*latitude, *longitude)) <= *radius
+            }),
+            Expr::Within {
+                south,
+                west,
+                north,
+                east,
+            } => self.location().is_some_and(|location| {
+                let (latitude, longitude) = (location.latitude(), location.longitude());
+                let inside_longitude = if west <= east {
+                    (*west..=*east).contains(&longitude)
+                } else {
+                    // the box crosses the antimeridian
+                    longitude >= *west || longitude <= *east
+                };
+                (*south..=*north).contains(&latitude) && inside_longitude
+            }),
+        }
+    }
+
+    fn fact(&self, field: &Field) -> Option<Fact> {
+        let text = |value: String| Some(Fact::Text(vec![value]));
+        let number = |value: f64| Some(Fact::Number(value));
+        let technical = || self.technical();
+        let time = || self.time().map(Time::datetime);
+        let path = self.media.path();
+
+        match field {
+            Field::Type => text(self.media.type_().to_string().to_lowercase()),
+            Field::Path => text(path.display().to_string()),
+            Field::Name => text(path.file_name()?.to_string_lossy().into_owned()),
+            Field::Ext => text(path.extension()?.to_string_lossy().into_owned()),
+            Field::Time => time().map(Fact::Time),
+            Field::Year => number(f64::from(time()?.year())),
+            Field::Month => number(f64::from(time()?.month())),
+            Field::Day => number(f64::from(time()?.day())),
+            Field::Hour => number(f64::from(time()?.hour())),
+            Field::TimeSource => {
+                let names = match self.time()?.source() {
+                    TimeSource::FileName { .. } => vec!["filename".into()],
+                    TimeSource::Tag { name, .. } => vec!["tag".into(), name.clone()],
+                    TimeSource::Takeout { .. } => vec!["takeout".into()],
+                };
+                Some(Fact::Text(names))
+            }
+            Field::Location => text(self.location()?.to_string()),
+            Field::Latitude => number(self.location()?.latitude()),
+            Field::Longitude => number(self.location()?.longitude()),
+            Field::Altitude => number(self.location()?.altitude()?),
+            Field::LocationSource => {
+                let location = self.location()?;
+                let mut sources = vec![format!("{:?}", location.source()).to_lowercase()];
+                if location.is_inferred() {
+                    sources.push("inferred".to_owned());
+                }
+                Some(Fact::Text(sources))
+            }
+            Field::Width => number(f64::from(technical().display_size()?.0)),
+            Field::Height => number(f64::from(technical().display_size()?.1)),
+            Field::Megapixels => number(technical().megapixels()?),
+            Field::Make => text(technical().make.clone()?),
+            Field::Model => text(technical().model.clone()?),
+            Field::Camera => text(technical().camera()?),
+            Field::Lens => text(technical().lens.clone()?),
+            Field::Iso => number(f64::from(technical().iso?)),
+            Field::Aperture => number(technical().aperture?),
+            Field::Exposure => number(technical().exposure_time?),
+            Field::Focal => number(technical().focal_length?),
+            Field::Focal35 => number(f64::from(technical().focal_length_35mm?)),
+            Field::Duration => number(self.video()?.duration()?.as_secs_f64()),
+            Field::Fps => number(self.video()?.video_stream()?.frame_rate?),
+            Field::Codec => {
+                let codecs: Vec<_> = self
+                    .video()?
+                    .streams
+                    .iter()
+                    .map(|stream| stream.codec.clone())
+                    .collect();
+                (!codecs.is_empty()).then_some(Fact::Text(codecs))
+            }
+            Field::Sidecar => {
+                let kinds: Vec<_> = self
+                    .media
+                    .sidecars()
+                    .iter()
+                    .map(|sidecar| sidecar.kind().to_string().to_lowercase())
+                    .collect();
+                (!kinds.is_empty()).then_some(Fact::Text(kinds))
+            }
+            Field::Country => {
+                let place = self.place()?;
+                Some(Fact::Text(vec![
+                    place.country.clone(),
+                    place.country_code.clone(),
+                ]))
+            }
+            Field::Region => text(self.place()?.region.clone()?),
+            Field::City => text(self.place()?.city.clone()),
+            Field::Tag(id) => text(find_tag(self.tags(), id)?.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_OPS: [Op; 6] = [Op::Eq, Op::Ne, Op::Lt, Op::Le, Op::Gt, Op::Ge];
+
+    /// The ops satisfied by the value.
+    fn satisfied<T: PartialOrd>(interval: &Interval<T>, value: &T) -> Vec<Op> {
+        ALL_OPS
+            .into_iter()
+            .filter(|op| interval.compare(*op, value))
+            .collect()
+    }
+
+    #[test]
+    fn closed_interval_includes_both_bounds() {
+        let interval = Interval::closed(100.0, 400.0);
+        assert_eq!(satisfied(&interval, &50.0), [Op::Ne, Op::Lt, Op::Le]);
+        assert_eq!(satisfied(&interval, &100.0), [Op::Eq, Op::Le, Op::Ge]);
+        assert_eq!(satisfied(&interval, &250.0), [Op::Eq, Op::Le, Op::Ge]);
+        assert_eq!(satisfied(&interval, &400.0), [Op::Eq, Op::Le, Op::Ge]);
+        assert_eq!(satisfied(&interval, &401.0), [Op::Ne, Op::Gt, Op::Ge]);
+    }
+
+    #[test]
+    fn single_value_interval() {
+        let interval = Interval::closed(2022.0, 2022.0);
+        assert_eq!(satisfied(&interval, &2021.0), [Op::Ne, Op::Lt, Op::Le]);
+        assert_eq!(satisfied(&interval, &2022.0), [Op::Eq, Op::Le, Op::Ge]);
+        assert_eq!(satisfied(&interval, &2023.0), [Op::Ne, Op::Gt, Op::Ge]);
+    }
+
+    #[test]
+    fn half_open_period_excludes_its_end() {
+        // `time = 2022-07`
+        let interval = parser::parse_time_interval("2022-07").unwrap();
+        let at = |text| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap();
+
+        assert_eq!(
+            satisfied(&interval, &at("2022-06-30 23:59:59")),
+            [Op::Ne, Op::Lt, Op::Le]
+        );
+        assert_eq!(
+            satisfied(&interval, &at("2022-07-01 00:00:00")),
+            [Op::Eq, Op::Le, Op::Ge]
+        );
+        // `time <= 2022-07` includes the whole July...
+        assert_eq!(
+            satisfied(&interval, &at("2022-07-31 23:59:59")),
+            [Op::Eq, Op::Le, Op::Ge]
+        );
+        // ...and `time > 2022-07` starts with August
+        assert_eq!(
+            satisfied(&interval, &at("2022-08-01 00:00:00")),
+            [Op::Ne, Op::Gt, Op::Ge]
+        );
+    }
+
+    #[test]
+    fn regex_ops_never_compare_intervals() {
+        let interval = Interval::closed(1, 3);
+        assert!(!interval.compare(Op::Match, &2));
+        assert!(!interval.compare(Op::NotMatch, &2));
+    }
+
+    /// Whether the tag value satisfies the condition `tag:ID {condition}`.
+    fn tag_matches(condition: &str, value: &TagValue) -> bool {
+        match parser::parse(&format!("tag:test {}", condition)).unwrap() {
+            Expr::Tag(_, op, literal) => literal.matches(op, value),
+            expr => panic!("not a tag condition: {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn tags_compared_by_their_types() {
+        let rating = TagValue::Integer(4);
+        assert!(tag_matches("= 3..5", &rating));
+        assert!(tag_matches("> 3", &rating));
+        assert!(!tag_matches("< 4", &rating));
+
+        let exposure = TagValue::Rational {
+            numerator: 1,
+            denominator: 500,
+        };
+        assert!(tag_matches("< 1/250", &exposure));
+        assert!(tag_matches("~ '^1/5'", &exposure));
+
+        let created = TagValue::DateTime(
+            NaiveDateTime::parse_from_str("2022-07-08 10:30:00", "%Y-%m-%d %H:%M:%S").unwrap(),
+        );
+        assert!(tag_matches("= 2022-07", &created));
+        assert!(tag_matches("< 2023", &created));
+        assert!(!tag_matches("> 2022", &created));
+
+        let model = TagValue::Text("Pixel 7".into());
+        assert!(tag_matches("= 'pixel*'", &model));
+        assert!(tag_matches("!= iPhone", &model));
+        // the text is never ordered
+        assert!(!tag_matches("> A", &model));
+    }
+
+    #[test]
+    fn tag_lists_match_any_or_negated_all() {
+        let subjects = TagValue::List(vec![
+            TagValue::Text("travel".into()),
+            TagValue::Text("mountains".into()),
+        ]);
+        assert!(tag_matches("= travel", &subjects));
+        assert!(tag_matches("~ mount", &subjects));
+        assert!(!tag_matches("!= travel", &subjects));
+        assert!(!tag_matches("!~ mount", &subjects));
+        assert!(tag_matches("!= sea", &subjects));
+    }
+}
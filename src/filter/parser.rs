@@ -0,0 +1,787 @@
+//! Tokenizer and recursive descent parser of the filter expressions.
+use chrono::{Datelike as _, Months, NaiveDate, NaiveDateTime, TimeDelta};
+use regex::{Regex, RegexBuilder};
+
+use super::{Expr, Field, FilterError, Interval, Op, TagLiteral, TextPattern};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Unquoted word: a keyword, a field name or a value.
+    Word(String),
+    /// Single- or double-quoted string.
+    Quoted(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    LeftParen,
+    RightParen,
+    Comma,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Self::Word(word) => format!("{:?}", word),
+            Self::Quoted(text) => format!("string {:?}", text),
+            Self::Op(op) => format!("{:?}", op.as_str()),
+            Self::And => "'and'".into(),
+            Self::Or => "'or'".into(),
+            Self::Not => "'not'".into(),
+            Self::LeftParen => "'('".into(),
+            Self::RightParen => "')'".into(),
+            Self::Comma => "','".into(),
+        }
+    }
+}
+
+/// Characters having the special meaning outside the quotes.
+fn is_special(c: char) -> bool {
+    c.is_whitespace() || "()=!<>~,&|\"'".contains(c)
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, FilterError> {
+    let mut tokens = vec![];
+    let mut chars = input.char_indices().peekable();
+    while let Some((pos, c)) = chars.next() {
+        let mut next_is = |expected: char| chars.next_if(|(_, c)| *c == expected).is_some();
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            ',' => Token::Comma,
+            '=' => {
+                // allow the C-style `==` as well
+                next_is('=');
+                Token::Op(Op::Eq)
+            }
+            '~' => Token::Op(Op::Match),
+            '!' if next_is('=') => Token::Op(Op::Ne),
+            '!' if next_is('~') => Token::Op(Op::NotMatch),
+            '!' => Token::Not,
+            '<' if next_is('=') => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if next_is('=') => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '&' if next_is('&') => Token::And,
+            '|' if next_is('|') => Token::Or,
+            '&' | '|' => {
+                return Err(FilterError::new(pos, format!("expected '{c}{c}'")));
+            }
+            '"' | '\'' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, end)) if end == c => break,
+                        Some((_, '\\')) => {
+                            if let Some((_, escaped)) = chars.next() {
+                                text.push(escaped);
+                            }
+                        }
+                        Some((_, other)) => text.push(other),
+                        None => return Err(FilterError::new(pos, "unterminated string")),
+                    }
+                }
+                Token::Quoted(text)
+            }
+            c => {
+                let mut word = String::from(c);
+                while let Some((_, c)) = chars.next_if(|(_, c)| !is_special(*c)) {
+                    word.push(c);
+                }
+                match word.to_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Word(word),
+                }
+            }
+        };
+        tokens.push((pos, token));
+    }
+    Ok(tokens)
+}
+
+/// Parse the whole filter expression.
+pub(super) fn parse(input: &str) -> Result<Expr, FilterError> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+        end: input.len(),
+    };
+    if parser.tokens.is_empty() {
+        return Err(FilterError::new(0, "empty filter"));
+    }
+    let expr = parser.or()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(parser.error(format!("unexpected {}", token.describe()))),
+    }
+}
+
+#[derive(Debug)]
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    /// The length of the input to report the errors at the end.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(_, token)| token.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, expected: &Token) -> bool {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Position of the current token in the input.
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |(pos, _)| *pos)
+    }
+
+    fn error(&self, message: impl Into<String>) -> FilterError {
+        FilterError::new(self.offset(), message)
+    }
+
+    fn expect(&mut self, expected: &Token) -> Result<(), FilterError> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected {}", expected.describe())))
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, FilterError> {
+        let mut operands = vec![self.and()?];
+        while self.eat(&Token::Or) {
+            operands.push(self.and()?);
+        }
+        Ok(if operands.len() == 1 {
+            operands.remove(0)
+        } else {
+            Expr::Or(operands)
+        })
+    }
+
+    /// The `and` keyword is optional:
+    /// the consecutive conditions are combined with it implicitly.
+    fn and(&mut self) -> Result<Expr, FilterError> {
+        let mut operands = vec![self.unary()?];
+        loop {
+            if !self.eat(&Token::And)
+                && matches!(self.peek(), None | Some(Token::Or | Token::RightParen))
+            {
+                break;
+            }
+            operands.push(self.unary()?);
+        }
+        Ok(if operands.len() == 1 {
+            operands.remove(0)
+        } else {
+            Expr::And(operands)
+        })
+    }
+
+    fn unary(&mut self) -> Result<Expr, FilterError> {
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat(&Token::LeftParen) {
+            let expr = self.or()?;
+            self.expect(&Token::RightParen)?;
+            return Ok(expr);
+        }
+
+        let offset = self.offset();
+        let Some(Token::Word(word)) = self.next() else {
+            self.pos -= 1;
+            return Err(self.error("expected a condition"));
+        };
+        if word.eq_ignore_ascii_case("has") {
+            let offset = self.offset();
+            let Some(Token::Word(name)) = self.next() else {
+                self.pos -= 1;
+                return Err(self.error("expected a field name after 'has'"));
+            };
+            let field = Field::parse(&name).ok_or_else(|| unknown_field(offset, &name))?;
+            return Ok(Expr::Has(field));
+        }
+        if self.peek() == Some(&Token::LeftParen) {
+            return self.function(offset, &word);
+        }
+
+        let field = Field::parse(&word).ok_or_else(|| unknown_field(offset, &word))?;
+        let Some(Token::Op(op)) = self.peek().cloned() else {
+            return Err(self.error(format!("expected a comparison after {:?}", word)));
+        };
+        self.pos += 1;
+        let offset = self.offset();
+        let value = match self.next() {
+            Some(Token::Word(value) | Token::Quoted(value)) => value,
+            _ => {
+                self.pos -= 1;
+                return Err(self.error("expected a value"));
+            }
+        };
+        comparison(field, op, &value).map_err(|message| FilterError::new(offset, message))
+    }
+
+    fn function(&mut self, offset: usize, name: &str) -> Result<Expr, FilterError> {
+        self.expect(&Token::LeftParen)?;
+        let mut args = vec![];
+        loop {
+            let arg_offset = self.offset();
+            match self.next() {
+                Some(Token::Word(arg) | Token::Quoted(arg)) => args.push((arg_offset, arg)),
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.error("expected an argument"));
+                }
+            }
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RightParen)?;
+
+        let number = |(offset, arg): &(usize, String), units: &[(&str, f64)]| {
+            parse_number(arg, units)
+                .ok_or_else(|| FilterError::new(*offset, format!("invalid number {:?}", arg)))
+        };
+        let coordinates = |args: &[(usize, String)]| {
+            args.iter()
+                .map(|arg| number(arg, &[]))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        match (name.to_lowercase().as_str(), args.as_slice()) {
+            ("near", [latitude, longitude, radius]) => {
+                let point = coordinates(&[latitude.clone(), longitude.clone()])?;
+                Ok(Expr::Near {
+                    latitude: point[0],
+                    longitude: point[1],
+                    radius: number(radius, DISTANCE_UNITS)?,
+                })
+            }
+            ("within", [_, _, _, _]) => {
+                let bbox = coordinates(&args)?;
+                Ok(Expr::Within {
+                    south: bbox[0],
+                    west: bbox[1],
+                    north: bbox[2],
+                    east: bbox[3],
+                })
+            }
+            ("near", _) => Err(FilterError::new(
+                offset,
+                "expected near(LATITUDE, LONGITUDE, RADIUS)",
+            )),
+            ("within", _) => Err(FilterError::new(
+                offset,
+                "expected within(SOUTH, WEST, NORTH, EAST)",
+            )),
+            _ => Err(FilterError::new(
+                offset,
+                format!("unknown function {:?}", name),
+            )),
+        }
+    }
+}
+
+fn unknown_field(offset: usize, name: &str) -> FilterError {
+    FilterError::new(offset, format!("unknown field {:?}", name))
+}
+
+/// The units of distance in meters.
+const DISTANCE_UNITS: &[(&str, f64)] = &[("km", 1000.0), ("m", 1.0)];
+
+/// The units of duration in seconds.
+const DURATION_UNITS: &[(&str, f64)] = &[("h", 3600.0), ("min", 60.0), ("m", 60.0), ("s", 1.0)];
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Parse a number which could be a fraction (`1/250`)
+/// or have one of the given unit suffixes (`10km`).
+fn parse_number(text: &str, units: &[(&str, f64)]) -> Option<f64> {
+    let text = text.trim();
+    let (text, multiplier) = units
+        .iter()
+        .find_map(|(unit, multiplier)| {
+            let number = text.strip_suffix(unit)?;
+            // `1e3m` is fine, but not the `m` alone
+            number
+                .ends_with(|c: char| c.is_ascii_digit() || c == '.')
+                .then_some((number, *multiplier))
+        })
+        .unwrap_or((text, 1.0));
+
+    let number = if let Some((numerator, denominator)) = text.split_once('/') {
+        let denominator: f64 = denominator.trim().parse().ok()?;
+        if denominator == 0.0 {
+            return None;
+        }
+        numerator.trim().parse::<f64>().ok()? / denominator
+    } else {
+        text.parse().ok()?
+    };
+    number.is_finite().then_some(number * multiplier)
+}
+
+/// Parse a single number or the inclusive range of numbers `MIN..MAX`.
+fn parse_interval(text: &str, parse: impl Fn(&str) -> Option<f64>) -> Option<Interval<f64>> {
+    if let Some((start, end)) = text.split_once("..") {
+        Some(Interval::closed(parse(start)?, parse(end)?))
+    } else {
+        let value = parse(text)?;
+        Some(Interval::closed(value, value))
+    }
+}
+
+/// Parse the month number (`7`) or name (`jul`, `July`).
+fn parse_month(text: &str) -> Option<f64> {
+    if let Ok(number) = text.parse::<u8>() {
+        return (1..=12).contains(&number).then_some(f64::from(number));
+    }
+    let prefix = text.get(..3)?.to_lowercase();
+    let index = MONTHS.iter().position(|month| *month == prefix)?;
+    #[allow(clippy::cast_precision_loss)]
+    Some((index + 1) as f64)
+}
+
+/// Parse a (partial) date or time denoting the whole period:
+/// `2022` (a year), `2022-07` (a month), `2022-07-08` (a day),
+/// `2022-07-08T10:30` (a minute) or `2022-07-08T10:30:15` (a second).
+fn parse_time(text: &str) -> Option<Interval<NaiveDateTime>> {
+    let text = text.trim().replacen(' ', "T", 1);
+    let start_of = |date: NaiveDate| date.and_hms_opt(0, 0, 0);
+
+    let (start, end) = match text.len() {
+        4 => {
+            let year = text.parse().ok()?;
+            let start = NaiveDate::from_ymd_opt(year, 1, 1)?;
+            (start_of(start)?, start_of(start.with_year(year + 1)?)?)
+        }
+        7 => {
+            let start = NaiveDate::parse_from_str(&format!("{text}-01"), "%Y-%m-%d").ok()?;
+            (
+                start_of(start)?,
+                start_of(start.checked_add_months(Months::new(1))?)?,
+            )
+        }
+        10 => {
+            let start = start_of(NaiveDate::parse_from_str(&text, "%Y-%m-%d").ok()?)?;
+            (start, start + TimeDelta::days(1))
+        }
+        16 => {
+            let start = NaiveDateTime::parse_from_str(&text, "%Y-%m-%dT%H:%M").ok()?;
+            (start, start + TimeDelta::minutes(1))
+        }
+        _ => {
+            let start = NaiveDateTime::parse_from_str(&text, "%Y-%m-%dT%H:%M:%S%.f").ok()?;
+            (start, start + TimeDelta::seconds(1))
+        }
+    };
+    Some(Interval::half_open(start, end))
+}
+
+/// Parse a single period of time or the range `FROM..TO` (including the whole `TO` period).
+pub(super) fn parse_time_interval(text: &str) -> Option<Interval<NaiveDateTime>> {
+    if let Some((start, end)) = text.split_once("..") {
+        let (start, end) = (parse_time(start)?, parse_time(end)?);
+        Some(Interval::half_open(start.start, end.end))
+    } else {
+        parse_time(text)
+    }
+}
+
+/// Compile the text pattern: a regular expression for `~`
+/// or a wildcard (`*` and `?`) pattern for `=`.
+/// Both are case-insensitive.
+fn text_pattern(op: Op, value: &str) -> Result<Regex, String> {
+    let pattern = if op.is_regex() {
+        value.to_owned()
+    } else {
+        let mut pattern = String::from("^");
+        for c in value.chars() {
+            match c {
+                '*' => pattern.push_str(".*"),
+                '?' => pattern.push('.'),
+                c => pattern.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        pattern.push('$');
+        pattern
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(true)
+        .build()
+        .map_err(|err| format!("invalid pattern {:?}: {}", value, err))
+}
+
+fn comparison(field: Field, op: Op, value: &str) -> Result<Expr, String> {
+    if let Field::Tag(id) = field {
+        let literal = TagLiteral {
+            pattern: text_pattern(op, value)?,
+            number: parse_interval(value, |text| parse_number(text, &[])),
+            time: parse_time_interval(value),
+        };
+        return Ok(Expr::Tag(id, op, literal));
+    }
+
+    if field == Field::Location {
+        return Err("the location can only be checked with 'has', near() or within()".into());
+    }
+    if field.is_text() {
+        if op.is_ordering() {
+            return Err(format!(
+                "{:?} cannot be compared with {:?}",
+                field.name(),
+                op.as_str()
+            ));
+        }
+        let pattern = TextPattern {
+            regex: text_pattern(op, value)?,
+            negate: op.is_negated(),
+        };
+        return Ok(Expr::Text(field, pattern));
+    }
+
+    if op.is_regex() {
+        return Err(format!(
+            "{:?} cannot be matched with {:?}",
+            field.name(),
+            op.as_str()
+        ));
+    }
+    if field == Field::Time {
+        let interval =
+            parse_time_interval(value).ok_or_else(|| format!("invalid time {:?}", value))?;
+        return Ok(Expr::Time(op, interval));
+    }
+
+    let interval = match field {
+        Field::Month => parse_interval(value, parse_month),
+        Field::Duration => parse_interval(value, |text| parse_number(text, DURATION_UNITS)),
+        Field::Altitude => parse_interval(value, |text| parse_number(text, DISTANCE_UNITS)),
+        _ => parse_interval(value, |text| parse_number(text, &[])),
+    };
+    let interval = interval.ok_or_else(|| format!("invalid number {:?}", value))?;
+    Ok(Expr::Number(field, op, interval))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dt(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn number(input: &str) -> (Field, Op, Interval<f64>) {
+        match parse(input).unwrap() {
+            Expr::Number(field, op, interval) => (field, op, interval),
+            expr => panic!("not a numeric condition: {:?}", expr),
+        }
+    }
+
+    fn time(input: &str) -> (Op, Interval<NaiveDateTime>) {
+        match parse(input).unwrap() {
+            Expr::Time(op, interval) => (op, interval),
+            expr => panic!("not a time condition: {:?}", expr),
+        }
+    }
+
+    fn error(input: &str) -> (usize, String) {
+        let err = parse(input).unwrap_err();
+        (err.position(), err.message().to_owned())
+    }
+
+    #[test]
+    fn tokens_with_positions() {
+        let tokens = tokenize("not(a==1)&&b!~'x\\'y' || c>=2").unwrap();
+        assert_eq!(
+            tokens,
+            [
+                (0, Token::Not),
+                (3, Token::LeftParen),
+                (4, Token::Word("a".into())),
+                (5, Token::Op(Op::Eq)),
+                (7, Token::Word("1".into())),
+                (8, Token::RightParen),
+                (9, Token::And),
+                (11, Token::Word("b".into())),
+                (12, Token::Op(Op::NotMatch)),
+                (14, Token::Quoted("x'y".into())),
+                (21, Token::Or),
+                (24, Token::Word("c".into())),
+                (25, Token::Op(Op::Ge)),
+                (27, Token::Word("2".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn keywords_are_case_insensitive() {
+        let tokens: Vec<_> = tokenize("AND Or nOt android")
+            .unwrap()
+            .into_iter()
+            .map(|(_, token)| token)
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::And,
+                Token::Or,
+                Token::Not,
+                Token::Word("android".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let expr = parse("type = video duration > 1m or not has gps").unwrap();
+        let Expr::Or(operands) = expr else {
+            panic!("expected 'or': {:?}", expr);
+        };
+        assert!(matches!(
+            operands.as_slice(),
+            [Expr::And(conditions), Expr::Not(negated)]
+                if matches!(
+                    conditions.as_slice(),
+                    [Expr::Text(Field::Type, _), Expr::Number(Field::Duration, Op::Gt, _)]
+                ) && matches!(**negated, Expr::Has(Field::Location))
+        ));
+    }
+
+    #[test]
+    fn parentheses_group_the_conditions() {
+        let expr = parse("year = 2022 && (month = 1 || month = 2)").unwrap();
+        let Expr::And(operands) = expr else {
+            panic!("expected 'and': {:?}", expr);
+        };
+        assert!(matches!(
+            operands.as_slice(),
+            [Expr::Number(Field::Year, Op::Eq, _), Expr::Or(months)] if months.len() == 2
+        ));
+    }
+
+    #[test]
+    fn metres_or_minutes_depending_on_the_field() {
+        assert_eq!(number("altitude > 1m").2, Interval::closed(1.0, 1.0));
+        assert_eq!(number("duration > 1m").2, Interval::closed(60.0, 60.0));
+        assert_eq!(number("duration > 2min").2, Interval::closed(120.0, 120.0));
+        assert_eq!(
+            number("duration > 1.5h").2,
+            Interval::closed(5400.0, 5400.0)
+        );
+        assert_eq!(
+            number("altitude < 1.5km").2,
+            Interval::closed(1500.0, 1500.0)
+        );
+        assert_eq!(
+            number("altitude < 1e3m").2,
+            Interval::closed(1000.0, 1000.0)
+        );
+        assert_eq!(
+            error("altitude > m"),
+            (11, "invalid number \"m\"".to_owned())
+        );
+        // the units are only accepted by the fields measured in them
+        assert_eq!(error("iso > 1m"), (6, "invalid number \"1m\"".to_owned()));
+    }
+
+    #[test]
+    fn fractions_and_ranges() {
+        let (field, op, interval) = number("exposure < 1/250");
+        assert_eq!((field, op), (Field::Exposure, Op::Lt));
+        assert_eq!(interval, Interval::closed(0.004, 0.004));
+        assert_eq!(number("iso = 100..400").2, Interval::closed(100.0, 400.0));
+        assert_eq!(
+            number("duration = 30s..2m").2,
+            Interval::closed(30.0, 120.0)
+        );
+        assert_eq!(
+            error("exposure < 1/0"),
+            (11, "invalid number \"1/0\"".to_owned())
+        );
+        assert_eq!(parse_number("inf", &[]), None);
+    }
+
+    #[test]
+    fn months_by_number_or_name() {
+        assert_eq!(number("month = 7").2, Interval::closed(7.0, 7.0));
+        assert_eq!(number("month = jul").2, Interval::closed(7.0, 7.0));
+        assert_eq!(number("month = December").2, Interval::closed(12.0, 12.0));
+        assert_eq!(number("month = mar..May").2, Interval::closed(3.0, 5.0));
+        assert_eq!(error("month = 13"), (8, "invalid number \"13\"".to_owned()));
+        assert_eq!(error("month = ju"), (8, "invalid number \"ju\"".to_owned()));
+    }
+
+    #[test]
+    fn partial_times_denote_whole_periods() {
+        let cases = [
+            ("2022", "2022-01-01 00:00:00", "2023-01-01 00:00:00"),
+            ("2022-12", "2022-12-01 00:00:00", "2023-01-01 00:00:00"),
+            ("2024-02-28", "2024-02-28 00:00:00", "2024-02-29 00:00:00"),
+            (
+                "2022-07-08T10:30",
+                "2022-07-08 10:30:00",
+                "2022-07-08 10:31:00",
+            ),
+            (
+                "'2022-07-08 10:30:15'",
+                "2022-07-08 10:30:15",
+                "2022-07-08 10:30:16",
+            ),
+            (
+                "2022-07-01..2022-07-15",
+                "2022-07-01 00:00:00",
+                "2022-07-16 00:00:00",
+            ),
+            (
+                "2021..2022-06",
+                "2021-01-01 00:00:00",
+                "2022-07-01 00:00:00",
+            ),
+        ];
+        for (value, start, end) in cases {
+            let (op, interval) = time(&format!("time >= {}", value));
+            assert_eq!(op, Op::Ge);
+            assert_eq!(
+                interval,
+                Interval::half_open(dt(start), dt(end)),
+                "{}",
+                value
+            );
+        }
+        assert_eq!(
+            error("time = 2022-13"),
+            (7, "invalid time \"2022-13\"".to_owned())
+        );
+        assert_eq!(
+            error("date = 2022-07-08T25:00"),
+            (7, "invalid time \"2022-07-08T25:00\"".to_owned())
+        );
+    }
+
+    #[test]
+    fn wildcards_are_anchored_and_case_insensitive() {
+        let Expr::Text(Field::Name, pattern) = parse("name = 'IMG_????.jp*g'").unwrap() else {
+            panic!("expected the name condition");
+        };
+        assert!(pattern.matches(&["img_0001.JPEG".into()]));
+        assert!(pattern.matches(&["IMG_0001.jpg".into()]));
+        assert!(!pattern.matches(&["IMG_01.jpg".into()]));
+        assert!(!pattern.matches(&["xIMG_0001.jpg".into()]));
+        // the dot is not a regex wildcard
+        assert!(!pattern.matches(&["IMG_0001-jpg".into()]));
+
+        let Expr::Text(Field::Model, pattern) = parse("model !~ 'pixel [0-9]'").unwrap() else {
+            panic!("expected the model condition");
+        };
+        assert!(!pattern.matches(&["Google Pixel 7".into()]));
+        assert!(pattern.matches(&["iPhone 13".into()]));
+    }
+
+    #[test]
+    fn tags_are_parsed_in_all_the_interpretations() {
+        let Expr::Tag(id, Op::Le, literal) = parse("tag:exif:DateTimeOriginal <= 2022-07").unwrap()
+        else {
+            panic!("expected the tag condition");
+        };
+        assert_eq!(id, "exif:DateTimeOriginal");
+        assert_eq!(
+            literal.time,
+            Some(Interval::half_open(
+                dt("2022-07-01 00:00:00"),
+                dt("2022-08-01 00:00:00")
+            ))
+        );
+        assert_eq!(literal.number, None);
+
+        let Expr::Tag(_, Op::Eq, literal) = parse("tag:xmp:Rating = 3..5").unwrap() else {
+            panic!("expected the tag condition");
+        };
+        assert_eq!(literal.number, Some(Interval::closed(3.0, 5.0)));
+        assert_eq!(literal.time, None);
+    }
+
+    #[test]
+    fn functions() {
+        assert!(matches!(
+            parse("near(50.45, 30.52, 5km)").unwrap(),
+            Expr::Near { latitude, longitude, radius }
+                if latitude == 50.45 && longitude == 30.52 && radius == 5000.0
+        ));
+        assert!(matches!(
+            parse("WITHIN(-10, 170, 10, -170)").unwrap(),
+            Expr::Within { south, west, north, east }
+                if south == -10.0 && west == 170.0 && north == 10.0 && east == -170.0
+        ));
+        assert_eq!(
+            error("near(50.45, 30.52)"),
+            (0, "expected near(LATITUDE, LONGITUDE, RADIUS)".to_owned())
+        );
+        assert_eq!(
+            error("type = photo within(1, 2, 3)"),
+            (13, "expected within(SOUTH, WEST, NORTH, EAST)".to_owned())
+        );
+        assert_eq!(
+            error("near(50.45, east, 1)"),
+            (12, "invalid number \"east\"".to_owned())
+        );
+        assert_eq!(error("near(1, 2, 3m"), (13, "expected ')'".to_owned()));
+        assert_eq!(error("near(1,)"), (7, "expected an argument".to_owned()));
+        assert_eq!(
+            error("far(1, 2, 3)"),
+            (0, "unknown function \"far\"".to_owned())
+        );
+    }
+
+    #[test]
+    fn error_positions() {
+        let cases = [
+            ("", 0, "empty filter"),
+            ("   ", 0, "empty filter"),
+            ("year = 2022 & month = 1", 12, "expected '&&'"),
+            ("name = 'abc", 7, "unterminated string"),
+            ("colour = red", 0, "unknown field \"colour\""),
+            ("has colour", 4, "unknown field \"colour\""),
+            ("has", 3, "expected a field name after 'has'"),
+            ("year 2022", 5, "expected a comparison after \"year\""),
+            ("year =", 6, "expected a value"),
+            ("year = )", 7, "expected a value"),
+            ("(year = 2022", 12, "expected ')'"),
+            ("year = 2022)", 11, "unexpected ')'"),
+            ("year = 2022 or", 14, "expected a condition"),
+            ("and year = 2022", 0, "expected a condition"),
+            ("year = abc", 7, "invalid number \"abc\""),
+            ("path < /tmp", 7, "\"path\" cannot be compared with \"<\""),
+            ("year ~ 20", 7, "\"year\" cannot be matched with \"~\""),
+            (
+                "location = 1",
+                11,
+                "the location can only be checked with 'has', near() or within()",
+            ),
+        ];
+        for (input, position, message) in cases {
+            assert_eq!(error(input), (position, message.to_owned()), "{:?}", input);
+        }
+        assert!(error("name ~ '('").1.starts_with("invalid pattern \"(\""));
+    }
+}
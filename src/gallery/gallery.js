@@ -0,0 +1,71 @@
+// The lightbox of the foton gallery: shows the preview of the photo
+// (or plays the video) along with its metadata.
+(function () {
+  'use strict';
+
+  var figures = Array.prototype.slice.call(document.querySelectorAll('figure.item'));
+  var box = document.getElementById('lightbox');
+  if (!box || figures.length === 0) {
+    return;
+  }
+  var stage = box.querySelector('.stage');
+  var info = box.querySelector('.info');
+  var current = -1;
+
+  function show(index) {
+    current = (index + figures.length) % figures.length;
+    var data = figures[current].dataset;
+    var element;
+    if (data.kind === 'video') {
+      element = document.createElement('video');
+      element.controls = true;
+      element.autoplay = true;
+      element.src = data.original;
+      if (data.preview) {
+        element.poster = data.preview;
+      }
+    } else {
+      element = document.createElement('img');
+      // the animations are only alive in the original
+      element.src = data.kind === 'animation' || !data.preview ? data.original : data.preview;
+      element.alt = data.name;
+    }
+    stage.innerHTML = '';
+    stage.appendChild(element);
+    info.innerHTML = figures[current].querySelector('.meta').innerHTML;
+    box.hidden = false;
+  }
+
+  function close() {
+    box.hidden = true;
+    // stops the video
+    stage.innerHTML = '';
+    current = -1;
+  }
+
+  figures.forEach(function (figure, index) {
+    figure.querySelector('a').addEventListener('click', function (event) {
+      event.preventDefault();
+      show(index);
+    });
+  });
+  box.querySelector('.close').addEventListener('click', close);
+  box.querySelector('.prev').addEventListener('click', function () {
+    show(current - 1);
+  });
+  box.querySelector('.next').addEventListener('click', function () {
+    show(current + 1);
+  });
+  document.addEventListener('keydown', function (event) {
+    if (box.hidden) {
+      return;
+    }
+    if (event.key === 'Escape') {
+      close();
+    } else if (event.key === 'ArrowLeft') {
+      show(current - 1);
+    } else if (event.key === 'ArrowRight') {
+      show(current + 1);
+    }
+  });
+})();
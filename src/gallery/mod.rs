@@ -0,0 +1,726 @@
+//! Static HTML site for browsing the library offline:
+//! the media grouped by years, months and events with the thumbnails
+//! and a lightbox showing the preview (or playing the video) with the metadata.
+//!
+//! Regenerating the site only rewrites the changed pages
+//! and copies the new thumbnails, the stale ones are removed.
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write as _,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use chrono::{Datelike as _, NaiveDateTime};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    event::{
+        cluster::{cluster_events, ClusterOptions, EventCluster, TimelineEntry},
+        Event,
+    },
+    export::{encode_path, escape},
+    file_types::{Media, MediaType},
+    geocode::Geocoder,
+    organize::EventNames,
+    thumbnail::ThumbnailCache,
+    AnyError,
+};
+
+const STYLE: &str = include_str!("gallery.css");
+const SCRIPT: &str = include_str!("gallery.js");
+
+const ASSETS_DIR: &str = "assets";
+const THUMBS_DIR: &str = "thumbs";
+const INDEX_PAGE: &str = "index.html";
+const UNDATED_PAGE: &str = "undated.html";
+
+/// Marks the pages generated by the gallery,
+/// so the other HTML files in the directory are never removed.
+const GENERATOR: &str = "<meta name=\"generator\" content=\"foton\">";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The look of the gallery.
+pub struct GalleryOptions {
+    #[serde(default = "GalleryOptions::default_title")]
+    /// The title of the site.
+    pub title: String,
+
+    #[serde(default = "GalleryOptions::default_thumbnail_size")]
+    /// The size of the thumbnails in the grid (the closest larger one is used).
+    pub thumbnail_size: u32,
+
+    #[serde(default = "GalleryOptions::default_preview_size")]
+    /// The size of the images in the lightbox (the closest larger one is used).
+    pub preview_size: u32,
+}
+
+impl GalleryOptions {
+    fn default_title() -> String {
+        "Photos".into()
+    }
+
+    const fn default_thumbnail_size() -> u32 {
+        256
+    }
+
+    const fn default_preview_size() -> u32 {
+        1024
+    }
+}
+
+impl Default for GalleryOptions {
+    fn default() -> Self {
+        Self {
+            title: Self::default_title(),
+            thumbnail_size: Self::default_thumbnail_size(),
+            preview_size: Self::default_preview_size(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+/// What has been done while generating the gallery.
+pub struct GalleryReport {
+    /// Number of the media in the gallery.
+    pub media: usize,
+    /// Number of the pages and assets written (the unchanged ones are not rewritten).
+    pub written: usize,
+    /// Number of the thumbnails copied into the gallery.
+    pub thumbnails_copied: usize,
+    /// Number of the stale pages and thumbnails removed.
+    pub removed: usize,
+    /// Number of the media without the thumbnails (e.g. of an unsupported format).
+    pub failed: usize,
+}
+
+/// A media prepared for the pages.
+#[derive(Debug)]
+struct Item {
+    name: String,
+    type_: MediaType,
+    time: Option<NaiveDateTime>,
+    /// The links are relative to the gallery directory.
+    thumbnail: Option<String>,
+    preview: Option<String>,
+    original: String,
+    meta: Vec<(&'static str, String)>,
+}
+
+#[derive(Debug)]
+/// Generator of the static HTML gallery.
+pub struct Gallery<'a> {
+    out_dir: PathBuf,
+    options: GalleryOptions,
+    events: ClusterOptions,
+    names: Option<&'a EventNames>,
+    geocoder: Option<&'a Geocoder>,
+}
+
+impl<'a> Gallery<'a> {
+    /// Prepare the gallery in the directory
+    /// grouping the media into events with the given options.
+    pub fn new(out_dir: &Path, options: &GalleryOptions, events: &ClusterOptions) -> Self {
+        Self {
+            out_dir: out_dir.to_owned(),
+            options: options.clone(),
+            events: *events,
+            names: None,
+            geocoder: None,
+        }
+    }
+
+    /// Title the events with the names.
+    pub fn with_names(mut self, names: &'a EventNames) -> Self {
+        self.names = Some(names);
+        self
+    }
+
+    /// Show the places of the media and the events.
+    pub fn with_geocoder(mut self, geocoder: &'a Geocoder) -> Self {
+        self.geocoder = Some(geocoder);
+        self
+    }
+
+    /// Generate (or update) the site taking the thumbnails from the cache.
+    pub fn generate(
+        &self,
+        media: impl IntoIterator<Item = (Media, Event)>,
+        cache: &mut ThumbnailCache,
+    ) -> Result<GalleryReport, AnyError> {
+        fs::create_dir_all(self.out_dir.join(THUMBS_DIR))?;
+        fs::create_dir_all(self.out_dir.join(ASSETS_DIR))?;
+        let root = fs::canonicalize(&self.out_dir)?;
+        let mut report = GalleryReport::default();
+
+        let media: Vec<_> = media.into_iter().collect();
+        let mut thumbnails = HashSet::new();
+        let mut items = HashMap::new();
+        for (media, event) in &media {
+            let item = self.prepare(media, event, &root, cache, &mut thumbnails, &mut report)?;
+            items.insert(media.path().to_owned(), item);
+        }
+        report.media = items.len();
+
+        let mut years: BTreeMap<i32, Vec<EventCluster>> = BTreeMap::new();
+        for entry in cluster_events(media, &self.events) {
+            let events = match entry {
+                TimelineEntry::Event(event) => vec![event],
+                TimelineEntry::Trip(trip) => trip.events().to_vec(),
+            };
+            for event in events {
+                years.entry(event.start().year()).or_default().push(event);
+            }
+        }
+        let mut undated: Vec<_> = items.values().filter(|item| item.time.is_none()).collect();
+        undated.sort_by(|a, b| a.original.cmp(&b.original));
+
+        let mut pages = vec![(
+            INDEX_PAGE.to_owned(),
+            self.index_page(&years, &items, &undated),
+        )];
+        let year_list: Vec<_> = years.keys().copied().collect();
+        for (i, (year, events)) in years.iter().enumerate() {
+            let prev = i.checked_sub(1).and_then(|i| year_list.get(i)).copied();
+            let next = year_list.get(i + 1).copied();
+            let page = self.year_page(*year, events, &items, prev, next);
+            pages.push((year_page_name(*year), page));
+        }
+        if !undated.is_empty() {
+            pages.push((UNDATED_PAGE.to_owned(), self.undated_page(&undated)));
+        }
+
+        let assets = [("gallery.css", STYLE), ("gallery.js", SCRIPT)];
+        for (name, content) in assets {
+            let path = self.out_dir.join(ASSETS_DIR).join(name);
+            report.written += usize::from(write_if_changed(&path, content)?);
+        }
+        for (name, content) in &pages {
+            report.written += usize::from(write_if_changed(&self.out_dir.join(name), content)?);
+        }
+
+        let page_names: HashSet<_> = pages.iter().map(|(name, _)| name.as_str()).collect();
+        report.removed += remove_stale(&self.out_dir, |name| {
+            let is_page = name == UNDATED_PAGE
+                || name
+                    .strip_suffix(".html")
+                    .is_some_and(|year| year.parse::<i32>().is_ok());
+            is_page && !page_names.contains(name) && is_generated(&self.out_dir.join(name))
+        })?;
+        report.removed += remove_stale(&self.out_dir.join(THUMBS_DIR), |name| {
+            !thumbnails.contains(name)
+        })?;
+        Ok(report)
+    }
+
+    fn prepare(
+        &self,
+        media: &Media,
+        event: &Event,
+        root: &Path,
+        cache: &mut ThumbnailCache,
+        used: &mut HashSet<String>,
+        report: &mut GalleryReport,
+    ) -> Result<Item, AnyError> {
+        let (thumbnail, preview) = match cache.get(media, false) {
+            Ok(thumbnails) => {
+                // the names of the thumbnails are unique, so the existing ones are up to date
+                let mut copy = |size| -> Result<Option<String>, AnyError> {
+                    let Some(source) = thumbnails.at_least(size) else {
+                        return Ok(None);
+                    };
+                    let Some(name) = source.file_name().and_then(|name| name.to_str()) else {
+                        return Ok(None);
+                    };
+                    let target = self.out_dir.join(THUMBS_DIR).join(name);
+                    if !target.exists() {
+                        fs::copy(source, &target)?;
+                        report.thumbnails_copied += 1;
+                    }
+                    used.insert(name.to_owned());
+                    Ok(Some(format!(
+                        "{}/{}",
+                        THUMBS_DIR,
+                        encode_path(Path::new(name))
+                    )))
+                };
+                (
+                    copy(self.options.thumbnail_size)?,
+                    copy(self.options.preview_size)?,
+                )
+            }
+            Err(err) => {
+                warn!("No thumbnails of {}: {}", media, err);
+                report.failed += 1;
+                (None, None)
+            }
+        };
+
+        let path = fs::canonicalize(media.path()).unwrap_or_else(|_| media.path().to_owned());
+        let original = encode_path(&relative_path(root, &path));
+        let time = event.time().map(|time| time.datetime());
+
+        let mut meta = vec![("Type", media.type_().to_string())];
+        if let Some(time) = time {
+            meta.push(("Time", time.format("%Y-%m-%d %H:%M:%S").to_string()));
+        }
+        let technical = media.technical().to_string();
+        if technical != "-" {
+            meta.push(("Details", technical));
+        }
+        if media.type_() != MediaType::Photo {
+            let duration = media.video_info().ok().and_then(|info| info.duration());
+            if let Some(duration) = duration {
+                meta.push(("Duration", format!("{:.1} s", duration.as_secs_f64())));
+            }
+        }
+        if let Some(location) = event.location() {
+            meta.push(("Location", location.to_string()));
+            if let Some(place) = self.geocoder.and_then(|g| g.locate(&location)) {
+                meta.push(("Place", place.to_string()));
+            }
+        }
+        meta.push(("File", media.path().display().to_string()));
+        debug!("Prepared {} for the gallery", media);
+
+        Ok(Item {
+            name: media
+                .path()
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            type_: media.type_(),
+            time,
+            thumbnail,
+            preview,
+            original,
+            meta,
+        })
+    }
+
+    fn index_page(
+        &self,
+        years: &BTreeMap<i32, Vec<EventCluster>>,
+        items: &HashMap<PathBuf, Item>,
+        undated: &[&Item],
+    ) -> String {
+        let mut body = String::from("<div class=\"albums\">\n");
+        for (year, events) in years {
+            let media: Vec<_> = events
+                .iter()
+                .flat_map(|event| event.media())
+                .filter_map(|media| items.get(media.path()))
+                .collect();
+            let summary = format!("{} media, {} events", media.len(), events.len());
+            album(
+                &mut body,
+                &year_page_name(*year),
+                &year.to_string(),
+                &summary,
+                &media,
+            );
+        }
+        if !undated.is_empty() {
+            let summary = format!("{} media", undated.len());
+            album(&mut body, UNDATED_PAGE, "Undated", &summary, undated);
+        }
+        body.push_str("</div>\n");
+        self.page(&self.options.title, "", &body)
+    }
+
+    fn year_page(
+        &self,
+        year: i32,
+        events: &[EventCluster],
+        items: &HashMap<PathBuf, Item>,
+        prev: Option<i32>,
+        next: Option<i32>,
+    ) -> String {
+        let mut months: BTreeMap<u32, Vec<&EventCluster>> = BTreeMap::new();
+        for event in events {
+            months.entry(event.start().month()).or_default().push(event);
+        }
+
+        let mut nav = String::new();
+        if let Some(prev) = prev {
+            let _ = write!(
+                nav,
+                " <a href=\"{}\">&larr; {}</a>",
+                year_page_name(prev),
+                prev
+            );
+        }
+        if let Some(next) = next {
+            let _ = write!(
+                nav,
+                " <a href=\"{}\">{} &rarr;</a>",
+                year_page_name(next),
+                next
+            );
+        }
+
+        let mut body = String::from("<p class=\"months\">");
+        for (month, events) in &months {
+            let _ = write!(
+                body,
+                "<a href=\"#m{:02}\">{}</a> ",
+                month,
+                events[0].start().format("%B")
+            );
+        }
+        body.push_str("</p>\n");
+        for (month, events) in &months {
+            let _ = writeln!(
+                body,
+                "<h2 id=\"m{:02}\">{}</h2>",
+                month,
+                events[0].start().format("%B %Y")
+            );
+            for event in events {
+                self.event_section(&mut body, event, items);
+            }
+        }
+        self.page(&year.to_string(), &nav, &body)
+    }
+
+    fn event_section(
+        &self,
+        out: &mut String,
+        event: &EventCluster,
+        items: &HashMap<PathBuf, Item>,
+    ) {
+        let (start, end) = (event.start(), event.end());
+        let period = if start == end {
+            start.format("%a %-d %B, %H:%M").to_string()
+        } else if start.date() == end.date() {
+            format!(
+                "{}, {}&ndash;{}",
+                start.format("%a %-d %B"),
+                start.format("%H:%M"),
+                end.format("%H:%M")
+            )
+        } else {
+            format!(
+                "{} &ndash; {}",
+                start.format("%a %-d %B %H:%M"),
+                end.format("%a %-d %B %H:%M")
+            )
+        };
+        let place = event
+            .centroid()
+            .and_then(|(latitude, longitude)| self.geocoder?.nearest(latitude, longitude));
+
+        // the named events show the period in the summary
+        let mut summary = vec![];
+        let title = match self.names.and_then(|names| names.name(start, end)) {
+            Some(name) => {
+                summary.push(period);
+                escape(name)
+            }
+            None => period,
+        };
+        summary.push(format!("{} media", event.media().len()));
+        summary.extend(place.map(|place| escape(&place.to_string())));
+
+        let _ = writeln!(
+            out,
+            "<section class=\"event\">\n<h3>{}</h3>\n<p class=\"summary\">{}</p>",
+            title,
+            summary.join(" &middot; ")
+        );
+        grid(
+            out,
+            event
+                .media()
+                .iter()
+                .filter_map(|media| items.get(media.path())),
+        );
+        out.push_str("</section>\n");
+    }
+
+    fn undated_page(&self, undated: &[&Item]) -> String {
+        let mut body = String::new();
+        grid(&mut body, undated.iter().copied());
+        self.page("Undated", "", &body)
+    }
+
+    fn page(&self, title: &str, nav: &str, body: &str) -> String {
+        let home = if title == self.options.title {
+            String::new()
+        } else {
+            format!(
+                "<a href=\"{}\">{}</a>",
+                INDEX_PAGE,
+                escape(&self.options.title)
+            )
+        };
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+{generator}
+<title>{title}</title>
+<link rel="stylesheet" href="{assets}/gallery.css">
+</head>
+<body>
+<header><nav>{home}{nav}</nav><h1>{title}</h1></header>
+<main>
+{body}</main>
+<div id="lightbox" hidden>
+<button class="close" title="Close (Esc)">&times;</button>
+<button class="prev" title="Previous (&larr;)">&lsaquo;</button>
+<div class="stage"></div>
+<button class="next" title="Next (&rarr;)">&rsaquo;</button>
+<aside class="info"></aside>
+</div>
+<script src="{assets}/gallery.js"></script>
+</body>
+</html>
+"#,
+            title = escape(title),
+            generator = GENERATOR,
+            assets = ASSETS_DIR,
+            home = home,
+            nav = nav,
+            body = body,
+        )
+    }
+}
+
+fn year_page_name(year: i32) -> String {
+    format!("{}.html", year)
+}
+
+/// The link to the page with the cover (the first thumbnail).
+fn album(out: &mut String, href: &str, title: &str, summary: &str, items: &[&Item]) {
+    let _ = writeln!(out, "<a class=\"album\" href=\"{}\">", href);
+    if let Some(cover) = items.iter().find_map(|item| item.thumbnail.as_ref()) {
+        let _ = writeln!(out, "<img src=\"{}\" alt=\"\" loading=\"lazy\">", cover);
+    }
+    let _ = writeln!(
+        out,
+        "<span>{}</span><small>{}</small>\n</a>",
+        escape(title),
+        summary
+    );
+}
+
+/// The thumbnails with the hidden metadata shown in the lightbox.
+fn grid<'i>(out: &mut String, items: impl IntoIterator<Item = &'i Item>) {
+    out.push_str("<div class=\"grid\">\n");
+    for item in items {
+        let kind = match item.type_ {
+            MediaType::Photo => "photo",
+            MediaType::Animation => "animation",
+            MediaType::Video => "video",
+        };
+        let href = item.preview.as_ref().unwrap_or(&item.original);
+        let _ = writeln!(
+            out,
+            "<figure class=\"item {kind}\" data-kind=\"{kind}\" data-name=\"{name}\" \
+             data-original=\"{original}\" data-preview=\"{preview}\">",
+            kind = kind,
+            name = escape(&item.name),
+            original = item.original,
+            preview = item.preview.as_deref().unwrap_or_default(),
+        );
+        let _ = write!(out, "<a href=\"{}\">", href);
+        if let Some(thumbnail) = &item.thumbnail {
+            let _ = write!(
+                out,
+                "<img src=\"{}\" alt=\"{}\" loading=\"lazy\">",
+                thumbnail,
+                escape(&item.name)
+            );
+        } else {
+            let _ = write!(out, "<span class=\"missing\">{}</span>", escape(&item.name));
+        }
+        out.push_str("</a>\n<div class=\"meta\" hidden><dl>");
+        for (key, value) in &item.meta {
+            let _ = write!(out, "<dt>{}</dt><dd>{}</dd>", key, escape(value));
+        }
+        let _ = writeln!(
+            out,
+            "</dl><p><a href=\"{}\" target=\"_blank\">Open the original</a></p></div>\n</figure>",
+            item.original
+        );
+    }
+    out.push_str("</div>\n");
+}
+
+/// The relative path from the directory to the file (both should be absolute).
+fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from: Vec<_> = from_dir.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let ups = from[common..]
+        .iter()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .map(|_| Component::ParentDir);
+    ups.chain(to[common..].iter().copied()).collect()
+}
+
+/// Write the file unless it already has the same content.
+fn write_if_changed(path: &Path, content: &str) -> io::Result<bool> {
+    match fs::read(path) {
+        Ok(existing) if existing == content.as_bytes() => Ok(false),
+        _ => fs::write(path, content).map(|()| true),
+    }
+}
+
+/// Whether the page has been generated by the gallery.
+fn is_generated(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|content| content.contains(GENERATOR))
+}
+
+/// Remove the files in the directory matching the predicate.
+fn remove_stale(dir: &Path, is_stale: impl Fn(&str) -> bool) -> io::Result<usize> {
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let stale = path.is_file()
+            && path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(&is_stale);
+        if stale {
+            debug!("Removing stale {}", path.display());
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use image::RgbImage;
+
+    use super::*;
+    use crate::{
+        event::{space, time, EventSources},
+        tags::TagValue,
+        thumbnail::ThumbnailOptions,
+    };
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("foton-gallery-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn shot(dir: &Path, name: &str, time: &str) -> (Media, Event) {
+        let path = dir.join(name);
+        // the different sizes make the different thumbnails
+        let width = u32::try_from(10 + name.len()).unwrap();
+        RgbImage::new(width, 10).save(&path).unwrap();
+        let media = Media {
+            type_: MediaType::Photo,
+            path,
+        };
+        let mut tags = HashMap::new();
+        if !time.is_empty() {
+            let time = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap();
+            tags.insert("exif:DateTimeOriginal".to_owned(), TagValue::DateTime(time));
+        }
+        let sources = EventSources {
+            time: vec![time::InfoSource::Tag {
+                name: "exif:DateTimeOriginal".to_owned(),
+                format: "%Y".into(),
+            }],
+            location: vec![space::InfoSource::Embedded],
+        };
+        let event = media.get_event_with_tags(&sources, Some(&tags));
+        (media, event)
+    }
+
+    #[test]
+    fn remove_matching_files() {
+        let dir = temp_dir("stale");
+        for name in ["a.html", "b.html", "c.txt"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        fs::create_dir(dir.join("d.html")).unwrap();
+
+        let removed = remove_stale(&dir, |name| name.ends_with(".html")).unwrap();
+        assert_eq!(removed, 2);
+        let mut left: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["c.txt", "d.html"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn regenerate() {
+        let dir = temp_dir("site");
+        let photos = dir.join("photos");
+        fs::create_dir(&photos).unwrap();
+        let out_dir = dir.join("site");
+        let mut cache =
+            ThumbnailCache::open(&dir.join("cache"), &ThumbnailOptions::default()).unwrap();
+        let gallery = Gallery::new(
+            &out_dir,
+            &GalleryOptions::default(),
+            &ClusterOptions::default(),
+        );
+
+        let old = shot(&photos, "old-photo.png", "2019-05-01 10:00");
+        let new = shot(&photos, "new.png", "2021-05-01 10:00");
+        let undated = shot(&photos, "undated.png", "");
+        let report = gallery
+            .generate([old, new.clone(), undated.clone()], &mut cache)
+            .unwrap();
+        assert_eq!(report.media, 3);
+        assert_eq!(report.failed, 0);
+        assert_eq!(report.removed, 0);
+        // the index, two years, the undated page and two assets
+        assert_eq!(report.written, 6);
+        for page in [INDEX_PAGE, "2019.html", "2021.html", UNDATED_PAGE] {
+            assert!(is_generated(&out_dir.join(page)), "{}", page);
+        }
+
+        let unchanged = gallery
+            .generate([new.clone(), undated.clone()], &mut cache)
+            .unwrap();
+        // only the index and the 2021 page linking to 2019 are rewritten,
+        // the 2019 page and thumbnails are removed
+        assert_eq!(unchanged.written, 2);
+        assert!(unchanged.removed > 1);
+        assert!(!out_dir.join("2019.html").exists());
+
+        // the pages not generated by the gallery are kept
+        let foreign = out_dir.join("2020.html");
+        fs::write(&foreign, "<html>my own page</html>").unwrap();
+        let report = gallery.generate([new], &mut cache).unwrap();
+        assert!(foreign.exists());
+        assert!(!out_dir.join(UNDATED_PAGE).exists());
+        assert_eq!(report.thumbnails_copied, 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn relative_paths() {
+        assert_eq!(
+            relative_path(
+                Path::new("/home/user/site"),
+                Path::new("/home/user/photos/a.jpg")
+            ),
+            Path::new("../photos/a.jpg")
+        );
+        assert_eq!(
+            relative_path(Path::new("/site"), Path::new("/site/thumbs/a.jpg")),
+            Path::new("thumbs/a.jpg")
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Offline reverse geocoding: finding the nearest populated place
+//! for the coordinates using the [GeoNames](https://www.geonames.org/) dumps.
+//!
+//! The required file is the list of the cities (e.g. `cities1000.txt`),
+//! the names of the regions (`admin1CodesASCII.txt`) and
+//! the countries (`countryInfo.txt`) are optional.
+use std::{
+    collections::HashMap as Map,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    event::space::{distance, Location},
+    AnyError,
+};
+
+/// The length of one degree of latitude in meters.
+const METERS_PER_DEGREE: f64 = 111_195.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Where to find the GeoNames dumps.
+pub struct GeocoderConfig {
+    /// The tab-separated list of populated places, e.g. `cities1000.txt`.
+    pub cities: PathBuf,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// The names of the first-level administrative divisions (`admin1CodesASCII.txt`).
+    pub regions: Option<PathBuf>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// The names of the countries (`countryInfo.txt`).
+    pub countries: Option<PathBuf>,
+
+    #[serde(default = "GeocoderConfig::default_max_distance")]
+    /// Do not name the places farther than the distance (in kilometers) from any city.
+    pub max_distance_km: f64,
+}
+
+impl GeocoderConfig {
+    const fn default_max_distance() -> f64 {
+        50.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The populated place nearest to a location.
+pub struct Place {
+    /// Name of the city (town, village).
+    pub city: String,
+    /// Name of the region (the first-level administrative division).
+    pub region: Option<String>,
+    /// Name of the country (or its code if the names are not loaded).
+    pub country: String,
+    /// The ISO 3166 code of the country, e.g. `UA`.
+    pub country_code: String,
+    /// Distance from the location to the city center in meters.
+    pub distance: f64,
+}
+
+impl fmt::Display for Place {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.city)?;
+        if let Some(region) = &self.region {
+            write!(f, ", {}", region)?;
+        }
+        write!(f, ", {}", self.country)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct City {
+    name: String,
+    latitude: f64,
+    longitude: f64,
+    country_code: String,
+    /// `{country_code}.{admin1_code}`
+    region_code: String,
+}
+
+#[derive(Debug)]
+/// Reverse geocoder with the places loaded into memory.
+pub struct Geocoder {
+    cities: Vec<City>,
+    /// Indices of the cities in the 1°x1° cells.
+    grid: Map<(i32, i32), Vec<usize>>,
+    regions: Map<String, String>,
+    countries: Map<String, String>,
+    max_distance: f64,
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn cell(latitude: f64, longitude: f64) -> (i32, i32) {
+    (latitude.floor() as i32, longitude.floor() as i32)
+}
+
+impl Geocoder {
+    /// Load the GeoNames dumps.
+    pub fn load(config: &GeocoderConfig) -> Result<Self, AnyError> {
+        let read = |path: &Path| {
+            fs::read_to_string(path)
+                .map_err(|err| format!("Failed to read {}: {}", path.display(), err))
+        };
+        let cities = parse_cities(&read(&config.cities)?);
+        if cities.is_empty() {
+            return Err(format!("No cities found in {}", config.cities.display()).into());
+        }
+        let regions = config
+            .regions
+            .as_deref()
+            .map(|path| read(path).map(|content| parse_names(&content, 0, 1)))
+            .transpose()?
+            .unwrap_or_default();
+        let countries = config
+            .countries
+            .as_deref()
+            .map(|path| read(path).map(|content| parse_names(&content, 0, 4)))
+            .transpose()?
+            .unwrap_or_default();
+        info!(
+            "Loaded {} cities, {} regions, {} countries",
+            cities.len(),
+            regions.len(),
+            countries.len()
+        );
+
+        let mut grid: Map<_, Vec<_>> = Map::new();
+        for (i, city) in cities.iter().enumerate() {
+            grid.entry(cell(city.latitude, city.longitude))
+                .or_default()
+                .push(i);
+        }
+        Ok(Self {
+            cities,
+            grid,
+            regions,
+            countries,
+            max_distance: config.max_distance_km * 1000.0,
+        })
+    }
+
+    /// Find the nearest populated place
+    /// (not farther than the [maximum distance][GeocoderConfig::max_distance_km]).
+    pub fn nearest(&self, latitude: f64, longitude: f64) -> Option<Place> {
+        let (lat_cell, lon_cell) = cell(latitude, longitude);
+        // the cells to scan to cover the maximum distance around the point
+        #[allow(clippy::cast_possible_truncation)]
+        let lat_span = (self.max_distance / METERS_PER_DEGREE).ceil() as i32;
+        let farthest_latitude = (latitude.abs() + f64::from(lat_span)).min(90.0);
+        let lon_degree = METERS_PER_DEGREE * farthest_latitude.to_radians().cos();
+        #[allow(clippy::cast_possible_truncation)]
+        let lon_span = if lon_degree > 1.0 {
+            (self.max_distance / lon_degree).ceil().min(180.0) as i32
+        } else {
+            180
+        };
+
+        let mut nearest: Option<(f64, &City)> = None;
+        for lat in lat_cell - lat_span..=lat_cell + lat_span {
+            for lon in lon_cell - lon_span..=lon_cell + lon_span {
+                // wrap around the antimeridian
+                let lon = (lon + 180).rem_euclid(360) - 180;
+                for &i in self.grid.get(&(lat, lon)).into_iter().flatten() {
+                    let city = &self.cities[i];
+                    let dist = distance((latitude, longitude), (city.latitude, city.longitude));
+                    if nearest.is_none_or(|(best, _)| dist < best) {
+                        nearest = Some((dist, city));
+                    }
+                }
+            }
+        }
+
+        let (dist, city) = nearest.filter(|(dist, _)| *dist <= self.max_distance)?;
+        Some(Place {
+            city: city.name.clone(),
+            region: self.regions.get(&city.region_code).cloned(),
+            country: self
+                .countries
+                .get(&city.country_code)
+                .unwrap_or(&city.country_code)
+                .clone(),
+            country_code: city.country_code.clone(),
+            distance: dist,
+        })
+    }
+
+    /// Find the populated place nearest to the location.
+    pub fn locate(&self, location: &Location) -> Option<Place> {
+        self.nearest(location.latitude(), location.longitude())
+    }
+}
+
+/// Parse the GeoNames `cities*.txt` (or `allCountries.txt`) tab-separated file.
+fn parse_cities(content: &str) -> Vec<City> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<_> = line.split('\t').collect();
+            let city = (|| {
+                let name = fields.get(1)?;
+                let latitude: f64 = fields.get(4)?.parse().ok()?;
+                let longitude: f64 = fields.get(5)?.parse().ok()?;
+                let country_code = fields.get(8)?;
+                let admin1 = fields.get(10)?;
+                Some(City {
+                    name: (*name).to_owned(),
+                    latitude,
+                    longitude,
+                    country_code: (*country_code).to_owned(),
+                    region_code: format!("{}.{}", country_code, admin1),
+                })
+            })();
+            if city.is_none() && !line.trim().is_empty() {
+                debug!("Invalid city line: {:?}", line);
+            }
+            city
+        })
+        .collect()
+}
+
+/// Parse the tab-separated list of codes and names skipping the `#` comments.
+fn parse_names(content: &str, code_column: usize, name_column: usize) -> Map<String, String> {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<_> = line.split('\t').collect();
+            let code = fields.get(code_column)?;
+            let name = fields.get(name_column)?;
+            Some(((*code).to_owned(), (*name).to_owned()))
+        })
+        .collect()
+}
@@ -0,0 +1,921 @@
+//! Assigning the locations to the media shot by the cameras without GPS
+//! and storing them either into the XMP sidecars or into the EXIF data.
+use std::{
+    collections::HashMap as Map,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeDelta, TimeZone as _, Utc};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    event::{
+        space::{Location, INFERRED_METHOD},
+        time::{InfoSource as TimeSource, Time},
+        Event,
+    },
+    file_types::Media,
+    sidecar::SidecarKind,
+    tags::TagValue,
+    track::{Track, TrackPoint},
+    AnyError,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+/// How to match the media with the track.
+pub struct GeotagOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The offset (e.g. `+03:00`) of the cameras' clock from UTC
+    /// for the media without the time zone in their metadata.
+    ///
+    /// If not specified, the time zone of the system is used.
+    pub utc_offset: Option<String>,
+
+    /// The longest time (in minutes) between the media and the track points
+    /// (or the [neighboring media][Neighbors]) to take the location from them.
+    pub max_gap_minutes: u32,
+}
+
+impl Default for GeotagOptions {
+    fn default() -> Self {
+        Self {
+            utc_offset: None,
+            max_gap_minutes: 10,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Where to store the found location.
+pub enum GeotagTarget {
+    /// The XMP sidecar (the existing one or `IMG_1234.jpg.xmp`).
+    Sidecar,
+    /// The EXIF GPS tags of a JPEG file itself.
+    Exif,
+}
+
+#[derive(Debug)]
+/// Finds the locations of the media by their time in a [`Track`].
+pub struct Geotagger {
+    track: Track,
+    utc_offset: Option<FixedOffset>,
+    max_gap: TimeDelta,
+}
+
+impl Geotagger {
+    /// Create the geotagger validating the options.
+    pub fn new(track: Track, options: &GeotagOptions) -> Result<Self, AnyError> {
+        let utc_offset = options
+            .utc_offset
+            .as_deref()
+            .map(|offset| {
+                offset
+                    .parse::<FixedOffset>()
+                    .map_err(|err| format!("Invalid UTC offset {:?}: {}", offset, err))
+            })
+            .transpose()?;
+        Ok(Self {
+            track,
+            utc_offset,
+            max_gap: TimeDelta::minutes(i64::from(options.max_gap_minutes)),
+        })
+    }
+
+    /// The track to find the locations in.
+    pub fn track(&self) -> &Track {
+        &self.track
+    }
+
+    /// Convert the local time of the media into UTC.
+    ///
+    /// The offset is taken (in the order of preference) from the Google Takeout
+    /// time source, from the media metadata, from the options or from the system time zone.
+    pub fn utc_time(
+        &self,
+        media: &Media,
+        time: &Time,
+        cached_tags: Option<&Map<String, TagValue>>,
+    ) -> Option<DateTime<Utc>> {
+        let offset = match time.source() {
+            // the time was converted from the UTC timestamp
+            TimeSource::Takeout { utc_offset: None } => FixedOffset::east_opt(0),
+            TimeSource::Takeout {
+                utc_offset: Some(offset),
+            } => offset.parse().ok(),
+            _ => media
+                .get_utc_offset_with_tags(cached_tags)
+                .or(self.utc_offset),
+        };
+        to_utc(time.datetime(), offset)
+    }
+
+    /// Find the position in the track at the time of the media.
+    pub fn locate(
+        &self,
+        media: &Media,
+        time: &Time,
+        cached_tags: Option<&Map<String, TagValue>>,
+    ) -> Option<TrackPoint> {
+        self.locate_at(self.utc_time(media, time, cached_tags)?)
+    }
+
+    /// Find the position in the track at the [UTC time][Self::utc_time].
+    pub fn locate_at(&self, time: DateTime<Utc>) -> Option<TrackPoint> {
+        self.track.locate(time, self.max_gap)
+    }
+}
+
+#[derive(Debug)]
+/// Finds the locations of the media by the other media shot at about the same time,
+/// e.g. a DSLR photo could borrow the location of a phone photo.
+///
+/// The local times of the media are compared, so the cameras are expected
+/// to have their clocks set to the same time zone.
+pub struct Neighbors {
+    /// The media having the measured location ordered by time.
+    located: Vec<(NaiveDateTime, Location, Media)>,
+    max_gap: TimeDelta,
+}
+
+impl Neighbors {
+    /// Collect the media having both the time and the measured
+    /// (not [inferred][Location::is_inferred]) location.
+    pub fn new<'a>(
+        media: impl IntoIterator<Item = (&'a Media, &'a Event)>,
+        options: &GeotagOptions,
+    ) -> Self {
+        let mut located: Vec<_> = media
+            .into_iter()
+            .filter_map(|(media, event)| {
+                let location = event.location().filter(|loc| !loc.is_inferred())?;
+                Some((event.time()?.datetime(), location, media.clone()))
+            })
+            .collect();
+        located.sort_by_key(|(time, _, _)| *time);
+        Self {
+            located,
+            max_gap: TimeDelta::minutes(i64::from(options.max_gap_minutes)),
+        }
+    }
+
+    /// Find the other media shot the closest in time (not farther than the maximum gap)
+    /// and borrow its location [marked as inferred][Location::is_inferred].
+    pub fn locate(&self, media: &Media, time: &Time) -> Option<(&Media, Location)> {
+        let time = time.datetime();
+        let index = self.located.partition_point(|(t, _, _)| *t < time);
+        // the media itself is located if its location is going to be overwritten
+        let is_other = |(_, _, other): &&(_, _, Media)| other.path() != media.path();
+        let before = self.located[..index].iter().rev().find(is_other);
+        let after = self.located[index..].iter().find(is_other);
+        [before, after]
+            .into_iter()
+            .flatten()
+            .filter(|(t, _, _)| (*t - time).abs() <= self.max_gap)
+            .min_by_key(|(t, _, _)| (*t - time).abs())
+            .map(|(_, location, other)| (other, location.into_inferred()))
+    }
+}
+
+fn to_utc(local: NaiveDateTime, offset: Option<FixedOffset>) -> Option<DateTime<Utc>> {
+    match offset {
+        Some(offset) => offset
+            .from_local_datetime(&local)
+            .single()
+            .map(|t| t.to_utc()),
+        // the ambiguous time during the DST transition is rare enough to take any variant
+        None => Local
+            .from_local_datetime(&local)
+            .earliest()
+            .map(|t| t.to_utc()),
+    }
+}
+
+impl Media {
+    /// Store the location into the media metadata.
+    ///
+    /// Only the JPEG files support the [EXIF target][GeotagTarget::Exif],
+    /// the others get the sidecar anyway.
+    /// The [inferred][Location::is_inferred] locations are marked
+    /// with the `GPSProcessingMethod` tag to be read back as such.
+    /// Returns the path of the written file.
+    pub fn write_location(
+        &self,
+        location: &Location,
+        target: GeotagTarget,
+    ) -> Result<PathBuf, AnyError> {
+        let gps = Gps {
+            latitude: location.latitude(),
+            longitude: location.longitude(),
+            altitude: location.altitude(),
+            inferred: location.is_inferred(),
+        };
+        match target {
+            GeotagTarget::Sidecar => {
+                let existing = self
+                    .sidecars()
+                    .into_iter()
+                    .rev()
+                    .find(|sidecar| sidecar.kind() == SidecarKind::Xmp);
+                let path = match existing {
+                    Some(sidecar) => {
+                        let content = fs::read_to_string(sidecar.path())?;
+                        let updated = insert_xmp_description(&content, &gps.xmp_description())
+                            .ok_or_else(|| {
+                                format!("Unsupported XMP sidecar: {}", sidecar.path().display())
+                            })?;
+                        write_replacing(sidecar.path(), updated.as_bytes())?;
+                        sidecar.path().to_path_buf()
+                    }
+                    None => {
+                        let mut name = self.path().as_os_str().to_owned();
+                        name.push(".xmp");
+                        let path = PathBuf::from(name);
+                        fs::write(&path, gps.xmp_packet())?;
+                        path
+                    }
+                };
+                Ok(path)
+            }
+            GeotagTarget::Exif => {
+                let jpeg = fs::read(self.path())?;
+                if !jpeg.starts_with(&[0xFF, JPEG_SOI]) {
+                    debug!("Not a JPEG file, writing the sidecar for {}", self);
+                    return self.write_location(location, GeotagTarget::Sidecar);
+                }
+                let updated = write_jpeg_gps(&jpeg, &gps)?;
+                write_replacing(self.path(), &updated)?;
+                Ok(self.path().to_path_buf())
+            }
+        }
+    }
+}
+
+/// Write the file through a temporary one, so it is never left half-written.
+fn write_replacing(path: &Path, content: &[u8]) -> Result<(), AnyError> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("Not a file: {}", path.display()))?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(name);
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+    fs::write(&tmp, content)?;
+    fs::set_permissions(&tmp, fs::metadata(path)?.permissions())?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Gps {
+    latitude: f64,
+    longitude: f64,
+    altitude: Option<f64>,
+    inferred: bool,
+}
+
+/// The precision of the GPS altitude and the seconds of the coordinates.
+const GPS_DENOMINATOR: u32 = 1000;
+
+impl Gps {
+    /// The `GPSProcessingMethod` tag value.
+    fn method(&self) -> &'static str {
+        if self.inferred {
+            INFERRED_METHOD
+        } else {
+            "GPS"
+        }
+    }
+
+    fn latitude_ref(&self) -> char {
+        if self.latitude < 0.0 {
+            'S'
+        } else {
+            'N'
+        }
+    }
+
+    fn longitude_ref(&self) -> char {
+        if self.longitude < 0.0 {
+            'W'
+        } else {
+            'E'
+        }
+    }
+
+    /// The XMP coordinate `DDD,MM.mmmmmmk`.
+    fn xmp_coordinate(degrees: f64, direction: char) -> String {
+        let degrees = degrees.abs();
+        let whole = degrees.trunc();
+        format!("{},{:.6}{}", whole, (degrees - whole) * 60.0, direction)
+    }
+
+    fn xmp_description(&self) -> String {
+        let mut properties = vec![
+            ("GPSVersionID", "2.3.0.0".to_owned()),
+            (
+                "GPSLatitude",
+                Self::xmp_coordinate(self.latitude, self.latitude_ref()),
+            ),
+            (
+                "GPSLongitude",
+                Self::xmp_coordinate(self.longitude, self.longitude_ref()),
+            ),
+        ];
+        if let Some(altitude) = self.altitude {
+            let (numerator, _) = rational(altitude.abs());
+            properties.push(("GPSAltitude", format!("{}/{}", numerator, GPS_DENOMINATOR)));
+            properties.push(("GPSAltitudeRef", u8::from(altitude < 0.0).to_string()));
+        }
+        properties.push(("GPSProcessingMethod", self.method().to_owned()));
+
+        let mut description = String::from(
+            "  <rdf:Description rdf:about=\"\"\n    xmlns:exif=\"http://ns.adobe.com/exif/1.0/\"",
+        );
+        for (name, value) in properties {
+            description.push_str(&format!("\n    exif:{}=\"{}\"", name, value));
+        }
+        description.push_str("/>\n");
+        description
+    }
+
+    fn xmp_packet(&self) -> String {
+        format!(
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n \
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+             {} </rdf:RDF>\n\
+             </x:xmpmeta>\n",
+            self.xmp_description()
+        )
+    }
+}
+
+/// Add the description to the existing XMP packet.
+///
+/// The later descriptions override the properties of the earlier ones.
+fn insert_xmp_description(content: &str, description: &str) -> Option<String> {
+    let end = content.rfind("</rdf:RDF>")?;
+    Some(format!(
+        "{}{}{}",
+        &content[..end],
+        description,
+        &content[end..]
+    ))
+}
+
+/// Split the non-negative number into the rational with the [fixed denominator][GPS_DENOMINATOR].
+fn rational(value: f64) -> (u32, u32) {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let numerator = (value * f64::from(GPS_DENOMINATOR)).round() as u32;
+    (numerator, GPS_DENOMINATOR)
+}
+
+/// Split the coordinate into the degrees, minutes and seconds rationals.
+fn dms(degrees: f64) -> [(u32, u32); 3] {
+    let degrees = degrees.abs();
+    let minutes = degrees.fract() * 60.0;
+    let seconds = minutes.fract() * 60.0;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let whole = |value: f64| (value.trunc() as u32, 1);
+    [whole(degrees), whole(minutes), rational(seconds)]
+}
+
+const JPEG_SOI: u8 = 0xD8;
+const JPEG_SOS: u8 = 0xDA;
+const JPEG_EOI: u8 = 0xD9;
+const JPEG_APP0: u8 = 0xE0;
+const JPEG_APP1: u8 = 0xE1;
+const EXIF_SIGNATURE: &[u8] = b"Exif\0\0";
+
+/// Replace (or add) the `APP1` EXIF segment of a JPEG file
+/// with the one containing the GPS tags.
+fn write_jpeg_gps(jpeg: &[u8], gps: &Gps) -> Result<Vec<u8>, AnyError> {
+    if jpeg.get(..2) != Some(&[0xFF, JPEG_SOI]) {
+        return Err("Not a JPEG file".into());
+    }
+
+    // the EXIF segment to replace or the position to insert a new one
+    let mut exif = None;
+    let mut insert_at = 2;
+    let mut pos = 2;
+    while let (Some(0xFF), Some(&marker)) = (jpeg.get(pos), jpeg.get(pos + 1)) {
+        if marker == JPEG_SOS || marker == JPEG_EOI {
+            break;
+        }
+        let len = jpeg
+            .get(pos + 2..pos + 4)
+            .map(|len| usize::from(u16::from_be_bytes([len[0], len[1]])))
+            .ok_or("Truncated JPEG segment")?;
+        let end = pos + 2 + len;
+        let data = jpeg.get(pos + 4..end).ok_or("Truncated JPEG segment")?;
+        if marker == JPEG_APP1 && data.starts_with(EXIF_SIGNATURE) {
+            exif = Some((pos, end, &data[EXIF_SIGNATURE.len()..]));
+            break;
+        }
+        if marker == JPEG_APP0 && pos == 2 {
+            // the JFIF segment should stay the first one
+            insert_at = end;
+        }
+        pos = end;
+    }
+
+    let (start, end, tiff) = match exif {
+        Some((start, end, tiff)) => (start, end, add_gps_ifd(tiff, gps)?),
+        None => (insert_at, insert_at, add_gps_ifd(&EMPTY_TIFF, gps)?),
+    };
+    let len = u16::try_from(2 + EXIF_SIGNATURE.len() + tiff.len())
+        .map_err(|_| "The EXIF data does not fit into the JPEG segment")?;
+
+    let mut result = Vec::with_capacity(jpeg.len() + tiff.len());
+    result.extend_from_slice(&jpeg[..start]);
+    result.extend_from_slice(&[0xFF, JPEG_APP1]);
+    result.extend_from_slice(&len.to_be_bytes());
+    result.extend_from_slice(EXIF_SIGNATURE);
+    result.extend_from_slice(&tiff);
+    result.extend_from_slice(&jpeg[end..]);
+    Ok(result)
+}
+
+/// Little-endian TIFF header with the empty IFD0.
+const EMPTY_TIFF: [u8; 14] = [
+    b'I', b'I', 42, 0, 8, 0, 0, 0, // header
+    0, 0, // no entries
+    0, 0, 0, 0, // no next IFD
+];
+
+const TAG_GPS_IFD: u16 = 0x8825;
+const TYPE_BYTE: u16 = 1;
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+const TYPE_UNDEFINED: u16 = 7;
+
+/// The character code of the EXIF text stored as the undefined bytes.
+const EXIF_ASCII_PREFIX: &[u8] = b"ASCII\0\0\0";
+
+#[derive(Debug, Copy, Clone)]
+struct ByteOrder {
+    little_endian: bool,
+}
+
+impl ByteOrder {
+    fn u16(self, bytes: &[u8], pos: usize) -> Option<u16> {
+        let bytes = bytes.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(self, bytes: &[u8], pos: usize) -> Option<u32> {
+        let bytes = bytes.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn put_u16(self, out: &mut Vec<u8>, value: u16) {
+        out.extend_from_slice(&if self.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        });
+    }
+
+    fn put_u32(self, out: &mut Vec<u8>, value: u32) {
+        out.extend_from_slice(&if self.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        });
+    }
+}
+
+/// The IFD entry with either the inline value (up to 4 bytes)
+/// or the data to place after the IFD.
+#[derive(Debug)]
+struct NewEntry {
+    tag: u16,
+    type_: u16,
+    count: u32,
+    data: Vec<u8>,
+}
+
+/// Append the GPS IFD (and the copy of IFD0 pointing to it) to the TIFF structure.
+///
+/// Nothing is moved inside the original data, so all the offsets
+/// (including the ones inside the MakerNote) stay valid.
+/// The original IFD0 and GPS IFD are left unreferenced,
+/// while the ones [appended before][appended_gps_start] are overwritten,
+/// so the data does not grow on every run.
+fn add_gps_ifd(tiff: &[u8], gps: &Gps) -> Result<Vec<u8>, AnyError> {
+    let order = match tiff.get(..2) {
+        Some(b"II") => ByteOrder {
+            little_endian: true,
+        },
+        Some(b"MM") => ByteOrder {
+            little_endian: false,
+        },
+        _ => return Err("Invalid TIFF header".into()),
+    };
+    let invalid = || "Invalid IFD0";
+    let ifd0 = order.u32(tiff, 4).ok_or_else(invalid)? as usize;
+    let count = usize::from(order.u16(tiff, ifd0).ok_or_else(invalid)?);
+    let entries_end = ifd0 + 2 + count * 12;
+    let next_ifd = order.u32(tiff, entries_end).ok_or_else(invalid)?;
+    let (gps_entry, mut entries): (Vec<_>, Vec<_>) = tiff[ifd0 + 2..entries_end]
+        .chunks_exact(12)
+        .map(<[u8]>::to_vec)
+        .partition(|entry| order.u16(entry, 0) == Some(TAG_GPS_IFD));
+
+    let mut out = tiff.to_vec();
+    if let Some(start) = appended_gps_start(tiff, order, ifd0, gps_entry.first(), &entries) {
+        debug!("Overwriting the GPS IFD appended at {}", start);
+        out.truncate(start);
+    }
+    let align = |out: &mut Vec<u8>| {
+        if out.len() % 2 == 1 {
+            out.push(0);
+        }
+    };
+
+    // the GPS IFD
+    align(&mut out);
+    let gps_offset = offset(&out)?;
+    let rationals = |values: &[(u32, u32)]| {
+        let mut data = vec![];
+        for (numerator, denominator) in values {
+            order.put_u32(&mut data, *numerator);
+            order.put_u32(&mut data, *denominator);
+        }
+        data
+    };
+    let ascii = |c: char| vec![c as u8, 0];
+    let mut gps_entries = vec![
+        NewEntry {
+            tag: 0x0000,
+            type_: TYPE_BYTE,
+            count: 4,
+            data: vec![2, 3, 0, 0],
+        },
+        NewEntry {
+            tag: 0x0001,
+            type_: TYPE_ASCII,
+            count: 2,
+            data: ascii(gps.latitude_ref()),
+        },
+        NewEntry {
+            tag: 0x0002,
+            type_: TYPE_RATIONAL,
+            count: 3,
+            data: rationals(&dms(gps.latitude)),
+        },
+        NewEntry {
+            tag: 0x0003,
+            type_: TYPE_ASCII,
+            count: 2,
+            data: ascii(gps.longitude_ref()),
+        },
+        NewEntry {
+            tag: 0x0004,
+            type_: TYPE_RATIONAL,
+            count: 3,
+            data: rationals(&dms(gps.longitude)),
+        },
+    ];
+    if let Some(altitude) = gps.altitude {
+        gps_entries.push(NewEntry {
+            tag: 0x0005,
+            type_: TYPE_BYTE,
+            count: 1,
+            data: vec![u8::from(altitude < 0.0)],
+        });
+        gps_entries.push(NewEntry {
+            tag: 0x0006,
+            type_: TYPE_RATIONAL,
+            count: 1,
+            data: rationals(&[rational(altitude.abs())]),
+        });
+    }
+    let method = [EXIF_ASCII_PREFIX, gps.method().as_bytes()].concat();
+    gps_entries.push(NewEntry {
+        tag: 0x001B,
+        type_: TYPE_UNDEFINED,
+        count: u32::try_from(method.len())?,
+        data: method,
+    });
+    write_ifd(&mut out, order, gps_offset, &gps_entries)?;
+
+    // the new IFD0
+    align(&mut out);
+    let new_ifd0 = offset(&out)?;
+    let mut gps_entry = vec![];
+    order.put_u16(&mut gps_entry, TAG_GPS_IFD);
+    order.put_u16(&mut gps_entry, TYPE_LONG);
+    order.put_u32(&mut gps_entry, 1);
+    order.put_u32(&mut gps_entry, gps_offset);
+    entries.push(gps_entry);
+    entries.sort_by_key(|entry| order.u16(entry, 0));
+    order.put_u16(&mut out, u16::try_from(entries.len())?);
+    for entry in &entries {
+        out.extend_from_slice(entry);
+    }
+    order.put_u32(&mut out, next_ifd);
+
+    let mut header = vec![];
+    order.put_u32(&mut header, new_ifd0);
+    out[4..8].copy_from_slice(&header);
+    Ok(out)
+}
+
+/// The start of the GPS IFD and IFD0 previously appended by [`add_gps_ifd`].
+///
+/// They are recognized by their layout: the IFD0 is the last structure of the data,
+/// the GPS IFD with its values immediately precedes it,
+/// and no other IFD0 value is stored after the GPS IFD.
+fn appended_gps_start(
+    tiff: &[u8],
+    order: ByteOrder,
+    ifd0: usize,
+    gps_entry: Option<&Vec<u8>>,
+    other_entries: &[Vec<u8>],
+) -> Option<usize> {
+    let ifd0_end = ifd0 + 2 + 12 * other_entries.len() + 12 + 4;
+    if ifd0_end != tiff.len() {
+        return None;
+    }
+    let gps_start = order.u32(gps_entry?, 8)? as usize;
+    let count = usize::from(order.u16(tiff, gps_start)?);
+    let mut gps_end = gps_start + 2 + 12 * count + 4;
+    for entry in tiff
+        .get(gps_start + 2..gps_start + 2 + 12 * count)?
+        .chunks_exact(12)
+    {
+        if let Some((offset, len)) = external_value(entry, order) {
+            gps_end = gps_end.max(offset + len);
+        }
+    }
+    // the IFD0 is aligned to the word boundary
+    if gps_end != ifd0 && gps_end + 1 != ifd0 {
+        return None;
+    }
+    let keeps_values = other_entries
+        .iter()
+        .all(|entry| external_value(entry, order).is_none_or(|(offset, _)| offset < gps_start));
+    keeps_values.then_some(gps_start)
+}
+
+/// The offset and the length of the IFD entry value stored outside the entry.
+fn external_value(entry: &[u8], order: ByteOrder) -> Option<(usize, usize)> {
+    let size = match order.u16(entry, 2)? {
+        // BYTE, ASCII, SBYTE, UNDEFINED
+        1 | 2 | 6 | 7 => 1,
+        // SHORT, SSHORT
+        3 | 8 => 2,
+        // LONG, SLONG, FLOAT, IFD
+        4 | 9 | 11 | 13 => 4,
+        // RATIONAL, SRATIONAL, DOUBLE
+        5 | 10 | 12 => 8,
+        _ => return None,
+    };
+    let len = size * order.u32(entry, 4)? as usize;
+    if len <= 4 {
+        return None;
+    }
+    Some((order.u32(entry, 8)? as usize, len))
+}
+
+fn offset(out: &[u8]) -> Result<u32, AnyError> {
+    Ok(u32::try_from(out.len())?)
+}
+
+/// Write the IFD without the next IFD at the end of `out` (located at the `start` offset).
+fn write_ifd(
+    out: &mut Vec<u8>,
+    order: ByteOrder,
+    start: u32,
+    entries: &[NewEntry],
+) -> Result<(), AnyError> {
+    let mut data_offset = start + 2 + 12 * u32::try_from(entries.len())? + 4;
+    let mut data = vec![];
+    order.put_u16(out, u16::try_from(entries.len())?);
+    for entry in entries {
+        order.put_u16(out, entry.tag);
+        order.put_u16(out, entry.type_);
+        order.put_u32(out, entry.count);
+        if entry.data.len() <= 4 {
+            let mut inline = entry.data.clone();
+            inline.resize(4, 0);
+            out.extend_from_slice(&inline);
+        } else {
+            order.put_u32(out, data_offset);
+            data.extend_from_slice(&entry.data);
+            data_offset += u32::try_from(entry.data.len())?;
+        }
+    }
+    order.put_u32(out, 0);
+    out.extend_from_slice(&data);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use exif::{In, Tag};
+    use image::{codecs::jpeg::JpegEncoder, RgbImage};
+
+    use super::*;
+    use crate::{event::space::InfoSource, file_types::MediaType, tags::read_exif};
+
+    const MAKER_NOTE: &[u8] = b"FOTON MAKER NOTE \x00\x01\x02\x03 with the data";
+
+    fn jpeg() -> Vec<u8> {
+        let mut jpeg = vec![];
+        let image = RgbImage::from_pixel(16, 16, image::Rgb([200, 100, 50]));
+        image
+            .write_with_encoder(JpegEncoder::new(&mut Cursor::new(&mut jpeg)))
+            .unwrap();
+        jpeg
+    }
+
+    /// The TIFF with the `Make` in IFD0 and the `MakerNote` in the EXIF IFD.
+    fn tiff_with_maker_note(order: ByteOrder) -> Vec<u8> {
+        let entry = |out: &mut Vec<u8>, tag, type_, count, value| {
+            order.put_u16(out, tag);
+            order.put_u16(out, type_);
+            order.put_u32(out, count);
+            order.put_u32(out, value);
+        };
+        let make = b"Foton\0";
+        // header (8) + IFD0 with 2 entries (2 + 24 + 4) + EXIF IFD with 1 entry (2 + 12 + 4)
+        let exif_ifd = 8 + 30;
+        let make_offset = exif_ifd + 18;
+        let maker_note_offset = make_offset + make.len();
+
+        let mut tiff = if order.little_endian {
+            b"II".to_vec()
+        } else {
+            b"MM".to_vec()
+        };
+        order.put_u16(&mut tiff, 42);
+        order.put_u32(&mut tiff, 8);
+        order.put_u16(&mut tiff, 2);
+        entry(&mut tiff, 0x010F, TYPE_ASCII, 6, make_offset as u32);
+        entry(&mut tiff, 0x8769, TYPE_LONG, 1, exif_ifd as u32);
+        order.put_u32(&mut tiff, 0);
+        order.put_u16(&mut tiff, 1);
+        entry(
+            &mut tiff,
+            0x927C,
+            TYPE_UNDEFINED,
+            MAKER_NOTE.len() as u32,
+            maker_note_offset as u32,
+        );
+        order.put_u32(&mut tiff, 0);
+        tiff.extend_from_slice(make);
+        tiff.extend_from_slice(MAKER_NOTE);
+        tiff
+    }
+
+    fn with_exif(jpeg: &[u8], tiff: &[u8]) -> Vec<u8> {
+        let len = u16::try_from(2 + EXIF_SIGNATURE.len() + tiff.len()).unwrap();
+        let mut result = jpeg[..2].to_vec();
+        result.extend_from_slice(&[0xFF, JPEG_APP1]);
+        result.extend_from_slice(&len.to_be_bytes());
+        result.extend_from_slice(EXIF_SIGNATURE);
+        result.extend_from_slice(tiff);
+        result.extend_from_slice(&jpeg[2..]);
+        result
+    }
+
+    fn exif_len(jpeg: &[u8]) -> usize {
+        let pos = jpeg
+            .windows(2 + 2 + EXIF_SIGNATURE.len())
+            .position(|w| w[..2] == [0xFF, JPEG_APP1] && w[4..] == *EXIF_SIGNATURE)
+            .unwrap();
+        usize::from(u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]))
+    }
+
+    fn save(name: &str, content: &[u8]) -> Media {
+        let path =
+            std::env::temp_dir().join(format!("foton-geotag-{}-{}.jpg", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        Media {
+            type_: MediaType::Photo,
+            path,
+        }
+    }
+
+    fn gps() -> Gps {
+        Gps {
+            latitude: 50.4501,
+            longitude: -30.5234,
+            altitude: Some(-12.5),
+            inferred: false,
+        }
+    }
+
+    fn assert_location(media: &Media, gps: &Gps) {
+        let (latitude, longitude, altitude) = media.exif_coordinates(None).unwrap();
+        assert!((latitude - gps.latitude).abs() < 1e-6, "{}", latitude);
+        assert!((longitude - gps.longitude).abs() < 1e-6, "{}", longitude);
+        assert!((altitude.unwrap() - gps.altitude.unwrap()).abs() < 1e-6);
+
+        let location = media
+            .get_location_from_source(&InfoSource::Embedded, None)
+            .unwrap();
+        assert_eq!(location.is_inferred(), gps.inferred);
+    }
+
+    fn round_trip(name: &str, original: &[u8]) -> Media {
+        let gps = gps();
+        let written = write_jpeg_gps(original, &gps).unwrap();
+        let media = save(name, &written);
+        assert_location(&media, &gps);
+        assert!(image::load_from_memory(&written).is_ok());
+        media
+    }
+
+    #[test]
+    fn gps_into_jpeg_without_exif() {
+        let media = round_trip("no-exif", &jpeg());
+        let exif = read_exif(media.path()).unwrap();
+        assert!(exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_some());
+        fs::remove_file(media.path()).unwrap();
+    }
+
+    fn keeps_maker_note(order: ByteOrder, name: &str) {
+        let original = with_exif(&jpeg(), &tiff_with_maker_note(order));
+        let media = round_trip(name, &original);
+
+        let exif = read_exif(media.path()).unwrap();
+        assert_eq!(exif.little_endian(), order.little_endian);
+        let make = exif.get_field(Tag::Make, In::PRIMARY).unwrap();
+        assert_eq!(make.display_value().to_string(), "\"Foton\"");
+        let maker_note = exif.get_field(Tag::MakerNote, In::PRIMARY).unwrap();
+        let exif::Value::Undefined(bytes, _) = &maker_note.value else {
+            panic!("{:?}", maker_note.value);
+        };
+        assert_eq!(bytes, MAKER_NOTE);
+        fs::remove_file(media.path()).unwrap();
+    }
+
+    #[test]
+    fn gps_into_little_endian_exif_with_maker_note() {
+        keeps_maker_note(
+            ByteOrder {
+                little_endian: true,
+            },
+            "ii",
+        );
+    }
+
+    #[test]
+    fn gps_into_big_endian_exif_with_maker_note() {
+        keeps_maker_note(
+            ByteOrder {
+                little_endian: false,
+            },
+            "mm",
+        );
+    }
+
+    #[test]
+    fn rewriting_gps_does_not_grow_exif() {
+        let order = ByteOrder {
+            little_endian: false,
+        };
+        let original = with_exif(&jpeg(), &tiff_with_maker_note(order));
+        let first = write_jpeg_gps(&original, &gps()).unwrap();
+        let moved = Gps {
+            latitude: -33.8688,
+            longitude: 151.2093,
+            altitude: None,
+            inferred: true,
+        };
+        let mut written = write_jpeg_gps(&first, &moved).unwrap();
+        for _ in 0..10 {
+            written = write_jpeg_gps(&written, &moved).unwrap();
+        }
+        // the second write drops the altitude
+        assert!(exif_len(&written) < exif_len(&first));
+        assert_eq!(written, write_jpeg_gps(&written, &moved).unwrap());
+
+        let media = save("rewrite", &written);
+        let (latitude, longitude, altitude) = media.exif_coordinates(None).unwrap();
+        assert!((latitude - moved.latitude).abs() < 1e-6);
+        assert!((longitude - moved.longitude).abs() < 1e-6);
+        assert_eq!(altitude, None);
+        let location = media
+            .get_location_from_source(&InfoSource::Embedded, None)
+            .unwrap();
+        assert!(location.is_inferred());
+        fs::remove_file(media.path()).unwrap();
+    }
+}
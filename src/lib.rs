@@ -6,19 +6,56 @@
 )]
 #![forbid(unsafe_code)]
 
+mod contact_sheet;
 mod dir;
 mod event;
+mod export;
 mod file_types;
+mod filter;
+mod gallery;
+mod geocode;
+mod geotag;
+mod live;
+mod organize;
+mod sidecar;
 mod tags;
+mod technical;
+mod thumbnail;
+mod track;
+mod verify;
+mod video;
 
 pub use self::{
-    dir::Library,
+    contact_sheet::{
+        group_for_sheets, ContactSheetOptions, ContactSheets, SheetGroup, SheetGrouping,
+    },
+    dir::{IgnoreRules, Library, WalkError, WalkErrorKind, WalkOptions, IGNORE_FILE_NAME},
     event::{
+        cluster::{cluster_events, ClusterOptions, EventCluster, Home, TimelineEntry, Trip},
+        space::{InfoSource as LocationSource, Location},
         time::{Format as TimeFormat, InfoSource as TimeSource, Time},
-        Event,
+        Event, EventSources,
+    },
+    export::{file_url, MapExport, MapFormat, MapPoint, MapTrack, TrackGrouping},
+    file_types::{ExtensionMap, ExtensionsOverride, Media, MediaType},
+    filter::{Filter, FilterError},
+    gallery::{Gallery, GalleryOptions, GalleryReport},
+    geocode::{Geocoder, GeocoderConfig, Place},
+    geotag::{GeotagOptions, GeotagTarget, Geotagger, Neighbors},
+    live::{
+        embedded_video_path, group_live_photos, EmbeddedVideo, LivePhoto, MediaItem, MotionVideo,
+    },
+    organize::{plan_organize, EventNames, PathTemplate, TemplateValues},
+    sidecar::{Sidecar, SidecarKind},
+    tags::{
+        exif_tag_id, find_exif_tag, find_tag, get_image_tags, get_iptc_tags, known_tags,
+        lookup_exif_tag, lookup_number, lookup_tag, tag_description, KnownTag, TagValue,
     },
-    file_types::MediaType,
-    tags::{find_exif_tag, get_image_tags},
+    technical::{Orientation, Technical},
+    thumbnail::{ThumbnailCache, ThumbnailFormat, ThumbnailOptions, Thumbnails},
+    track::{Track, TrackPoint},
+    verify::{Defect, Integrity},
+    video::{StreamInfo, StreamKind, VideoInfo},
 };
 
 type AnyError = Box<dyn std::error::Error + Send + Sync>;
@@ -0,0 +1,574 @@
+//! Live Photos (Apple) and Motion Photos (Google, Samsung):
+//! still images accompanied by a short video clip.
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::File,
+    io::{self, Read as _, Seek as _, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info};
+
+use crate::{
+    file_types::{Media, MediaType},
+    sidecar::{transfer_files, transfer_targets},
+    tags::{decode_maker_note, jpeg_exif_and_xmp, read_exif},
+};
+
+/// The QuickTime metadata key holding the Live Photo identifier of a video.
+const QUICKTIME_CONTENT_ID: &str = "ffmpeg:com.apple.quicktime.content.identifier";
+
+/// The Apple MakerNote tag holding the Live Photo identifier of an image.
+const APPLE_CONTENT_ID: &str = "apple:ContentIdentifier";
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// Location of a video clip appended to the end of an image file.
+pub struct EmbeddedVideo {
+    /// Offset of the video from the start of the file (in bytes).
+    pub offset: u64,
+    /// Size of the video (in bytes).
+    pub length: u64,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// The video part of a [`LivePhoto`].
+pub enum MotionVideo {
+    /// Separate video file (Apple Live Photo).
+    Paired(Media),
+    /// Video embedded into the image file itself (Google Motion Photo).
+    Embedded(EmbeddedVideo),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// A still image accompanied by a short video clip.
+pub struct LivePhoto {
+    photo: Media,
+    video: MotionVideo,
+}
+
+impl LivePhoto {
+    /// The still image.
+    pub fn photo(&self) -> &Media {
+        &self.photo
+    }
+
+    /// The video part.
+    pub fn video(&self) -> &MotionVideo {
+        &self.video
+    }
+
+    /// All the files constituting the [`LivePhoto`]
+    /// including their [sidecars][Media::sidecars].
+    ///
+    /// They should always be moved or copied together.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let mut paths = self.photo.files();
+        if let MotionVideo::Paired(video) = &self.video {
+            // the sidecars named by the common stem (e.g. `IMG_1234.AAE`) are shared
+            for path in video.files() {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+        paths
+    }
+
+    /// Save the video embedded into the image as a separate file.
+    ///
+    /// Returns `Ok(false)` if the video is already a separate file.
+    pub fn extract_video(&self, dest: &Path) -> io::Result<bool> {
+        match &self.video {
+            MotionVideo::Paired(_) => Ok(false),
+            MotionVideo::Embedded(embedded) => {
+                embedded.extract(self.photo.path(), dest)?;
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl fmt::Display for LivePhoto {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.video {
+            MotionVideo::Paired(video) => write!(
+                f,
+                "[LIVE]{} + {}",
+                self.photo.path().display(),
+                video.path().display()
+            ),
+            MotionVideo::Embedded(embedded) => write!(
+                f,
+                "[MOTION]{} ({} bytes of video)",
+                self.photo.path().display(),
+                embedded.length
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// A logical item of a collection: either a single file
+/// or the multiple files which should be treated as a whole.
+pub enum MediaItem {
+    /// Just a regular photo or video.
+    Single(Media),
+    /// Live Photo or Motion Photo.
+    Live(LivePhoto),
+}
+
+impl MediaItem {
+    /// The main file of the item.
+    pub fn main(&self) -> &Media {
+        match self {
+            Self::Single(media) => media,
+            Self::Live(live) => live.photo(),
+        }
+    }
+
+    /// All the media constituting the item.
+    pub fn media(&self) -> Vec<&Media> {
+        match self {
+            Self::Single(media) => vec![media],
+            Self::Live(live) => match &live.video {
+                MotionVideo::Paired(video) => vec![&live.photo, video],
+                MotionVideo::Embedded(_) => vec![&live.photo],
+            },
+        }
+    }
+
+    /// All the files constituting the item
+    /// including their [sidecars][Media::sidecars].
+    pub fn paths(&self) -> Vec<PathBuf> {
+        match self {
+            Self::Single(media) => media.files(),
+            Self::Live(live) => live.paths(),
+        }
+    }
+
+    /// Check that the item can be [copied][Self::copy_to]
+    /// or [moved][Self::move_to] into the directory.
+    ///
+    /// Fails if any of its files already exists in the directory.
+    pub fn check_targets(&self, dir: &Path, move_: bool) -> io::Result<()> {
+        transfer_targets(&self.paths(), dir, move_).map(drop)
+    }
+
+    /// Copy all the [files][Self::paths] of the item into the directory.
+    ///
+    /// Nothing is copied if any of the files already exists in the directory.
+    pub fn copy_to(&self, dir: &Path) -> io::Result<()> {
+        transfer_files(&self.paths(), dir, false)
+    }
+
+    /// Move all the [files][Self::paths] of the item into the directory.
+    ///
+    /// Nothing is moved if any of the files already exists in the directory.
+    pub fn move_to(&self, dir: &Path) -> io::Result<()> {
+        transfer_files(&self.paths(), dir, true)
+    }
+}
+
+impl fmt::Display for MediaItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Single(media) => media.fmt(f),
+            Self::Live(live) => live.fmt(f),
+        }
+    }
+}
+
+impl EmbeddedVideo {
+    fn extract(&self, image: &Path, dest: &Path) -> io::Result<()> {
+        let mut file = File::open(image)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut video = file.take(self.length);
+
+        let mut header = [0; 8];
+        video.read_exact(&mut header)?;
+        if &header[4..] != b"ftyp" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the embedded data is not an MP4 video",
+            ));
+        }
+
+        let mut out = File::create(dest)?;
+        io::Write::write_all(&mut out, &header)?;
+        io::copy(&mut video, &mut out)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+/// What makes a media a part of a [`LivePhoto`].
+struct LiveMarks {
+    embedded: Option<EmbeddedVideo>,
+    content_id: Option<String>,
+}
+
+impl Media {
+    /// Identifier shared by the image and the video of an Apple Live Photo.
+    pub fn content_identifier(&self) -> Option<String> {
+        self.live_marks().content_id
+    }
+
+    /// Find the video embedded into a Google Motion Photo (JPEG only).
+    pub fn embedded_video(&self) -> Option<EmbeddedVideo> {
+        self.live_marks().embedded
+    }
+
+    /// Read the metadata needed to detect a Live Photo or a Motion Photo.
+    ///
+    /// Every file is read only once: for JPEG images
+    /// both the EXIF and the XMP are found in a single pass.
+    fn live_marks(&self) -> LiveMarks {
+        match self.type_ {
+            MediaType::Photo => {
+                let (exif, xmp) = match jpeg_exif_and_xmp(self.path()) {
+                    Ok(Some(found)) => found,
+                    // only the JPEG images can be the Motion Photos
+                    Ok(None) => {
+                        let exif = read_exif(self.path())
+                            .map_err(|err| debug!("Failed to read EXIF of {}: {:?}", self, err))
+                            .ok();
+                        (exif, None)
+                    }
+                    Err(err) => {
+                        debug!("Failed to read metadata of {}: {:?}", self, err);
+                        return LiveMarks::default();
+                    }
+                };
+                let embedded = xmp.and_then(|xmp| {
+                    let length = motion_photo_video_length(&xmp)?;
+                    let file_size = self.path().metadata().ok()?.len();
+                    let offset = file_size.checked_sub(length)?;
+                    Some(EmbeddedVideo { offset, length })
+                });
+                let content_id = exif
+                    .and_then(|exif| decode_maker_note(&exif).remove(APPLE_CONTENT_ID))
+                    .map(|id| id.to_string());
+                LiveMarks {
+                    embedded,
+                    content_id,
+                }
+            }
+            MediaType::Video => LiveMarks {
+                embedded: None,
+                content_id: self
+                    .get_embedded_tags()
+                    .map_err(|err| debug!("Failed to read metadata of {}: {:?}", self, err))
+                    .ok()
+                    .and_then(|mut tags| tags.remove(QUICKTIME_CONTENT_ID))
+                    .map(|id| id.to_string()),
+            },
+            MediaType::Animation => LiveMarks::default(),
+        }
+    }
+
+    /// The key to pair the parts of a Live Photo missing the identifiers
+    /// (e.g. stripped by an export): the image and the QuickTime video
+    /// with the same name in the same directory.
+    fn live_stem(&self) -> Option<PathBuf> {
+        let is_quicktime = self
+            .path()
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("mov"));
+        match self.type_ {
+            MediaType::Photo => Some(self.path().with_extension("")),
+            MediaType::Video if is_quicktime => Some(self.path().with_extension("")),
+            MediaType::Video | MediaType::Animation => None,
+        }
+    }
+}
+
+/// Combine the images and videos of Live Photos and Motion Photos
+/// into single [items][MediaItem].
+///
+/// The image and the video of a Live Photo are paired by their `ContentIdentifier`
+/// or, if the image does not have one, by the same name (`IMG_1234.HEIC` and `IMG_1234.MOV`).
+///
+/// The order of the media is preserved, the paired videos
+/// are placed together with their images. The Live Photo videos
+/// without the matching image are placed at the end.
+pub fn group_live_photos(media: impl IntoIterator<Item = Media>) -> Vec<MediaItem> {
+    pair_live_photos(media.into_iter().map(|m| {
+        let marks = m.live_marks();
+        (m, marks)
+    }))
+}
+
+fn pair_live_photos(media: impl IntoIterator<Item = (Media, LiveMarks)>) -> Vec<MediaItem> {
+    let mut items = vec![];
+    let mut photos_by_id = HashMap::new();
+    let mut photos_by_stem = HashMap::new();
+    let mut seen_video_ids = HashSet::new();
+    let mut videos = vec![];
+    let mut unmarked_videos = vec![];
+
+    for (m, marks) in media {
+        match m.type_ {
+            MediaType::Photo => {
+                if let Some(embedded) = marks.embedded {
+                    items.push(Some(MediaItem::Live(LivePhoto {
+                        photo: m,
+                        video: MotionVideo::Embedded(embedded),
+                    })));
+                    continue;
+                }
+                if let Some(id) = marks.content_id {
+                    photos_by_id.entry(id).or_insert(items.len());
+                } else if let Some(stem) = m.live_stem() {
+                    photos_by_stem.entry(stem).or_insert(items.len());
+                }
+            }
+            MediaType::Video => match marks.content_id {
+                Some(id) => {
+                    if seen_video_ids.insert(id.clone()) {
+                        videos.push((id, m));
+                        continue;
+                    }
+                }
+                None => unmarked_videos.push(items.len()),
+            },
+            MediaType::Animation => {}
+        }
+        items.push(Some(MediaItem::Single(m)));
+    }
+
+    for (id, video) in videos {
+        let by_id = photos_by_id.get(&id).copied();
+        let paired = pair_at(&mut items, by_id, &video) || {
+            let by_stem = video
+                .live_stem()
+                .and_then(|stem| photos_by_stem.remove(&stem));
+            pair_at(&mut items, by_stem, &video)
+        };
+        if !paired {
+            info!("No image found for the Live Photo video {}", video);
+            items.push(Some(MediaItem::Single(video)));
+        }
+    }
+
+    for i in unmarked_videos {
+        let Some(MediaItem::Single(video)) = items[i].clone() else {
+            continue;
+        };
+        let by_stem = video
+            .live_stem()
+            .and_then(|stem| photos_by_stem.remove(&stem));
+        if pair_at(&mut items, by_stem, &video) {
+            items[i] = None;
+        }
+    }
+
+    items.into_iter().flatten().collect()
+}
+
+/// Turn the single photo at the index into a [`LivePhoto`] with the video.
+fn pair_at(items: &mut [Option<MediaItem>], photo: Option<usize>, video: &Media) -> bool {
+    let Some(i) = photo else {
+        return false;
+    };
+    let Some(MediaItem::Single(image)) = &items[i] else {
+        return false;
+    };
+    items[i] = Some(MediaItem::Live(LivePhoto {
+        photo: image.clone(),
+        video: MotionVideo::Paired(video.clone()),
+    }));
+    true
+}
+
+/// Default path to extract the video embedded into the image,
+/// e.g. `PXL_20230101_120000000.MP.jpg` -> `PXL_20230101_120000000.mp4`.
+pub fn embedded_video_path(image: &Path) -> PathBuf {
+    let stem = image.file_stem().unwrap_or_default().to_string_lossy();
+    let stem = stem
+        .strip_suffix(".MP")
+        .or_else(|| stem.strip_suffix(".mp"))
+        .unwrap_or(&stem);
+    image.with_file_name(format!("{stem}.mp4"))
+}
+
+/// Find the value of the XMP property written either
+/// as an attribute (`name="value"`) or as an element (`<name>value</name>`).
+fn xmp_property<'x>(xmp: &'x str, name: &str) -> Option<&'x str> {
+    let attr = format!("{name}=\"");
+    if let Some(start) = xmp.find(&attr) {
+        let value = &xmp[start + attr.len()..];
+        return value.split('"').next();
+    }
+
+    let element = format!("<{name}>");
+    let start = xmp.find(&element)?;
+    let value = &xmp[start + element.len()..];
+    value.split('<').next().map(str::trim)
+}
+
+/// Detect the size of the video embedded into a Motion Photo.
+///
+/// Supports both the legacy `MicroVideo` markers and
+/// the newer `MotionPhoto` ones with the `Container` directory.
+fn motion_photo_video_length(xmp: &str) -> Option<u64> {
+    if xmp_property(xmp, "GCamera:MicroVideo") == Some("1") {
+        return xmp_property(xmp, "GCamera:MicroVideoOffset")?.parse().ok();
+    }
+
+    if xmp_property(xmp, "GCamera:MotionPhoto") == Some("1") {
+        // the item description looks like
+        // <Container:Item Item:Mime="video/mp4" Item:Semantic="MotionPhoto" Item:Length="123"/>
+        let semantic = xmp.find("Semantic=\"MotionPhoto\"")?;
+        let item_start = xmp[..semantic].rfind('<')?;
+        let item_end = semantic + xmp[semantic..].find('>')?;
+        let item = &xmp[item_start..item_end];
+        return ["Item:Length", "GContainerItem:Length"]
+            .into_iter()
+            .find_map(|name| xmp_property(item, name))?
+            .parse()
+            .ok();
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    fn media(type_: MediaType, path: &str) -> Media {
+        Media {
+            type_,
+            path: PathBuf::from(path),
+        }
+    }
+
+    fn with_id(m: Media, id: &str) -> (Media, LiveMarks) {
+        let marks = LiveMarks {
+            embedded: None,
+            content_id: Some(id.to_owned()),
+        };
+        (m, marks)
+    }
+
+    fn unmarked(m: Media) -> (Media, LiveMarks) {
+        (m, LiveMarks::default())
+    }
+
+    fn live(photo: Media, video: Media) -> MediaItem {
+        MediaItem::Live(LivePhoto {
+            photo,
+            video: MotionVideo::Paired(video),
+        })
+    }
+
+    #[test]
+    fn pair_by_content_identifier() {
+        let photo = media(MediaType::Photo, "a/IMG_0001.HEIC");
+        let other = media(MediaType::Photo, "a/IMG_0002.HEIC");
+        let video = media(MediaType::Video, "b/renamed.MOV");
+
+        let items = pair_live_photos([
+            with_id(photo.clone(), "ID-1"),
+            with_id(video.clone(), "ID-1"),
+            with_id(other.clone(), "ID-2"),
+        ]);
+        assert_eq!(
+            items,
+            [live(photo, video), MediaItem::Single(other.clone())]
+        );
+    }
+
+    #[test]
+    fn pair_by_stem() {
+        let photo = media(MediaType::Photo, "a/IMG_0001.HEIC");
+        let video = media(MediaType::Video, "a/IMG_0001.MOV");
+        let clip = media(MediaType::Video, "a/IMG_0002.mp4");
+        let photo2 = media(MediaType::Photo, "a/IMG_0002.JPG");
+
+        let items = pair_live_photos([
+            unmarked(video.clone()),
+            unmarked(photo.clone()),
+            unmarked(clip.clone()),
+            unmarked(photo2.clone()),
+        ]);
+        // only the QuickTime videos are the Live Photo parts
+        assert_eq!(
+            items,
+            [
+                live(photo, video),
+                MediaItem::Single(clip),
+                MediaItem::Single(photo2),
+            ]
+        );
+    }
+
+    #[test]
+    fn identified_video_falls_back_to_stem() {
+        let photo = media(MediaType::Photo, "a/IMG_0001.JPG");
+        let video = media(MediaType::Video, "a/IMG_0001.MOV");
+
+        let items = pair_live_photos([unmarked(photo.clone()), with_id(video.clone(), "ID-1")]);
+        assert_eq!(items, [live(photo, video)]);
+    }
+
+    #[test]
+    fn identified_photo_is_not_paired_by_stem() {
+        let photo = media(MediaType::Photo, "a/IMG_0001.HEIC");
+        let video = media(MediaType::Video, "a/IMG_0001.MOV");
+
+        let items = pair_live_photos([with_id(photo.clone(), "ID-1"), unmarked(video.clone())]);
+        assert_eq!(items, [MediaItem::Single(photo), MediaItem::Single(video)]);
+    }
+
+    #[test]
+    fn unmatched_video_goes_last() {
+        let video = media(MediaType::Video, "a/IMG_0001.MOV");
+        let photo = media(MediaType::Photo, "a/IMG_0002.HEIC");
+
+        let items = pair_live_photos([with_id(video.clone(), "ID-1"), unmarked(photo.clone())]);
+        assert_eq!(items, [MediaItem::Single(photo), MediaItem::Single(video)]);
+    }
+
+    #[test]
+    fn motion_photo() {
+        const VIDEO: &[u8] = b"....ftypmp42 video data";
+
+        let xmp = format!(
+            r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+<rdf:Description xmlns:GCamera="http://ns.google.com/photos/1.0/camera/"
+ GCamera:MicroVideo="1" GCamera:MicroVideoOffset="{}"/></rdf:RDF></x:xmpmeta>"#,
+            VIDEO.len()
+        );
+        let mut segment = b"http://ns.adobe.com/xap/1.0/\0".to_vec();
+        segment.extend_from_slice(xmp.as_bytes());
+        let len = u16::try_from(segment.len() + 2).unwrap();
+
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        data.extend_from_slice(&len.to_be_bytes());
+        data.extend_from_slice(&segment);
+        data.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xD9]);
+        data.extend_from_slice(VIDEO);
+
+        let path = env::temp_dir().join(format!("foton-motion-{}.MP.jpg", process::id()));
+        fs::write(&path, &data).unwrap();
+        let photo = media(MediaType::Photo, path.to_str().unwrap());
+        let embedded = EmbeddedVideo {
+            offset: (data.len() - VIDEO.len()) as u64,
+            length: VIDEO.len() as u64,
+        };
+
+        let items = group_live_photos([photo.clone()]);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            items,
+            [MediaItem::Live(LivePhoto {
+                photo,
+                video: MotionVideo::Embedded(embedded),
+            })]
+        );
+    }
+}
@@ -0,0 +1,583 @@
+//! Laying out the media into directories by a path template,
+//! e.g. `{year}/{event_start:%Y-%m-%d} {event_name}`.
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    NaiveDateTime,
+};
+use serde::Deserialize;
+
+use crate::{
+    event::{
+        cluster::{cluster_events, ClusterOptions, EventCluster, TimelineEntry},
+        time::Time,
+        Event,
+    },
+    file_types::Media,
+    filter::parse_period,
+    geocode::{Geocoder, Place},
+    technical::Technical,
+    AnyError,
+};
+
+/// Used in place of the missing values (except for the event name).
+const UNKNOWN: &str = "unknown";
+
+/// The format of the time placeholders without the explicit format.
+const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Placeholder {
+    Year,
+    Month,
+    Day,
+    Time,
+    Type,
+    Make,
+    Model,
+    Camera,
+    EventStart,
+    EventEnd,
+    EventName,
+    Country,
+    Region,
+    City,
+}
+
+impl Placeholder {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "year" => Self::Year,
+            "month" => Self::Month,
+            "day" => Self::Day,
+            "time" => Self::Time,
+            "type" => Self::Type,
+            "make" => Self::Make,
+            "model" => Self::Model,
+            "camera" => Self::Camera,
+            "event_start" => Self::EventStart,
+            "event_end" => Self::EventEnd,
+            "event_name" => Self::EventName,
+            "country" => Self::Country,
+            "region" => Self::Region,
+            "city" => Self::City,
+            _ => return None,
+        })
+    }
+
+    fn is_time(self) -> bool {
+        matches!(self, Self::Time | Self::EventStart | Self::EventEnd)
+    }
+
+    fn is_event(self) -> bool {
+        matches!(self, Self::EventStart | Self::EventEnd | Self::EventName)
+    }
+
+    fn is_place(self) -> bool {
+        matches!(self, Self::Country | Self::Region | Self::City)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Value {
+        placeholder: Placeholder,
+        format: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Template of the directory to place a media into.
+///
+/// The `/` separates the nested directories, the placeholders are:
+/// - `{year}`, `{month}`, `{day}` of the media time;
+/// - `{time:FORMAT}` the media time in the [`strftime` format][chrono::format::strftime];
+/// - `{type}` of the media (`photo`, `animation` or `video`);
+/// - `{make}`, `{model}` and `{camera}` from the [technical metadata][Technical];
+/// - `{event_start:FORMAT}`, `{event_end:FORMAT}` and `{event_name}` of the event
+///   (or the whole trip) the media belongs to; the name is taken from the [`EventNames`]
+///   or, for the unnamed events, is the city nearest to the event's center;
+/// - `{country}`, `{region}` and `{city}` of the media location
+///   found by the [`Geocoder`].
+///
+/// The time placeholders without the format are rendered like `2022-07-08`.
+/// The missing values are rendered as `unknown`, except for the `{event_name}`
+/// which is just omitted.
+pub struct PathTemplate {
+    source: String,
+    parts: Vec<Part>,
+}
+
+impl FromStr for PathTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = vec![];
+        let mut rest = s;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                parts.push(Part::Literal(rest[..open].to_owned()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed placeholder in {:?}", s))?;
+            let inner = &rest[open + 1..open + close];
+            let (name, format) = inner
+                .split_once(':')
+                .map_or((inner, None), |(name, format)| (name, Some(format)));
+            let placeholder = Placeholder::parse(name.trim())
+                .ok_or_else(|| format!("unknown placeholder {{{}}}", name))?;
+            if let Some(format) = format {
+                if !placeholder.is_time() {
+                    return Err(format!("{{{}}} does not accept the format", name));
+                }
+                if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+                    return Err(format!("invalid time format {:?}", format));
+                }
+            }
+            parts.push(Part::Value {
+                placeholder,
+                format: format.map(ToOwned::to_owned),
+            });
+            rest = &rest[open + close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_owned()));
+        }
+        if parts.is_empty() {
+            return Err("empty template".into());
+        }
+
+        Ok(Self {
+            source: s.to_owned(),
+            parts,
+        })
+    }
+}
+
+impl fmt::Display for PathTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// The values to fill a [`PathTemplate`] with
+/// in addition to the ones taken from the media itself.
+pub struct TemplateValues {
+    /// The time of the media.
+    pub time: Option<NaiveDateTime>,
+    /// The start of the event the media belongs to.
+    pub event_start: Option<NaiveDateTime>,
+    /// The end of the event the media belongs to.
+    pub event_end: Option<NaiveDateTime>,
+    /// The name of the event the media belongs to.
+    pub event_name: Option<String>,
+    /// The place the media was shot at.
+    pub place: Option<Place>,
+}
+
+impl PathTemplate {
+    /// Whether the template needs the events to be detected.
+    pub fn uses_events(&self) -> bool {
+        self.placeholders().any(Placeholder::is_event)
+    }
+
+    /// Whether the template has the `{event_name}`
+    /// (which could be geocoded for the unnamed events).
+    pub fn uses_event_names(&self) -> bool {
+        self.placeholders().any(|p| p == Placeholder::EventName)
+    }
+
+    /// Whether the template needs the locations to be geocoded.
+    pub fn uses_places(&self) -> bool {
+        self.placeholders().any(Placeholder::is_place)
+    }
+
+    fn placeholders(&self) -> impl Iterator<Item = Placeholder> + '_ {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Literal(_) => None,
+            Part::Value { placeholder, .. } => Some(*placeholder),
+        })
+    }
+
+    /// Fill the template producing the relative path of the directory.
+    ///
+    /// The `/` in the values are replaced, so they could not produce
+    /// the extra directories. The empty directory names
+    /// (e.g. when the event name is missing) are skipped.
+    pub fn render(&self, media: &Media, values: &TemplateValues) -> PathBuf {
+        let mut technical = None;
+        let mut rendered = String::new();
+        for part in &self.parts {
+            let (placeholder, format) = match part {
+                Part::Literal(text) => {
+                    rendered.push_str(text);
+                    continue;
+                }
+                Part::Value {
+                    placeholder,
+                    format,
+                } => (*placeholder, format.as_deref()),
+            };
+            let format_time = |time: Option<NaiveDateTime>, default: &str| {
+                time.map(|time| time.format(format.unwrap_or(default)).to_string())
+            };
+            let mut camera = |field: fn(&Technical) -> Option<String>| {
+                field(technical.get_or_insert_with(|| media.technical()))
+            };
+
+            let value = match placeholder {
+                Placeholder::Year => format_time(values.time, "%Y"),
+                Placeholder::Month => format_time(values.time, "%m"),
+                Placeholder::Day => format_time(values.time, "%d"),
+                Placeholder::Time => format_time(values.time, DEFAULT_TIME_FORMAT),
+                Placeholder::Type => Some(media.type_().to_string().to_lowercase()),
+                Placeholder::Make => camera(|t| t.make.clone()),
+                Placeholder::Model => camera(|t| t.model.clone()),
+                Placeholder::Camera => camera(Technical::camera),
+                Placeholder::EventStart => format_time(values.event_start, DEFAULT_TIME_FORMAT),
+                Placeholder::EventEnd => format_time(values.event_end, DEFAULT_TIME_FORMAT),
+                Placeholder::EventName => Some(values.event_name.clone().unwrap_or_default()),
+                Placeholder::Country => values.place.as_ref().map(|place| place.country.clone()),
+                Placeholder::Region => values.place.as_ref().and_then(|place| place.region.clone()),
+                Placeholder::City => values.place.as_ref().map(|place| place.city.clone()),
+            };
+            let value = value.unwrap_or_else(|| UNKNOWN.to_owned());
+            rendered.push_str(&value.replace(['/', '\\'], "-"));
+        }
+
+        rendered
+            .split('/')
+            .map(|name| name.trim_matches(|c: char| c.is_whitespace() || c == '-' || c == '_'))
+            .filter(|name| !name.is_empty() && *name != "." && *name != "..")
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NamedPeriod {
+    name: String,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// User-defined names of the events.
+///
+/// The mapping file lists the names with the periods of time:
+///
+/// ```toml
+/// [[event]]
+/// name = "Paris"
+/// time = "2023-06-10..2023-06-12"
+///
+/// [[event]]
+/// name = "Birthday party"
+/// time = "2023-05-01"
+/// ```
+///
+/// The time is written like in the [filter][crate::Filter]'s `time` condition.
+pub struct EventNames {
+    periods: Vec<NamedPeriod>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventNamesFile {
+    #[serde(default)]
+    event: Vec<EventNameEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventNameEntry {
+    name: String,
+    time: String,
+}
+
+impl FromStr for EventNames {
+    type Err = AnyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let file: EventNamesFile = toml::from_str(s)?;
+        let periods = file
+            .event
+            .into_iter()
+            .map(|entry| {
+                let (start, end) = parse_period(&entry.time)
+                    .ok_or_else(|| format!("invalid time of {:?}: {:?}", entry.name, entry.time))?;
+                Ok(NamedPeriod {
+                    name: entry.name,
+                    start,
+                    end,
+                })
+            })
+            .collect::<Result<_, AnyError>>()?;
+        Ok(Self { periods })
+    }
+}
+
+impl EventNames {
+    /// Read the mapping file.
+    pub fn load(path: &Path) -> Result<Self, AnyError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// The name of the first period overlapping with the event.
+    pub fn name(&self, start: NaiveDateTime, end: NaiveDateTime) -> Option<&str> {
+        self.periods
+            .iter()
+            .find(|period| period.start <= end && start < period.end)
+            .map(|period| period.name.as_str())
+    }
+}
+
+/// Calculate the destination directory (relative to the organized library)
+/// for every media.
+///
+/// The events are only detected if the template [uses them][PathTemplate::uses_events].
+/// The media inside a [trip][crate::Trip] get the values of the whole trip.
+///
+/// The places (and the names of the events missing in the `names`)
+/// are only found if the `geocoder` is given.
+pub fn plan_organize(
+    media: impl IntoIterator<Item = (Media, Event)>,
+    template: &PathTemplate,
+    options: &ClusterOptions,
+    names: &EventNames,
+    geocoder: Option<&Geocoder>,
+) -> Vec<(Media, PathBuf)> {
+    let media: Vec<_> = media.into_iter().collect();
+    let mut values: HashMap<_, _> = media
+        .iter()
+        .map(|(media, event)| {
+            let values = TemplateValues {
+                time: event.time().map(Time::datetime),
+                place: geocoder.and_then(|geocoder| geocoder.locate(&event.location()?)),
+                ..TemplateValues::default()
+            };
+            (media.path().to_path_buf(), values)
+        })
+        .collect();
+
+    if template.uses_events() {
+        for entry in cluster_events(media.iter().cloned(), options) {
+            let (start, end) = (entry.start(), entry.end());
+            let name = names
+                .name(start, end)
+                .map(ToOwned::to_owned)
+                .or_else(|| place_name(&entry, geocoder?));
+            let members: Vec<_> = match &entry {
+                TimelineEntry::Event(event) => event.media().iter().collect(),
+                TimelineEntry::Trip(trip) => trip
+                    .events()
+                    .iter()
+                    .flat_map(|event| event.media())
+                    .collect(),
+            };
+            for member in members {
+                if let Some(values) = values.get_mut(member.path()) {
+                    values.event_start = Some(start);
+                    values.event_end = Some(end);
+                    values.event_name.clone_from(&name);
+                }
+            }
+        }
+    }
+
+    media
+        .into_iter()
+        .map(|(media, _)| {
+            let dir = values
+                .get(media.path())
+                .map(|values| template.render(&media, values))
+                .unwrap_or_default();
+            (media, dir)
+        })
+        .collect()
+}
+
+/// The city nearest to the center of the event.
+///
+/// The center of a trip could be far from any city (e.g. between two of them),
+/// so the place of its largest event is used then.
+fn place_name(entry: &TimelineEntry, geocoder: &Geocoder) -> Option<String> {
+    let nearest = |(latitude, longitude)| geocoder.nearest(latitude, longitude);
+    let place = entry.centroid().and_then(nearest).or_else(|| match entry {
+        TimelineEntry::Event(_) => None,
+        TimelineEntry::Trip(trip) => trip
+            .events()
+            .iter()
+            .filter(|event| event.centroid().is_some())
+            .max_by_key(|event| event.media().len())
+            .and_then(EventCluster::centroid)
+            .and_then(nearest),
+    });
+    place.map(|place| place.city)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        event::{space, time, EventSources},
+        file_types::MediaType,
+        tags::TagValue,
+    };
+
+    const NAMES: &str = r#"
+[[event]]
+name = "Paris"
+time = "2023-06-10..2023-06-12"
+
+[[event]]
+name = "Birthday party"
+time = "2023-05-01"
+
+[[event]]
+name = "Overlapping"
+time = "2023-06-11"
+"#;
+
+    fn datetime(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn photo(name: &str) -> Media {
+        Media {
+            type_: MediaType::Photo,
+            path: PathBuf::from(name),
+        }
+    }
+
+    fn shot(name: &str, time: &str) -> (Media, Event) {
+        let media = photo(name);
+        let mut tags = HashMap::new();
+        if !time.is_empty() {
+            tags.insert(
+                "exif:DateTimeOriginal".to_owned(),
+                TagValue::DateTime(datetime(time)),
+            );
+        }
+        let sources = EventSources {
+            time: vec![time::InfoSource::Tag {
+                name: "exif:DateTimeOriginal".to_owned(),
+                format: "%Y".into(),
+            }],
+            location: vec![space::InfoSource::Embedded],
+        };
+        let event = media.get_event_with_tags(&sources, Some(&tags));
+        (media, event)
+    }
+
+    #[test]
+    fn parse_template() {
+        let template: PathTemplate = "{year}/{event_start:%Y-%m-%d} {event_name}"
+            .parse()
+            .unwrap();
+        assert!(template.uses_events());
+        assert!(template.uses_event_names());
+        assert!(!template.uses_places());
+        assert_eq!(
+            template.to_string(),
+            "{year}/{event_start:%Y-%m-%d} {event_name}"
+        );
+
+        let places: PathTemplate = "{country}/{city}".parse().unwrap();
+        assert!(places.uses_places() && !places.uses_events());
+
+        for invalid in ["", "{year", "{unknown}", "{year:%Y}", "{time:%Q}"] {
+            assert!(invalid.parse::<PathTemplate>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn render() {
+        let template: PathTemplate = "{year}/{time:%m-%d} {event_name}/{type}".parse().unwrap();
+        let media = photo("IMG_0001.jpg");
+        let mut values = TemplateValues {
+            time: Some(datetime("2022-07-08 10:30")),
+            ..TemplateValues::default()
+        };
+        // the missing event name is omitted
+        assert_eq!(
+            template.render(&media, &values),
+            Path::new("2022/07-08/photo")
+        );
+
+        values.event_name = Some("Paris/Lyon".to_owned());
+        assert_eq!(
+            template.render(&media, &values),
+            Path::new("2022/07-08 Paris-Lyon/photo")
+        );
+
+        // the values can not escape the directory
+        values.event_name = Some("..".to_owned());
+        let name_only: PathTemplate = "{event_name}".parse().unwrap();
+        assert_eq!(name_only.render(&media, &values), Path::new(""));
+
+        assert_eq!(
+            template.render(&media, &TemplateValues::default()),
+            Path::new("unknown/unknown/photo")
+        );
+    }
+
+    #[test]
+    fn event_name_ranges() {
+        let names: EventNames = NAMES.parse().unwrap();
+        let name = |start, end| names.name(datetime(start), datetime(end));
+
+        assert_eq!(
+            name("2023-05-01 23:00", "2023-05-01 23:30"),
+            Some("Birthday party")
+        );
+        // the periods are half-open
+        assert_eq!(name("2023-05-02 00:00", "2023-05-02 01:00"), None);
+        assert_eq!(name("2023-04-30 10:00", "2023-04-30 23:59"), None);
+
+        // any overlap is enough
+        assert_eq!(name("2023-06-09 22:00", "2023-06-10 01:00"), Some("Paris"));
+        assert_eq!(name("2023-06-12 20:00", "2023-06-12 21:00"), Some("Paris"));
+        assert_eq!(name("2023-06-13 00:00", "2023-06-13 10:00"), None);
+        // the first period wins
+        assert_eq!(name("2023-06-11 10:00", "2023-06-11 11:00"), Some("Paris"));
+
+        let invalid = "[[event]]\nname = \"Broken\"\ntime = \"2023-13\"";
+        assert!(invalid.parse::<EventNames>().is_err());
+        assert_eq!("".parse::<EventNames>().unwrap(), EventNames::default());
+    }
+
+    #[test]
+    fn plan_with_event_names() {
+        let template: PathTemplate = "{event_start:%Y-%m-%d} {event_name}".parse().unwrap();
+        let names: EventNames = NAMES.parse().unwrap();
+        let media = [
+            shot("a", "2023-06-10 10:00"),
+            shot("b", "2023-06-10 11:00"),
+            shot("c", "2023-06-20 10:00"),
+            shot("d", ""),
+        ];
+        let plan = plan_organize(media, &template, &ClusterOptions::default(), &names, None);
+        let dirs: Vec<_> = plan
+            .iter()
+            .map(|(media, dir)| (media.path().to_str().unwrap(), dir.to_str().unwrap()))
+            .collect();
+        assert_eq!(
+            dirs,
+            [
+                ("a", "2023-06-10 Paris"),
+                ("b", "2023-06-10 Paris"),
+                ("c", "2023-06-20"),
+                ("d", "unknown"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,747 @@
+//! Sidecars are the files which are not media by themselves
+//! but contain some metadata or edits of a media file.
+use std::{
+    collections::{HashMap as Map, HashSet},
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader, Read as _},
+    path::{Path, PathBuf},
+};
+
+use log::{debug, warn};
+use roxmltree::Document;
+use serde_json::Value as JsonValue;
+
+use crate::{
+    file_types::Media,
+    tags::{get_image_tags, parse_xmp, TagValue},
+    AnyError,
+};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+/// Type of a sidecar file.
+///
+/// The order of variants defines the precedence of the metadata:
+/// when the same tag is found in multiple sources,
+/// the value from the latter one wins.
+pub enum SidecarKind {
+    /// Google Takeout metadata (`IMG_1234.jpg.json` or its mangled variants).
+    TakeoutJson,
+    /// Apple Photos adjustments (`IMG_1234.AAE`).
+    Aae,
+    /// Thumbnail with EXIF data for a video (`MVI_0001.THM`).
+    Thm,
+    /// XMP metadata written by photo editors (`IMG_1234.xmp` or `IMG_1234.jpg.xmp`).
+    ///
+    /// As the editors write them after the shot, they
+    /// override even the metadata embedded into the media itself.
+    Xmp,
+}
+
+impl SidecarKind {
+    /// Prefix for the tags extracted from a sidecar.
+    ///
+    /// The XMP and THM sidecars contain the same tags
+    /// as the embedded metadata, so no prefix is used.
+    fn tag_prefix(self) -> &'static str {
+        match self {
+            Self::TakeoutJson => "takeout:",
+            Self::Aae => "aae:",
+            Self::Thm | Self::Xmp => "",
+        }
+    }
+}
+
+impl fmt::Display for SidecarKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let desc = match self {
+            Self::TakeoutJson => "JSON",
+            Self::Aae => "AAE",
+            Self::Thm => "THM",
+            Self::Xmp => "XMP",
+        };
+        f.write_str(desc)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// A file with metadata belonging to a [`Media`].
+pub struct Sidecar {
+    kind: SidecarKind,
+    path: PathBuf,
+}
+
+impl Sidecar {
+    /// Type of the sidecar.
+    pub fn kind(&self) -> SidecarKind {
+        self.kind
+    }
+
+    /// Path to the sidecar file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Extract the tags from the sidecar file.
+    pub fn get_tags(&self) -> Result<Map<String, TagValue>, AnyError> {
+        let tags = match self.kind {
+            SidecarKind::Xmp => parse_xmp(&fs::read_to_string(&self.path)?)?,
+            SidecarKind::Thm => get_image_tags(&self.path)?,
+            SidecarKind::Aae => parse_plist(&fs::read_to_string(&self.path)?)?,
+            SidecarKind::TakeoutJson => {
+                let json: JsonValue = serde_json::from_str(&fs::read_to_string(&self.path)?)?;
+                let mut tags = Map::new();
+                flatten_json(&json, "", &mut tags);
+                tags
+            }
+        };
+
+        let prefix = self.kind.tag_prefix();
+        Ok(if prefix.is_empty() {
+            tags
+        } else {
+            tags.into_iter()
+                .map(|(k, v)| (format!("{prefix}{k}"), v))
+                .collect()
+        })
+    }
+}
+
+impl fmt::Display for Sidecar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}]{}", self.kind, self.path.display())
+    }
+}
+
+impl Media {
+    /// Find the sidecar files located next to the media.
+    ///
+    /// The sidecars are sorted by their [precedence][SidecarKind].
+    pub fn sidecars(&self) -> Vec<Sidecar> {
+        let path = self.path();
+        let (Some(stem), Some(file_name)) = (path.file_stem(), path.file_name()) else {
+            return vec![];
+        };
+        let (stem, file_name) = (stem.to_string_lossy(), file_name.to_string_lossy());
+
+        let takeout = takeout_json_names(&file_name)
+            .into_iter()
+            .find(|name| path.with_file_name(name).is_file())
+            .or_else(|| {
+                takeout_stem_json_name(&file_name)
+                    .filter(|name| path.with_file_name(name).is_file() && !has_namesakes(path))
+            })
+            .map(|name| (SidecarKind::TakeoutJson, name));
+        let candidates = takeout.into_iter().chain([
+            (SidecarKind::Aae, format!("{stem}.AAE")),
+            (SidecarKind::Aae, format!("{stem}.aae")),
+            (SidecarKind::Thm, format!("{stem}.THM")),
+            (SidecarKind::Thm, format!("{stem}.thm")),
+            (SidecarKind::Xmp, format!("{stem}.xmp")),
+            (SidecarKind::Xmp, format!("{stem}.XMP")),
+            (SidecarKind::Xmp, format!("{file_name}.xmp")),
+            (SidecarKind::Xmp, format!("{file_name}.XMP")),
+        ]);
+
+        let mut sidecars: Vec<_> = candidates
+            .into_iter()
+            .map(|(kind, name)| Sidecar {
+                kind,
+                path: path.with_file_name(name),
+            })
+            .filter(|sidecar| sidecar.path.is_file())
+            .collect();
+        // on case-insensitive filesystems the same file matches multiple names
+        let mut seen = HashSet::new();
+        sidecars.retain(|sidecar| {
+            seen.insert(
+                sidecar
+                    .path
+                    .canonicalize()
+                    .unwrap_or_else(|_| sidecar.path.clone()),
+            )
+        });
+        sidecars
+    }
+
+    /// Merge the tags of the media with the ones from its sidecars
+    /// according to their [precedence][SidecarKind].
+    ///
+    /// If the media has some sidecars, failing to read
+    /// its own (embedded) tags is not an error.
+    pub(crate) fn merge_sidecar_tags(
+        &self,
+        embedded: Result<Map<String, TagValue>, AnyError>,
+    ) -> Result<Map<String, TagValue>, AnyError> {
+        let sidecars = self.sidecars();
+        let embedded = match embedded {
+            Ok(tags) => tags,
+            Err(err) if !sidecars.is_empty() => {
+                debug!("Using only the sidecars' tags for {}: {:?}", self, err);
+                Map::new()
+            }
+            Err(err) => return Err(err),
+        };
+
+        let (lower, higher): (Vec<_>, Vec<_>) = sidecars
+            .into_iter()
+            .partition(|sidecar| sidecar.kind < SidecarKind::Xmp);
+
+        let mut tags = Map::new();
+        for sidecar in &lower {
+            tags.extend(sidecar_tags(sidecar));
+        }
+        tags.extend(embedded);
+        for sidecar in &higher {
+            tags.extend(sidecar_tags(sidecar));
+        }
+        Ok(tags)
+    }
+
+    /// All the files belonging to the media: the media itself and its sidecars.
+    ///
+    /// They should always be moved or copied together.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let sidecars = self.sidecars().into_iter().map(|s| s.path);
+        std::iter::once(self.path.clone()).chain(sidecars).collect()
+    }
+
+    /// Copy the media along with its sidecars into the directory.
+    ///
+    /// Nothing is copied if any of the files already exists in the directory.
+    /// Returns the copied [`Media`].
+    pub fn copy_to(&self, dir: &Path) -> io::Result<Self> {
+        transfer_files(&self.files(), dir, false)?;
+        Ok(self.relocated(dir))
+    }
+
+    /// Move the media along with its sidecars into the directory.
+    ///
+    /// Nothing is moved if any of the files already exists in the directory.
+    /// Returns the moved [`Media`].
+    pub fn move_to(&self, dir: &Path) -> io::Result<Self> {
+        transfer_files(&self.files(), dir, true)?;
+        Ok(self.relocated(dir))
+    }
+
+    fn relocated(&self, dir: &Path) -> Self {
+        let mut path = dir.to_path_buf();
+        if let Some(name) = self.path.file_name() {
+            path.push(name);
+        }
+        Self {
+            type_: self.type_,
+            path,
+        }
+    }
+}
+
+/// Google Takeout limits the JSON sidecar name
+/// to this number of characters (not counting the `.json`).
+const TAKEOUT_MAX_NAME_LEN: usize = 46;
+
+/// Google Photos adds a (localized) suffix to the edited copies
+/// which share the JSON sidecar with the original.
+const TAKEOUT_EDITED_SUFFIXES: &[&str] = &[
+    "-edited",
+    "-bearbeitet",
+    "-modifié",
+    "-editado",
+    "-modificato",
+    "-bewerkt",
+];
+
+/// Split the name of a media file exported by Google Takeout into
+/// the stem of the original, its extension and the duplicate counter (`(1)`).
+fn takeout_name_parts(file_name: &str) -> (&str, Option<&str>, &str) {
+    let (stem, ext) = match file_name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, Some(ext)),
+        _ => (file_name, None),
+    };
+
+    let (stem, counter) = match stem.strip_suffix(')').and_then(|s| s.rsplit_once('(')) {
+        Some((base, n)) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => {
+            (base, &stem[base.len()..])
+        }
+        _ => (stem, ""),
+    };
+
+    let stem = TAKEOUT_EDITED_SUFFIXES
+        .iter()
+        .find_map(|suffix| stem.strip_suffix(suffix))
+        .unwrap_or(stem);
+    (stem, ext, counter)
+}
+
+/// Add the `.json` to the name truncated the way Google Takeout does.
+fn takeout_json_name(base: &str, counter: &str) -> String {
+    let truncated: String = base.chars().take(TAKEOUT_MAX_NAME_LEN).collect();
+    format!("{truncated}{counter}.json")
+}
+
+/// Possible names of the Google Takeout JSON sidecar
+/// for a media file (in the order of preference).
+///
+/// Google Takeout has a number of quirks naming the sidecars:
+/// - `IMG_1234.jpg` -> `IMG_1234.jpg.json`
+///   (or `IMG_1234.jpg.supplemental-metadata.json` in the newer exports);
+/// - the duplicate `IMG_1234(1).jpg` -> `IMG_1234.jpg(1).json`;
+/// - the edited `IMG_1234-edited.jpg` -> `IMG_1234.jpg.json`;
+/// - the long names are truncated to 46 characters before adding the `.json`.
+///
+/// See [`takeout_stem_json_name`] for the names without the media extension.
+fn takeout_json_names(file_name: &str) -> Vec<String> {
+    let (stem, ext, counter) = takeout_name_parts(file_name);
+    let original = match ext {
+        Some(ext) => format!("{stem}.{ext}"),
+        None => stem.to_owned(),
+    };
+
+    let mut names = vec![format!("{file_name}.json")];
+    for base in [
+        original.clone(),
+        format!("{original}.supplemental-metadata"),
+    ] {
+        let name = takeout_json_name(&base, counter);
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Rarely Google Takeout drops the extension naming the JSON sidecar:
+/// `IMG_1234.jpg` -> `IMG_1234.json`.
+///
+/// Such a sidecar can belong to any media with the same stem,
+/// so it should only be used if there are no [namesakes][has_namesakes].
+fn takeout_stem_json_name(file_name: &str) -> Option<String> {
+    let (stem, ext, counter) = takeout_name_parts(file_name);
+    ext.map(|_| takeout_json_name(stem, counter))
+}
+
+/// Whether other files (besides the sidecars) share the stem with the file,
+/// e.g. `IMG_1234.mp4` for the `IMG_1234.jpg`.
+fn has_namesakes(path: &Path) -> bool {
+    const SIDECAR_EXTENSIONS: &[&str] = &["json", "aae", "thm", "xmp"];
+
+    let (Some(dir), Some(stem)) = (path.parent(), path.file_stem()) else {
+        return false;
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+    entries.filter_map(Result::ok).any(|entry| {
+        let other = entry.path();
+        other.file_stem() == Some(stem)
+            && other.file_name() != path.file_name()
+            && !other.extension().is_some_and(|ext| {
+                SIDECAR_EXTENSIONS
+                    .iter()
+                    .any(|known| ext.eq_ignore_ascii_case(known))
+            })
+    })
+}
+
+/// Copy (or move) the files into the directory.
+///
+/// All the destinations are checked beforehand, so the existing files
+/// are never overwritten and a conflict does not separate a media from its sidecars.
+/// When copying, a file identical to the existing destination is skipped:
+/// it is a sidecar shared with another media (e.g. `IMG_1234.xmp`
+/// of both `IMG_1234.jpg` and `IMG_1234.mp4`) copied along with that media.
+///
+/// If any transfer fails, the files already transferred are put back.
+pub(crate) fn transfer_files(files: &[PathBuf], dir: &Path, move_: bool) -> io::Result<()> {
+    let targets = transfer_targets(files, dir, move_)?;
+    fs::create_dir_all(dir)?;
+    let mut done: Vec<(&PathBuf, PathBuf)> = vec![];
+    for (file, target) in targets {
+        let result = if move_ {
+            move_file(file, &target)
+        } else {
+            fs::copy(file, &target).map(drop)
+        };
+        if let Err(err) = result {
+            for (file, target) in done.into_iter().rev() {
+                let restored = if move_ {
+                    move_file(&target, file)
+                } else {
+                    fs::remove_file(&target)
+                };
+                if let Err(err) = restored {
+                    warn!("Failed to roll back {}: {}", target.display(), err);
+                }
+            }
+            return Err(err);
+        }
+        done.push((file, target));
+    }
+    Ok(())
+}
+
+/// Find the destinations of the files to [transfer][transfer_files]
+/// failing if any of them already exists.
+pub(crate) fn transfer_targets<'f>(
+    files: &'f [PathBuf],
+    dir: &Path,
+    move_: bool,
+) -> io::Result<Vec<(&'f PathBuf, PathBuf)>> {
+    let mut targets: Vec<(&PathBuf, PathBuf)> = vec![];
+    for file in files {
+        let target = dir.join(file_name(file)?);
+        if targets.iter().any(|(other, _)| *other == file) {
+            continue;
+        }
+        let exists = target.exists();
+        let conflict = targets.iter().any(|(_, other)| *other == target)
+            || (exists && (move_ || !same_content(file, &target)?));
+        if conflict {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", target.display()),
+            ));
+        }
+        // the identical copy is a sidecar shared with another media
+        if !exists {
+            targets.push((file, target));
+        }
+    }
+    Ok(targets)
+}
+
+/// Rename the file falling back to copying when the destination
+/// is located on another filesystem.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from).inspect_err(|_| {
+                // do not leave the second copy
+                let _ = fs::remove_file(to);
+            })
+        }
+        result => result,
+    }
+}
+
+fn file_name(path: &Path) -> io::Result<&std::ffi::OsStr> {
+    path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("not a file: {}", path.display()),
+        )
+    })
+}
+
+fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    let (mut a, mut b) = (
+        BufReader::new(File::open(a)?),
+        BufReader::new(File::open(b)?),
+    );
+    let (mut buf_a, mut buf_b) = ([0; 8192], [0; 8192]);
+    loop {
+        let read = a.read(&mut buf_a)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buf_b[..read])?;
+        if buf_a[..read] != buf_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+fn sidecar_tags(sidecar: &Sidecar) -> Map<String, TagValue> {
+    sidecar
+        .get_tags()
+        .map_err(|err| {
+            warn!("Failed to get tags from sidecar {}: {:?}", sidecar, err);
+        })
+        .unwrap_or_default()
+}
+
+/// Parse the top-level dictionary of an Apple property list (XML flavor).
+fn parse_plist(content: &str) -> Result<Map<String, TagValue>, AnyError> {
+    let doc = Document::parse(content)?;
+    let dict = doc
+        .root_element()
+        .children()
+        .find(|n| n.has_tag_name("dict"))
+        .ok_or("no dictionary found in the property list")?;
+
+    let mut tags = Map::new();
+    let mut key = None;
+    for node in dict.children().filter(roxmltree::Node::is_element) {
+        if node.has_tag_name("key") {
+            key = node.text();
+        } else if let Some(key) = key.take() {
+            let text = node.text().unwrap_or_default().trim();
+            let value = match node.tag_name().name() {
+                "true" | "false" => TagValue::from(node.tag_name().name()),
+                "integer" => text.parse().map_or_else(|_| text.into(), TagValue::Integer),
+                "real" => text.parse().map_or_else(|_| text.into(), TagValue::Float),
+                // the adjustment data is an opaque binary blob
+                "data" | "dict" | "array" => continue,
+                _ => TagValue::from_text(text),
+            };
+            tags.insert(key.to_owned(), value);
+        }
+    }
+    Ok(tags)
+}
+
+/// Flatten the nested JSON objects into the `parent.child` keys.
+fn flatten_json(value: &JsonValue, path: &str, tags: &mut Map<String, TagValue>) {
+    match value {
+        JsonValue::Object(map) => {
+            for (k, v) in map {
+                let path = if path.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{path}.{k}")
+                };
+                flatten_json(v, &path, tags);
+            }
+        }
+        JsonValue::Array(items) => {
+            for (i, v) in items.iter().enumerate() {
+                flatten_json(v, &format!("{path}[{i}]"), tags);
+            }
+        }
+        JsonValue::String(s) => {
+            tags.insert(path.to_owned(), TagValue::from_text(s.as_str()));
+        }
+        JsonValue::Number(n) => {
+            let value = n
+                .as_i64()
+                .map(TagValue::Integer)
+                .or_else(|| n.as_f64().map(TagValue::Float))
+                .unwrap_or_else(|| n.to_string().into());
+            tags.insert(path.to_owned(), value);
+        }
+        JsonValue::Bool(b) => {
+            tags.insert(path.to_owned(), b.to_string().into());
+        }
+        JsonValue::Null => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MediaType;
+
+    /// Create an empty directory for a test removing its previous content.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("foton-sidecar-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn touch(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn photo(path: PathBuf) -> Media {
+        Media {
+            type_: MediaType::Photo,
+            path,
+        }
+    }
+
+    fn sidecar_names(media: &Media) -> Vec<String> {
+        media
+            .sidecars()
+            .iter()
+            .map(|sidecar| {
+                file_name(sidecar.path())
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn takeout_plain_names() {
+        assert_eq!(
+            takeout_json_names("IMG_1234.jpg"),
+            [
+                "IMG_1234.jpg.json",
+                "IMG_1234.jpg.supplemental-metadata.json"
+            ]
+        );
+        assert_eq!(
+            takeout_stem_json_name("IMG_1234.jpg").as_deref(),
+            Some("IMG_1234.json")
+        );
+    }
+
+    #[test]
+    fn takeout_duplicate_names() {
+        assert_eq!(
+            takeout_json_names("IMG_1234(1).jpg"),
+            [
+                "IMG_1234(1).jpg.json",
+                "IMG_1234.jpg(1).json",
+                "IMG_1234.jpg.supplemental-metadata(1).json"
+            ]
+        );
+        assert_eq!(
+            takeout_stem_json_name("IMG_1234(1).jpg").as_deref(),
+            Some("IMG_1234(1).json")
+        );
+        // not a counter
+        assert_eq!(
+            takeout_json_names("IMG(a).jpg")[1],
+            "IMG(a).jpg.supplemental-metadata.json"
+        );
+    }
+
+    #[test]
+    fn takeout_edited_names() {
+        assert_eq!(
+            takeout_json_names("IMG_1234-edited.jpg"),
+            [
+                "IMG_1234-edited.jpg.json",
+                "IMG_1234.jpg.json",
+                "IMG_1234.jpg.supplemental-metadata.json"
+            ]
+        );
+        assert_eq!(
+            takeout_json_names("IMG_1234-bearbeitet(2).jpg")[1],
+            "IMG_1234.jpg(2).json"
+        );
+    }
+
+    #[test]
+    fn takeout_truncated_names() {
+        let file_name = "Screenshot_2020-01-01-12-30-45-123_com.example.app.jpg";
+        let names = takeout_json_names(file_name);
+        assert_eq!(
+            names[1],
+            "Screenshot_2020-01-01-12-30-45-123_com.example.json"
+        );
+        // the supplemental metadata name is truncated to the same one
+        assert_eq!(names.len(), 2);
+
+        let names = takeout_json_names("PXL_20230101_123456789.MP.jpg");
+        assert_eq!(
+            names[1],
+            "PXL_20230101_123456789.MP.jpg.supplemental-met.json"
+        );
+    }
+
+    #[test]
+    fn takeout_names_without_extension() {
+        assert_eq!(
+            takeout_json_names("IMG_1234"),
+            ["IMG_1234.json", "IMG_1234.supplemental-metadata.json"]
+        );
+        assert_eq!(takeout_stem_json_name("IMG_1234"), None);
+        assert_eq!(takeout_json_names(".hidden")[0], ".hidden.json");
+    }
+
+    #[test]
+    fn takeout_stem_json_is_not_shared() {
+        let dir = temp_dir("stem-json");
+        let jpg = photo(touch(&dir, "IMG_1234.jpg", ""));
+        touch(&dir, "IMG_1234.json", "{}");
+        assert_eq!(sidecar_names(&jpg), ["IMG_1234.json"]);
+
+        touch(&dir, "IMG_1234.xmp", "");
+        assert_eq!(sidecar_names(&jpg), ["IMG_1234.json", "IMG_1234.xmp"]);
+
+        // the JSON could belong to the video as well
+        touch(&dir, "IMG_1234.mp4", "");
+        assert_eq!(sidecar_names(&jpg), ["IMG_1234.xmp"]);
+
+        // but not the ones with the full name
+        touch(&dir, "IMG_1234.jpg.json", "{}");
+        assert_eq!(sidecar_names(&jpg), ["IMG_1234.jpg.json", "IMG_1234.xmp"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn same_sidecar_found_once() {
+        let dir = temp_dir("dedup");
+        let jpg = photo(touch(&dir, "IMG_1234.jpg", ""));
+        touch(&dir, "IMG_1234.xmp", "");
+        std::os::unix::fs::symlink(dir.join("IMG_1234.xmp"), dir.join("IMG_1234.jpg.xmp")).unwrap();
+        std::os::unix::fs::symlink(dir.join("IMG_1234.xmp"), dir.join("IMG_1234.jpg.XMP")).unwrap();
+        touch(&dir, "IMG_1234.THM", "");
+        assert_eq!(sidecar_names(&jpg), ["IMG_1234.THM", "IMG_1234.xmp"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn shared_sidecar_copied_once() {
+        let dir = temp_dir("shared");
+        let out = dir.join("out");
+        let jpg = photo(touch(&dir, "IMG_1234.jpg", "photo"));
+        let mp4 = Media {
+            type_: MediaType::Video,
+            path: touch(&dir, "IMG_1234.mp4", "video"),
+        };
+        touch(&dir, "IMG_1234.xmp", "xmp");
+
+        jpg.copy_to(&out).unwrap();
+        mp4.copy_to(&out).unwrap();
+        for name in ["IMG_1234.jpg", "IMG_1234.mp4", "IMG_1234.xmp"] {
+            assert!(out.join(name).is_file(), "{name}");
+        }
+
+        // another file with the same name is never overwritten
+        touch(&dir, "IMG_1234.xmp", "changed");
+        let err = mp4.copy_to(&out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_move_is_rolled_back() {
+        let dir = temp_dir("rollback-move");
+        let out = dir.join("out");
+        let files = [
+            touch(&dir, "IMG_1234.jpg", "photo"),
+            touch(&dir, "IMG_1234.xmp", "xmp"),
+            dir.join("missing.AAE"),
+        ];
+
+        let err = transfer_files(&files, &out, true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(files[0].is_file());
+        assert!(files[1].is_file());
+        assert_eq!(fs::read_dir(&out).unwrap().count(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_copy_is_rolled_back() {
+        let dir = temp_dir("rollback-copy");
+        let out = dir.join("out");
+        let files = [
+            touch(&dir, "IMG_1234.jpg", "photo"),
+            dir.join("missing.xmp"),
+        ];
+
+        assert!(transfer_files(&files, &out, false).is_err());
+        assert!(files[0].is_file());
+        assert_eq!(fs::read_dir(&out).unwrap().count(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+//! IPTC Information Interchange Model (IIM) metadata
+//! written mostly by the news agencies and the scanning software.
+use std::{collections::HashMap as Map, path::Path};
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+
+use super::{read_jpeg_segment, TagValue};
+use crate::AnyError;
+
+/// Well-known datasets of the IPTC-IIM application record (2).
+const IPTC_DATASETS: &[(u8, &str)] = &[
+    (5, "ObjectName"),
+    (10, "Urgency"),
+    (15, "Category"),
+    (20, "SupplementalCategories"),
+    (25, "Keywords"),
+    (40, "SpecialInstructions"),
+    (55, "DateCreated"),
+    (60, "TimeCreated"),
+    (62, "DigitalCreationDate"),
+    (63, "DigitalCreationTime"),
+    (80, "By-line"),
+    (85, "By-lineTitle"),
+    (90, "City"),
+    (92, "Sub-location"),
+    (95, "Province-State"),
+    (100, "Country-PrimaryLocationCode"),
+    (101, "Country-PrimaryLocationName"),
+    (103, "OriginalTransmissionReference"),
+    (105, "Headline"),
+    (110, "Credit"),
+    (115, "Source"),
+    (116, "CopyrightNotice"),
+    (118, "Contact"),
+    (120, "Caption-Abstract"),
+    (122, "Writer-Editor"),
+];
+
+/// Get IPTC-IIM tags of an image by its path.
+///
+/// The tags are read from the Photoshop `APP13` segment of a JPEG file
+/// and named as `iptc:Name`, e.g. `iptc:Keywords` or `iptc:Caption-Abstract`.
+/// The repeatable datasets (like keywords) are collected into a [list][TagValue::List].
+///
+/// For convenience, the `DateCreated` and `TimeCreated` are also
+/// combined into the `iptc:DateTimeCreated`.
+pub fn get_iptc_tags<P>(path: &P) -> Result<Map<String, TagValue>, AnyError>
+where
+    P: AsRef<Path> + ?Sized,
+{
+    const APP13: u8 = 0xED;
+    const PHOTOSHOP_SIGNATURE: &[u8] = b"Photoshop 3.0\0";
+    const IPTC_RESOURCE_ID: u16 = 0x0404;
+
+    let Some(resources) = read_jpeg_segment(path, APP13, PHOTOSHOP_SIGNATURE)? else {
+        return Ok(Map::new());
+    };
+    let iptc = photoshop_resource(&resources, IPTC_RESOURCE_ID).unwrap_or_default();
+    Ok(parse_iptc(iptc))
+}
+
+/// Find the data of an Image Resource Block (`8BIM`) by its ID.
+fn photoshop_resource(mut resources: &[u8], id: u16) -> Option<&[u8]> {
+    loop {
+        let rest = resources.strip_prefix(b"8BIM")?;
+        let resource_id = u16::from_be_bytes([*rest.first()?, *rest.get(1)?]);
+        // Pascal string padded to the even size
+        let name_len = usize::from(*rest.get(2)?);
+        let name_size = (name_len + 1 + 1) & !1;
+        let rest = rest.get(2 + name_size..)?;
+        let size = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?);
+        let size = usize::try_from(size).ok()?;
+        let data = rest.get(4..4 + size)?;
+        if resource_id == id {
+            return Some(data);
+        }
+        // data is padded to the even size too
+        resources = rest.get(4 + ((size + 1) & !1)..)?;
+    }
+}
+
+fn parse_iptc(mut data: &[u8]) -> Map<String, TagValue> {
+    const TAG_MARKER: u8 = 0x1C;
+    const ENVELOPE_RECORD: u8 = 1;
+    const APPLICATION_RECORD: u8 = 2;
+    const CODED_CHARACTER_SET: u8 = 90;
+    const UTF8_ESCAPE: &[u8] = b"\x1b%G";
+
+    let mut is_utf8 = false;
+    let mut values: Vec<(&str, String)> = vec![];
+    while let [TAG_MARKER, record, dataset, size_hi, size_lo, rest @ ..] = data {
+        let size = usize::from(u16::from_be_bytes([*size_hi, *size_lo]));
+        // extended datasets (size > 32767) are binary previews, stop parsing them
+        if size & 0x8000 != 0 {
+            break;
+        }
+        let Some(value) = rest.get(..size) else {
+            break;
+        };
+        data = &rest[size..];
+
+        match *record {
+            ENVELOPE_RECORD if *dataset == CODED_CHARACTER_SET => {
+                is_utf8 = value == UTF8_ESCAPE;
+            }
+            APPLICATION_RECORD => {
+                let Some((_, name)) = IPTC_DATASETS.iter().find(|(id, _)| id == dataset) else {
+                    continue;
+                };
+                let value = if is_utf8 {
+                    String::from_utf8_lossy(value).into_owned()
+                } else {
+                    // assume ISO 8859-1 which maps directly into the Unicode
+                    String::from_utf8(value.to_vec())
+                        .unwrap_or_else(|_| value.iter().map(|&b| char::from(b)).collect())
+                };
+                values.push((name, value.trim_end_matches('\0').trim().to_owned()));
+            }
+            _ => {}
+        }
+    }
+
+    let mut tags: Map<String, TagValue> = Map::new();
+    for (name, value) in values {
+        let key = format!("iptc:{name}");
+        let value = TagValue::Text(value);
+        match tags.remove(&key) {
+            Some(TagValue::List(mut existing)) => {
+                existing.push(value);
+                tags.insert(key, TagValue::List(existing));
+            }
+            Some(existing) => {
+                tags.insert(key, TagValue::List(vec![existing, value]));
+            }
+            None => {
+                tags.insert(key, value);
+            }
+        }
+    }
+
+    if let Some(date_time) = iptc_datetime(&tags) {
+        tags.insert("iptc:DateTimeCreated".into(), TagValue::DateTime(date_time));
+    }
+    tags
+}
+
+/// Combine the `DateCreated` (`CCYYMMDD`) and `TimeCreated` (`HHMMSS±HHMM`).
+fn iptc_datetime(tags: &Map<String, TagValue>) -> Option<NaiveDateTime> {
+    let date = tags.get("iptc:DateCreated")?.as_text()?;
+    let date = NaiveDate::parse_from_str(date, "%Y%m%d").ok()?;
+    let time = tags
+        .get("iptc:TimeCreated")
+        .and_then(TagValue::as_text)
+        .and_then(|time| NaiveTime::parse_from_str(time.get(..6)?, "%H%M%S").ok())
+        .unwrap_or_default();
+    Some(date.and_time(time))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn dataset(record: u8, dataset: u8, value: &[u8]) -> Vec<u8> {
+        let mut data = vec![0x1C, record, dataset];
+        data.extend_from_slice(&u16::try_from(value.len()).unwrap().to_be_bytes());
+        data.extend_from_slice(value);
+        data
+    }
+
+    /// The Image Resource Block with the name padded to the even size.
+    fn resource(id: u16, name: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut block = b"8BIM".to_vec();
+        block.extend_from_slice(&id.to_be_bytes());
+        block.push(u8::try_from(name.len()).unwrap());
+        block.extend_from_slice(name);
+        if name.len() & 1 == 0 {
+            block.push(0);
+        }
+        block.extend_from_slice(&u32::try_from(data.len()).unwrap().to_be_bytes());
+        block.extend_from_slice(data);
+        if data.len() & 1 == 1 {
+            block.push(0);
+        }
+        block
+    }
+
+    fn text(tags: &Map<String, TagValue>, key: &str) -> String {
+        tags[key].as_text().unwrap().to_owned()
+    }
+
+    #[test]
+    fn repeated_datasets_are_collected_into_list() {
+        let data = [
+            dataset(2, 25, b"travel"),
+            dataset(2, 5, b"Title\0"),
+            dataset(2, 25, b"mountains"),
+            dataset(2, 25, b" sea "),
+            // unknown dataset
+            dataset(2, 200, b"skipped"),
+        ]
+        .concat();
+        let tags = parse_iptc(&data);
+        assert_eq!(
+            tags["iptc:Keywords"],
+            TagValue::List(vec![
+                TagValue::Text("travel".into()),
+                TagValue::Text("mountains".into()),
+                TagValue::Text("sea".into()),
+            ])
+        );
+        assert_eq!(text(&tags, "iptc:ObjectName"), "Title");
+        assert_eq!(tags.len(), 2);
+    }
+
+    #[test]
+    fn utf8_declared_in_envelope_or_latin1() {
+        let latin1 = parse_iptc(&dataset(2, 90, b"K\xf6ln"));
+        assert_eq!(text(&latin1, "iptc:City"), "Köln");
+
+        let data = [dataset(1, 90, b"\x1b%G"), dataset(2, 90, "Київ".as_bytes())].concat();
+        assert_eq!(text(&parse_iptc(&data), "iptc:City"), "Київ");
+    }
+
+    #[test]
+    fn truncated_and_extended_datasets_stop_parsing() {
+        let mut data = dataset(2, 105, b"Headline");
+        let mut truncated = dataset(2, 120, b"Caption is cut");
+        truncated.truncate(10);
+        data.extend_from_slice(&truncated);
+        let tags = parse_iptc(&data);
+        assert_eq!(tags.len(), 1);
+        assert_eq!(text(&tags, "iptc:Headline"), "Headline");
+
+        let mut data = dataset(2, 105, b"Headline");
+        // the extended dataset with 4 bytes of the length
+        data.extend_from_slice(&[0x1C, 2, 202, 0x80, 0x04, 0, 0, 0, 1, 0]);
+        data.extend_from_slice(&dataset(2, 110, b"Credit"));
+        assert_eq!(parse_iptc(&data).len(), 1);
+    }
+
+    #[test]
+    fn date_and_time_are_combined() {
+        let data = [dataset(2, 55, b"20220708"), dataset(2, 60, b"103015+0300")].concat();
+        let expected = NaiveDate::from_ymd_opt(2022, 7, 8)
+            .unwrap()
+            .and_hms_opt(10, 30, 15)
+            .unwrap();
+        assert_eq!(
+            parse_iptc(&data)["iptc:DateTimeCreated"],
+            TagValue::DateTime(expected)
+        );
+
+        let midnight = expected.date().and_hms_opt(0, 0, 0).unwrap();
+        assert_eq!(
+            parse_iptc(&dataset(2, 55, b"20220708"))["iptc:DateTimeCreated"],
+            TagValue::DateTime(midnight)
+        );
+        assert!(!parse_iptc(&dataset(2, 55, b"2022")).contains_key("iptc:DateTimeCreated"));
+    }
+
+    #[test]
+    fn resource_found_after_padded_blocks() {
+        let resources = [
+            resource(0x03ED, b"", b"odd"),
+            resource(0x0400, b"name", b"even"),
+            resource(0x0404, b"x", b"iptc"),
+        ]
+        .concat();
+        assert_eq!(photoshop_resource(&resources, 0x0404), Some(&b"iptc"[..]));
+        assert_eq!(photoshop_resource(&resources, 0x03ED), Some(&b"odd"[..]));
+        assert_eq!(photoshop_resource(&resources, 0x0409), None);
+        // the size exceeding the data
+        assert_eq!(photoshop_resource(&resources[..20], 0x0404), None);
+    }
+
+    #[test]
+    fn tags_read_from_jpeg() {
+        let iptc = dataset(2, 120, b"A caption");
+        let segment = [&b"Photoshop 3.0\0"[..], &resource(0x0404, b"", &iptc)].concat();
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xED];
+        jpeg.extend_from_slice(&u16::try_from(segment.len() + 2).unwrap().to_be_bytes());
+        jpeg.extend_from_slice(&segment);
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0, 2, 0xFF, 0xD9]);
+
+        let path = std::env::temp_dir().join(format!("foton-iptc-{}.jpg", std::process::id()));
+        fs::write(&path, jpeg).unwrap();
+        let tags = get_iptc_tags(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(text(&tags, "iptc:Caption-Abstract"), "A caption");
+    }
+}
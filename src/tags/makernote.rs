@@ -0,0 +1,801 @@
+//! Decoders of the vendor-specific MakerNote EXIF tag.
+//!
+//! The MakerNote is mostly a regular TIFF IFD prepended with a vendor signature.
+//! The vendors differ in the signature, the byte order and the base
+//! of the offsets (either the start of the EXIF data or the MakerNote itself).
+//!
+//! Only the unencrypted tags are decoded: e.g. most of the Sony's
+//! internal data (including the shutter count) is obfuscated and not available.
+use std::collections::HashMap as Map;
+
+use chrono::NaiveDate;
+use exif::{Exif, In, Tag, Value};
+
+use super::TagValue;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Vendor {
+    Apple,
+    Canon,
+    Fujifilm,
+    Nikon,
+    Sony,
+}
+
+impl Vendor {
+    fn detect(make: &str, maker_note: &[u8]) -> Option<Self> {
+        if maker_note.starts_with(b"Apple iOS\0") {
+            return Some(Self::Apple);
+        }
+        if maker_note.starts_with(b"Nikon\0") {
+            return Some(Self::Nikon);
+        }
+        if maker_note.starts_with(b"FUJIFILM") {
+            return Some(Self::Fujifilm);
+        }
+        if maker_note.starts_with(b"SONY DSC ") || maker_note.starts_with(b"SONY CAM ") {
+            return Some(Self::Sony);
+        }
+
+        let make = make.trim().to_lowercase();
+        if make.starts_with("canon") {
+            Some(Self::Canon)
+        } else if make.starts_with("sony") {
+            Some(Self::Sony)
+        } else {
+            None
+        }
+    }
+
+    /// Namespace of the decoded tags, e.g. `canon:LensModel`.
+    fn namespace(self) -> &'static str {
+        match self {
+            Self::Apple => "apple",
+            Self::Canon => "canon",
+            Self::Fujifilm => "fujifilm",
+            Self::Nikon => "nikon",
+            Self::Sony => "sony",
+        }
+    }
+
+    /// The tags which are decoded as is.
+    fn simple_tags(self) -> &'static [(u16, &'static str)] {
+        match self {
+            Self::Apple => APPLE_TAGS,
+            Self::Canon => CANON_TAGS,
+            Self::Fujifilm => FUJIFILM_TAGS,
+            Self::Nikon => NIKON_TAGS,
+            Self::Sony => SONY_TAGS,
+        }
+    }
+}
+
+const APPLE_TAGS: &[(u16, &str)] = &[
+    (0x0001, "MakerNoteVersion"),
+    (0x0008, "AccelerationVector"),
+    (0x000A, "HDRImageType"),
+    (0x000B, "BurstUUID"),
+    (0x0011, "ContentIdentifier"),
+    (0x0014, "ImageCaptureType"),
+    (0x0015, "ImageUniqueID"),
+    (0x0017, "LivePhotoVideoIndex"),
+];
+
+const CANON_TAGS: &[(u16, &str)] = &[
+    (0x0006, "ImageType"),
+    (0x0007, "FirmwareVersion"),
+    (0x0008, "FileNumber"),
+    (0x0009, "OwnerName"),
+    (0x000C, "SerialNumber"),
+    (0x0010, "ModelID"),
+    (0x0095, "LensModel"),
+    (0x0096, "InternalSerialNumber"),
+];
+
+const FUJIFILM_TAGS: &[(u16, &str)] = &[
+    (0x0000, "Version"),
+    (0x0010, "InternalSerialNumber"),
+    (0x1000, "Quality"),
+    (0x1401, "DynamicRange"),
+    (0x1404, "MinFocalLength"),
+    (0x1405, "MaxFocalLength"),
+    (0x1422, "ImageStabilization"),
+    (0x1431, "Rating"),
+];
+
+const NIKON_TAGS: &[(u16, &str)] = &[
+    (0x0001, "MakerNoteVersion"),
+    (0x0002, "ISO"),
+    (0x0004, "Quality"),
+    (0x001D, "SerialNumber"),
+    (0x0083, "LensType"),
+    (0x0084, "Lens"),
+    (0x00A7, "ShutterCount"),
+];
+
+const SONY_TAGS: &[(u16, &str)] = &[
+    (0x0102, "Quality"),
+    (0x0104, "FlashExposureComp"),
+    (0x0115, "WhiteBalance"),
+    (0x2002, "Rating"),
+    (0x2031, "SerialNumber"),
+    (0xB000, "FileFormat"),
+    (0xB001, "SonyModelID"),
+    (0xB027, "LensType"),
+    (0xB02A, "LensSpec"),
+];
+
+/// Decode the MakerNote of the supported vendors into the
+/// `vendor:Name` tags, e.g. `nikon:ShutterCount` or `apple:ContentIdentifier`.
+///
+/// The time zone settings of Canon and Nikon cameras are exposed
+/// as `vendor:UtcOffset` (e.g. `+03:00`) including the daylight saving time.
+pub(crate) fn decode_maker_note(exif: &Exif) -> Map<String, TagValue> {
+    let mut tags = Map::new();
+    let Some(field) = exif.get_field(Tag::MakerNote, In::PRIMARY) else {
+        return tags;
+    };
+    let Value::Undefined(maker_note, offset) = &field.value else {
+        return tags;
+    };
+    let make = exif
+        .get_field(Tag::Make, In::PRIMARY)
+        .map(|f| TagValue::from_exif(&f.value).to_string())
+        .unwrap_or_default();
+    let Some(vendor) = Vendor::detect(&make, maker_note) else {
+        return tags;
+    };
+    let Some(ifd) = vendor_ifd(vendor, exif, maker_note, *offset) else {
+        return tags;
+    };
+
+    let namespace = vendor.namespace();
+    let mut insert = |name: &str, value: TagValue| {
+        tags.insert(format!("{}:{}", namespace, name), value);
+    };
+    for entry in ifd.entries() {
+        if let Some((_, name)) = vendor.simple_tags().iter().find(|(t, _)| *t == entry.tag) {
+            if let Some(value) = entry.value() {
+                insert(name, value);
+            }
+        }
+
+        match (vendor, entry.tag) {
+            (Vendor::Canon, 0x0001) => decode_canon_camera_settings(&entry, &mut insert),
+            (Vendor::Canon, 0x0035) => decode_canon_time_info(&entry, &mut insert),
+            (Vendor::Nikon, 0x0024) => decode_nikon_world_time(&entry, &mut insert),
+            (Vendor::Nikon, 0x00B6) => decode_nikon_power_up_time(&entry, &mut insert),
+            (Vendor::Fujifilm, 0x1438) => {
+                // the highest bit has some unknown meaning
+                if let Some(&count) = entry.u16s().first() {
+                    insert("ImageCount", TagValue::Integer((count & 0x7FFF).into()));
+                }
+            }
+            _ => {}
+        }
+    }
+    tags
+}
+
+/// Locate the IFD inside the MakerNote.
+///
+/// The `offset` is the position of the MakerNote inside the EXIF data.
+fn vendor_ifd<'d>(
+    vendor: Vendor,
+    exif: &'d Exif,
+    maker_note: &'d [u8],
+    offset: u32,
+) -> Option<Ifd<'d>> {
+    let offset = usize::try_from(offset).ok()?;
+    let exif_ifd = |start| Ifd {
+        data: exif.buf(),
+        start,
+        big_endian: !exif.little_endian(),
+    };
+
+    match vendor {
+        // `Apple iOS\0`, 2-byte version, `MM` and the IFD
+        // with the offsets relative to the MakerNote start
+        Vendor::Apple => Some(Ifd {
+            data: maker_note,
+            start: 14,
+            big_endian: maker_note.get(12..14)? == b"MM",
+        }),
+        // `Nikon\0`, 4-byte version and the complete TIFF structure
+        Vendor::Nikon => {
+            let tiff = maker_note.get(10..)?;
+            let big_endian = match tiff.get(..2)? {
+                b"MM" => true,
+                b"II" => false,
+                _ => return None,
+            };
+            let start = read_u32(tiff, 4, big_endian)?;
+            Some(Ifd {
+                data: tiff,
+                start: usize::try_from(start).ok()?,
+                big_endian,
+            })
+        }
+        // `FUJIFILM`, the offset of the IFD, always little-endian
+        Vendor::Fujifilm => {
+            let start = read_u32(maker_note, 8, false)?;
+            Some(Ifd {
+                data: maker_note,
+                start: usize::try_from(start).ok()?,
+                big_endian: false,
+            })
+        }
+        // 12-byte signature (missing for some models), the offsets are relative to the EXIF
+        Vendor::Sony => {
+            let header = if maker_note.starts_with(b"SONY") {
+                12
+            } else {
+                0
+            };
+            Some(exif_ifd(offset + header))
+        }
+        // no signature, the offsets are relative to the EXIF
+        Vendor::Canon => Some(exif_ifd(offset)),
+    }
+}
+
+/// Lens information from the Canon `CameraSettings` array.
+fn decode_canon_camera_settings(entry: &Entry<'_>, insert: &mut impl FnMut(&str, TagValue)) {
+    const LENS_TYPE: usize = 22;
+    const MAX_FOCAL_LENGTH: usize = 23;
+    const MIN_FOCAL_LENGTH: usize = 24;
+    const FOCAL_UNITS: usize = 25;
+
+    let settings = entry.u16s();
+    if let Some(&lens) = settings.get(LENS_TYPE) {
+        insert("LensType", TagValue::Integer(lens.into()));
+    }
+    let units = settings.get(FOCAL_UNITS).copied().filter(|u| *u > 0);
+    if let Some(units) = units {
+        for (index, name) in [
+            (MIN_FOCAL_LENGTH, "MinFocalLength"),
+            (MAX_FOCAL_LENGTH, "MaxFocalLength"),
+        ] {
+            if let Some(&focal) = settings.get(index).filter(|f| **f > 0) {
+                insert(name, TagValue::Float(f64::from(focal) / f64::from(units)));
+            }
+        }
+    }
+}
+
+/// The Canon `TimeInfo`: the time zone (in minutes),
+/// the time zone city and the daylight saving time (0 or 60 minutes).
+fn decode_canon_time_info(entry: &Entry<'_>, insert: &mut impl FnMut(&str, TagValue)) {
+    let info = entry.i32s();
+    let (Some(&time_zone), Some(&dst)) = (info.get(1), info.get(3)) else {
+        return;
+    };
+    insert("TimeZone", format_utc_offset(time_zone).into());
+    if let Some(&city) = info.get(2) {
+        insert("TimeZoneCity", TagValue::Integer(city.into()));
+    }
+    insert("DaylightSavings", TagValue::Integer(dst.into()));
+    insert("UtcOffset", format_utc_offset(time_zone + dst).into());
+}
+
+/// The Nikon `WorldTime`: the time zone (in minutes),
+/// the daylight saving flag and the date display format.
+fn decode_nikon_world_time(entry: &Entry<'_>, insert: &mut impl FnMut(&str, TagValue)) {
+    let Some(time_zone) = read_u16(entry.data, 0, entry.big_endian) else {
+        return;
+    };
+    let time_zone = i32::from(time_zone.cast_signed());
+    let dst = entry.data.get(2).is_some_and(|dst| *dst != 0);
+    insert("TimeZone", format_utc_offset(time_zone).into());
+    insert("DaylightSavings", TagValue::Integer(i64::from(dst)));
+    let dst_minutes = if dst { 60 } else { 0 };
+    insert(
+        "UtcOffset",
+        format_utc_offset(time_zone + dst_minutes).into(),
+    );
+}
+
+/// The Nikon `PowerUpTime`: the time when the camera was switched on.
+fn decode_nikon_power_up_time(entry: &Entry<'_>, insert: &mut impl FnMut(&str, TagValue)) {
+    let &[y1, y2, month, day, hour, minute, second, ..] = entry.data else {
+        return;
+    };
+    let year = u16::from_be_bytes([y1, y2]);
+    let time = NaiveDate::from_ymd_opt(year.into(), month.into(), day.into())
+        .and_then(|date| date.and_hms_opt(hour.into(), minute.into(), second.into()));
+    if let Some(time) = time {
+        insert("PowerUpTime", TagValue::DateTime(time));
+    }
+}
+
+fn format_utc_offset(minutes: i32) -> String {
+    let sign = if minutes < 0 { '-' } else { '+' };
+    let minutes = minutes.unsigned_abs();
+    format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+}
+
+/// TIFF Image File Directory.
+#[derive(Debug, Copy, Clone)]
+struct Ifd<'d> {
+    /// The data which the offsets inside the IFD are relative to.
+    data: &'d [u8],
+    /// The position of the IFD inside the data.
+    start: usize,
+    big_endian: bool,
+}
+
+/// Single tag of the IFD.
+#[derive(Debug, Copy, Clone)]
+struct Entry<'d> {
+    tag: u16,
+    type_: u16,
+    data: &'d [u8],
+    big_endian: bool,
+}
+
+impl<'d> Ifd<'d> {
+    /// Protection against the garbage data.
+    const MAX_ENTRIES: u16 = 1000;
+
+    fn entries(self) -> impl Iterator<Item = Entry<'d>> {
+        let count = read_u16(self.data, self.start, self.big_endian)
+            .filter(|count| *count <= Self::MAX_ENTRIES)
+            .unwrap_or_default();
+        (0..usize::from(count)).filter_map(move |i| self.entry(self.start + 2 + i * 12))
+    }
+
+    fn entry(self, at: usize) -> Option<Entry<'d>> {
+        let tag = read_u16(self.data, at, self.big_endian)?;
+        let type_ = read_u16(self.data, at + 2, self.big_endian)?;
+        let count = usize::try_from(read_u32(self.data, at + 4, self.big_endian)?).ok()?;
+        let size = type_size(type_)?.checked_mul(count)?;
+        let data = if size <= 4 {
+            self.data.get(at + 8..at + 8 + size)?
+        } else {
+            let offset = usize::try_from(read_u32(self.data, at + 8, self.big_endian)?).ok()?;
+            self.data.get(offset..offset.checked_add(size)?)?
+        };
+        Some(Entry {
+            tag,
+            type_,
+            data,
+            big_endian: self.big_endian,
+        })
+    }
+}
+
+impl Entry<'_> {
+    const BYTE: u16 = 1;
+    const ASCII: u16 = 2;
+    const SHORT: u16 = 3;
+    const LONG: u16 = 4;
+    const RATIONAL: u16 = 5;
+    const SBYTE: u16 = 6;
+    const SSHORT: u16 = 8;
+    const SLONG: u16 = 9;
+    const SRATIONAL: u16 = 10;
+    const FLOAT: u16 = 11;
+    const DOUBLE: u16 = 12;
+
+    fn u16s(&self) -> Vec<u16> {
+        if self.type_ != Self::SHORT && self.type_ != Self::SSHORT {
+            return vec![];
+        }
+        (0..self.data.len() / 2)
+            .filter_map(|i| read_u16(self.data, i * 2, self.big_endian))
+            .collect()
+    }
+
+    fn i32s(&self) -> Vec<i32> {
+        if self.type_ != Self::LONG && self.type_ != Self::SLONG {
+            return vec![];
+        }
+        (0..self.data.len() / 4)
+            .filter_map(|i| read_u32(self.data, i * 4, self.big_endian))
+            .map(u32::cast_signed)
+            .collect()
+    }
+
+    /// Generic conversion of the value according to its type.
+    fn value(&self) -> Option<TagValue> {
+        let (data, be) = (self.data, self.big_endian);
+        let chunks = |size: usize| data.chunks_exact(size);
+        let values: Vec<TagValue> = match self.type_ {
+            Self::ASCII => {
+                let text = data.split(|b| *b == 0).next().unwrap_or_default();
+                let text = String::from_utf8_lossy(text).trim().to_owned();
+                return (!text.is_empty()).then_some(TagValue::Text(text));
+            }
+            Self::BYTE => data
+                .iter()
+                .map(|b| TagValue::Integer((*b).into()))
+                .collect(),
+            Self::SBYTE => data
+                .iter()
+                .map(|b| TagValue::Integer(b.cast_signed().into()))
+                .collect(),
+            Self::SHORT => chunks(2)
+                .filter_map(|c| read_u16(c, 0, be))
+                .map(|x| TagValue::Integer(x.into()))
+                .collect(),
+            Self::SSHORT => chunks(2)
+                .filter_map(|c| read_u16(c, 0, be))
+                .map(|x| TagValue::Integer(x.cast_signed().into()))
+                .collect(),
+            Self::LONG => chunks(4)
+                .filter_map(|c| read_u32(c, 0, be))
+                .map(|x| TagValue::Integer(x.into()))
+                .collect(),
+            Self::SLONG => chunks(4)
+                .filter_map(|c| read_u32(c, 0, be))
+                .map(|x| TagValue::Integer(x.cast_signed().into()))
+                .collect(),
+            Self::RATIONAL => chunks(8)
+                .filter_map(|c| Some((read_u32(c, 0, be)?, read_u32(c, 4, be)?)))
+                .map(|(n, d)| TagValue::Rational {
+                    numerator: n.into(),
+                    denominator: d.into(),
+                })
+                .collect(),
+            Self::SRATIONAL => chunks(8)
+                .filter_map(|c| Some((read_u32(c, 0, be)?, read_u32(c, 4, be)?)))
+                .map(|(n, d)| TagValue::Rational {
+                    numerator: n.cast_signed().into(),
+                    denominator: d.cast_signed().into(),
+                })
+                .collect(),
+            Self::FLOAT => chunks(4)
+                .filter_map(|c| read_u32(c, 0, be))
+                .map(|x| TagValue::Float(f32::from_bits(x).into()))
+                .collect(),
+            Self::DOUBLE => chunks(8)
+                .filter_map(|c| c.try_into().ok())
+                .map(|c| {
+                    TagValue::Float(if be {
+                        f64::from_be_bytes(c)
+                    } else {
+                        f64::from_le_bytes(c)
+                    })
+                })
+                .collect(),
+            _ => return Some(TagValue::Binary(data.to_vec())),
+        };
+
+        match <[_; 1]>::try_from(values) {
+            Ok([single]) => Some(single),
+            Err(values) if values.is_empty() => None,
+            Err(values) => Some(TagValue::List(values)),
+        }
+    }
+}
+
+fn type_size(type_: u16) -> Option<usize> {
+    match type_ {
+        1 | 2 | 6 | 7 => Some(1),
+        3 | 8 => Some(2),
+        4 | 9 | 11 => Some(4),
+        5 | 10 | 12 => Some(8),
+        _ => None,
+    }
+}
+
+fn read_u16(data: &[u8], at: usize, big_endian: bool) -> Option<u16> {
+    let bytes = data.get(at..at.checked_add(2)?)?.try_into().ok()?;
+    Some(if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    })
+}
+
+fn read_u32(data: &[u8], at: usize, big_endian: bool) -> Option<u32> {
+    let bytes = data.get(at..at.checked_add(4)?)?.try_into().ok()?;
+    Some(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use exif::Reader;
+
+    use super::*;
+
+    const UNDEFINED: u16 = 7;
+
+    fn shorts(values: &[u16], big_endian: bool) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|x| {
+                if big_endian {
+                    x.to_be_bytes()
+                } else {
+                    x.to_le_bytes()
+                }
+            })
+            .collect()
+    }
+
+    fn longs(values: &[u32], big_endian: bool) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|x| {
+                if big_endian {
+                    x.to_be_bytes()
+                } else {
+                    x.to_le_bytes()
+                }
+            })
+            .collect()
+    }
+
+    /// The IFD with the values longer than 4 bytes placed right after it.
+    ///
+    /// The `base` is the position of the IFD in the data the offsets are relative to.
+    fn ifd(entries: &[(u16, u16, Vec<u8>)], big_endian: bool, base: usize) -> Vec<u8> {
+        let count = u16::try_from(entries.len()).unwrap();
+        let mut ifd = shorts(&[count], big_endian);
+        let mut external = vec![];
+        let external_start = base + 2 + entries.len() * 12 + 4;
+        for (tag, type_, value) in entries {
+            let count = value.len() / type_size(*type_).unwrap();
+            ifd.extend(shorts(&[*tag, *type_], big_endian));
+            ifd.extend(longs(&[u32::try_from(count).unwrap()], big_endian));
+            if value.len() <= 4 {
+                let mut inline = value.clone();
+                inline.resize(4, 0);
+                ifd.extend(inline);
+            } else {
+                let offset = u32::try_from(external_start + external.len()).unwrap();
+                ifd.extend(longs(&[offset], big_endian));
+                external.extend_from_slice(value);
+                if external.len() & 1 == 1 {
+                    external.push(0);
+                }
+            }
+        }
+        ifd.extend(longs(&[0], big_endian));
+        ifd.extend(external);
+        ifd
+    }
+
+    /// The EXIF with the `Make` and the `MakerNote` built for its offset in the TIFF data.
+    fn exif(make: &str, big_endian: bool, maker_note: impl Fn(usize) -> Vec<u8>) -> Exif {
+        let make = format!("{}\0", make).into_bytes();
+        let ifd0 = |exif_ifd: u32| {
+            ifd(
+                &[
+                    (0x010F, Entry::ASCII, make.clone()),
+                    (0x8769, Entry::LONG, longs(&[exif_ifd], big_endian)),
+                ],
+                big_endian,
+                8,
+            )
+        };
+        let exif_ifd = 8 + ifd0(0).len();
+        // a single entry IFD followed by the MakerNote
+        let maker_note = maker_note(exif_ifd + 18);
+
+        let mut tiff = if big_endian {
+            b"MM".to_vec()
+        } else {
+            b"II".to_vec()
+        };
+        tiff.extend(shorts(&[42], big_endian));
+        tiff.extend(longs(&[8], big_endian));
+        tiff.extend(ifd0(u32::try_from(exif_ifd).unwrap()));
+        tiff.extend(ifd(
+            &[(0x927C, UNDEFINED, maker_note)],
+            big_endian,
+            exif_ifd,
+        ));
+        Reader::new().read_raw(tiff).unwrap()
+    }
+
+    fn text(value: &str) -> TagValue {
+        TagValue::Text(value.into())
+    }
+
+    #[test]
+    fn apple_offsets_relative_to_maker_note() {
+        let exif = exif("Apple", false, |_| {
+            let mut note = b"Apple iOS\0\0\x01MM".to_vec();
+            note.extend(ifd(
+                &[
+                    (0x0001, Entry::SLONG, longs(&[14], true)),
+                    (0x0011, Entry::ASCII, b"8C1B9A4E-LIVE-PHOTO\0".to_vec()),
+                    (0x0099, Entry::ASCII, b"not decoded\0".to_vec()),
+                ],
+                true,
+                14,
+            ));
+            note
+        });
+        let tags = decode_maker_note(&exif);
+        assert_eq!(tags["apple:MakerNoteVersion"], TagValue::Integer(14));
+        assert_eq!(tags["apple:ContentIdentifier"], text("8C1B9A4E-LIVE-PHOTO"));
+        assert_eq!(tags.len(), 2);
+    }
+
+    #[test]
+    fn nikon_embedded_tiff() {
+        for big_endian in [true, false] {
+            let exif = exif("NIKON CORPORATION", !big_endian, |_| {
+                let mut note = b"Nikon\0\x02\x10\0\0".to_vec();
+                note.extend(if big_endian { b"MM" } else { b"II" });
+                note.extend(shorts(&[42], big_endian));
+                note.extend(longs(&[8], big_endian));
+                let mut world_time = shorts(&[(-300_i16).cast_unsigned()], big_endian);
+                world_time.extend([1, 0]);
+                note.extend(ifd(
+                    &[
+                        (0x0024, UNDEFINED, world_time),
+                        (0x00A7, Entry::LONG, longs(&[12345], big_endian)),
+                        (0x00B6, UNDEFINED, vec![0x07, 0xE6, 7, 8, 10, 30, 15, 0]),
+                    ],
+                    big_endian,
+                    8,
+                ));
+                note
+            });
+            let tags = decode_maker_note(&exif);
+            assert_eq!(tags["nikon:ShutterCount"], TagValue::Integer(12345));
+            assert_eq!(tags["nikon:TimeZone"], "-05:00".into());
+            assert_eq!(tags["nikon:DaylightSavings"], TagValue::Integer(1));
+            assert_eq!(tags["nikon:UtcOffset"], "-04:00".into());
+            let power_up = NaiveDate::from_ymd_opt(2022, 7, 8)
+                .unwrap()
+                .and_hms_opt(10, 30, 15)
+                .unwrap();
+            assert_eq!(tags["nikon:PowerUpTime"], TagValue::DateTime(power_up));
+        }
+    }
+
+    #[test]
+    fn canon_offsets_relative_to_exif() {
+        for big_endian in [false, true] {
+            let exif = exif("Canon", big_endian, |offset| {
+                let mut settings = [0; 26];
+                settings[22] = 61182;
+                settings[23] = 105;
+                settings[24] = 24;
+                settings[25] = 1;
+                ifd(
+                    &[
+                        (0x0001, Entry::SHORT, shorts(&settings, big_endian)),
+                        (0x0035, Entry::SLONG, longs(&[16, 120, 5, 60], big_endian)),
+                        (0x0095, Entry::ASCII, b"RF24-105mm F4 L IS USM\0".to_vec()),
+                    ],
+                    big_endian,
+                    offset,
+                )
+            });
+            let tags = decode_maker_note(&exif);
+            assert_eq!(tags["canon:LensModel"], text("RF24-105mm F4 L IS USM"));
+            assert_eq!(tags["canon:LensType"], TagValue::Integer(61182));
+            assert_eq!(tags["canon:MinFocalLength"], TagValue::Float(24.0));
+            assert_eq!(tags["canon:MaxFocalLength"], TagValue::Float(105.0));
+            assert_eq!(tags["canon:TimeZone"], "+02:00".into());
+            assert_eq!(tags["canon:TimeZoneCity"], TagValue::Integer(5));
+            assert_eq!(tags["canon:DaylightSavings"], TagValue::Integer(60));
+            assert_eq!(tags["canon:UtcOffset"], "+03:00".into());
+        }
+    }
+
+    #[test]
+    fn fujifilm_always_little_endian() {
+        let exif = exif("FUJIFILM", true, |_| {
+            let mut note = b"FUJIFILM".to_vec();
+            note.extend(longs(&[12], false));
+            note.extend(ifd(
+                &[
+                    (0x0000, UNDEFINED, b"0130".to_vec()),
+                    (0x1438, Entry::SHORT, shorts(&[0x8000 | 321], false)),
+                ],
+                false,
+                12,
+            ));
+            note
+        });
+        let tags = decode_maker_note(&exif);
+        assert_eq!(tags["fujifilm:Version"], TagValue::Binary(b"0130".to_vec()));
+        assert_eq!(tags["fujifilm:ImageCount"], TagValue::Integer(321));
+    }
+
+    #[test]
+    fn sony_with_or_without_signature() {
+        let entries = |big_endian| {
+            vec![
+                (0x2002, Entry::LONG, longs(&[3], big_endian)),
+                (0xB001, Entry::SHORT, shorts(&[380], big_endian)),
+            ]
+        };
+        let signed = exif("SONY", false, |offset| {
+            let mut note = b"SONY DSC \0\0\0".to_vec();
+            note.extend(ifd(&entries(false), false, offset + 12));
+            note
+        });
+        let unsigned = exif("SONY", false, |offset| ifd(&entries(false), false, offset));
+        for exif in [signed, unsigned] {
+            let tags = decode_maker_note(&exif);
+            assert_eq!(tags["sony:Rating"], TagValue::Integer(3));
+            assert_eq!(tags["sony:SonyModelID"], TagValue::Integer(380));
+        }
+    }
+
+    #[test]
+    fn unknown_or_garbage_maker_notes() {
+        let unknown = exif("Pentax", false, |offset| {
+            ifd(
+                &[(0x0001, Entry::SHORT, shorts(&[1], false))],
+                false,
+                offset,
+            )
+        });
+        assert!(decode_maker_note(&unknown).is_empty());
+
+        for note in [
+            &b"Nikon\0\x02\x10\0\0XX\0\x2a"[..],
+            b"Nikon\0",
+            b"FUJIFILM\xff\xff\xff\xff",
+            b"Apple iOS\0\0\x01MM\xff",
+        ] {
+            let exif = exif("", false, |_| note.to_vec());
+            assert!(decode_maker_note(&exif).is_empty(), "{:?}", note);
+        }
+        // too many entries
+        let exif = exif("Canon", false, |_| shorts(&[5000], false));
+        assert!(decode_maker_note(&exif).is_empty());
+    }
+
+    #[test]
+    fn values_by_types() {
+        let entry = |type_, data: &'static [u8]| Entry {
+            tag: 0,
+            type_,
+            data,
+            big_endian: true,
+        };
+        assert_eq!(
+            entry(Entry::BYTE, &[1, 2]).value(),
+            Some(TagValue::List(vec![
+                TagValue::Integer(1),
+                TagValue::Integer(2)
+            ]))
+        );
+        assert_eq!(
+            entry(Entry::SSHORT, &[0xFF, 0xFE]).value(),
+            Some(TagValue::Integer(-2))
+        );
+        assert_eq!(
+            entry(Entry::SRATIONAL, &[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 3]).value(),
+            Some(TagValue::Rational {
+                numerator: -1,
+                denominator: 3
+            })
+        );
+        assert_eq!(
+            entry(Entry::FLOAT, &[0x3F, 0xC0, 0, 0]).value(),
+            Some(TagValue::Float(1.5))
+        );
+        assert_eq!(
+            entry(Entry::DOUBLE, &[0xC0, 0x04, 0, 0, 0, 0, 0, 0]).value(),
+            Some(TagValue::Float(-2.5))
+        );
+        assert_eq!(entry(Entry::ASCII, b"  \0").value(), None);
+        assert_eq!(entry(Entry::SHORT, &[]).value(), None);
+        assert_eq!(
+            entry(UNDEFINED, &[1, 2, 3]).value(),
+            Some(TagValue::Binary(vec![1, 2, 3]))
+        );
+    }
+
+    #[test]
+    fn utc_offsets() {
+        assert_eq!(format_utc_offset(0), "+00:00");
+        assert_eq!(format_utc_offset(345), "+05:45");
+        assert_eq!(format_utc_offset(-570), "-09:30");
+    }
+}
@@ -0,0 +1,316 @@
+//! Metadata of the media files.
+//!
+//! Every tag is identified by the stable `namespace:Name` identifier, e.g.:
+//! - `exif:DateTimeOriginal` for the EXIF tags;
+//! - `ffmpeg:creation_time` for the video container metadata;
+//! - `xmp:CreateDate` or `dc:subject` for the XMP properties;
+//! - `iptc:Keywords` for the IPTC-IIM datasets;
+//! - `canon:LensModel` or `nikon:ShutterCount` for the MakerNote data.
+use std::{
+    collections::HashMap as Map,
+    fs::File,
+    io::{self, BufReader, Read as _},
+    path::Path,
+};
+
+use exif::{Exif, In, Reader, Tag};
+use ffmpeg_next::format::context::Input;
+use log::debug;
+use once_cell::sync::Lazy;
+
+use crate::{file_types::Media, AnyError, MediaType};
+
+pub use self::{
+    iptc::get_iptc_tags,
+    registry::{known_tags, lookup_exif_tag, lookup_number, lookup_tag, KnownTag},
+    value::TagValue,
+};
+pub(crate) use self::{
+    makernote::decode_maker_note,
+    xmp::{jpeg_exif_and_xmp, parse_xmp, read_xmp_packet},
+};
+
+mod iptc;
+mod makernote;
+mod registry;
+mod value;
+mod xmp;
+
+/// Namespace of the EXIF tags.
+const EXIF_NAMESPACE: &str = "exif";
+
+/// Namespace of the metadata extracted by the `ffmpeg`.
+const FFMPEG_NAMESPACE: &str = "ffmpeg";
+
+/// Read the raw EXIF data of an image by its path.
+pub(crate) fn read_exif<P>(path: &P) -> Result<Exif, AnyError>
+where
+    P: AsRef<Path> + ?Sized,
+{
+    let mut file = BufReader::new(File::open(path)?);
+    Ok(Reader::new().read_from_container(&mut file)?)
+}
+
+/// Read the data of the first JPEG segment of the given type
+/// which starts with the signature (the signature itself is stripped).
+pub(crate) fn read_jpeg_segment<P>(
+    path: &P,
+    marker: u8,
+    signature: &[u8],
+) -> io::Result<Option<Vec<u8>>>
+where
+    P: AsRef<Path> + ?Sized,
+{
+    let segments = read_jpeg_segments(path, &[(marker, signature)])?;
+    Ok(segments.and_then(|mut found| found.pop().flatten()))
+}
+
+/// Read the data of the first JPEG segments of the given types
+/// which start with the signatures in a single pass over the file.
+///
+/// The found segments are returned in the order of the requested ones
+/// or `None` if the file is not a JPEG at all.
+pub(crate) fn read_jpeg_segments<P>(
+    path: &P,
+    wanted: &[(u8, &[u8])],
+) -> io::Result<Option<Vec<Option<Vec<u8>>>>>
+where
+    P: AsRef<Path> + ?Sized,
+{
+    const SOI: u8 = 0xD8;
+    const SOS: u8 = 0xDA;
+
+    let mut file = BufReader::new(File::open(path)?);
+    let mut header = [0; 2];
+    file.read_exact(&mut header)?;
+    if header != [0xFF, SOI] {
+        return Ok(None);
+    }
+
+    let mut found = vec![None; wanted.len()];
+    while found.iter().any(Option::is_none) {
+        file.read_exact(&mut header)?;
+        // the metadata segments are always located before the image data
+        if header[0] != 0xFF || header[1] == SOS {
+            break;
+        }
+        let mut len = [0; 2];
+        file.read_exact(&mut len)?;
+        let len = u64::from(u16::from_be_bytes(len).saturating_sub(2));
+        if wanted.iter().any(|(marker, _)| *marker == header[1]) {
+            let mut segment = vec![];
+            (&mut file).take(len).read_to_end(&mut segment)?;
+            for ((marker, signature), found) in wanted.iter().zip(&mut found) {
+                if found.is_none() && *marker == header[1] {
+                    if let Some(data) = segment.strip_prefix(*signature) {
+                        *found = Some(data.to_vec());
+                    }
+                }
+            }
+        } else {
+            file.seek_relative(i64::try_from(len).unwrap_or(i64::MAX))?;
+        }
+    }
+    Ok(Some(found))
+}
+
+/// Get EXIF tags of an image by its path.
+///
+/// The tags are named as `exif:Name`, e.g. `exif:DateTimeOriginal`.
+/// If the same tag present both in the main image and in the thumbnail,
+/// the main image's one is used.
+///
+/// The GPS coordinates are converted into the [decimal degrees][TagValue::Gps]
+/// taking into account their references (`N`/`S`, `E`/`W`).
+///
+/// The MakerNote of Apple, Canon, Fujifilm, Nikon and Sony cameras
+/// is decoded into the `vendor:Name` tags, e.g. `nikon:ShutterCount`.
+pub fn get_image_tags<P>(path: &P) -> Result<Map<String, TagValue>, AnyError>
+where
+    P: AsRef<Path> + ?Sized,
+{
+    let exif = read_exif(path)?;
+    let mut tags = Map::new();
+    for field in exif.fields() {
+        let value = exif_field_value(&exif, field.tag, &field.value);
+        tags.entry(exif_tag_id(field.tag)).or_insert(value);
+    }
+    tags.extend(decode_maker_note(&exif));
+    Ok(tags)
+}
+
+fn exif_field_value(exif: &Exif, tag: Tag, value: &exif::Value) -> TagValue {
+    let reference = |ref_tag| {
+        exif.get_field(ref_tag, In::PRIMARY)
+            .map(|f| TagValue::from_exif(&f.value))
+    };
+    let is_negative = |ref_tag, negative: &str| {
+        reference(ref_tag)
+            .and_then(|r| r.as_text().map(|r| r.eq_ignore_ascii_case(negative)))
+            .unwrap_or_default()
+    };
+
+    let value = TagValue::from_exif(value);
+    let negate = match tag {
+        Tag::GPSLatitude => is_negative(Tag::GPSLatitudeRef, "S"),
+        Tag::GPSLongitude => is_negative(Tag::GPSLongitudeRef, "W"),
+        Tag::GPSAltitude => {
+            let below_sea_level = reference(Tag::GPSAltitudeRef)
+                .and_then(|r| r.as_i64())
+                .is_some_and(|r| r == 1);
+            return match value.as_f64() {
+                Some(alt) if below_sea_level => TagValue::Float(-alt),
+                Some(alt) => TagValue::Float(alt),
+                None => value,
+            };
+        }
+        _ => return value,
+    };
+
+    // degrees, minutes and seconds
+    let TagValue::List(parts) = &value else {
+        return value;
+    };
+    let degrees: Option<f64> = parts
+        .iter()
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(part, divisor)| part.as_f64().map(|x| x / divisor))
+        .sum();
+    match degrees {
+        Some(degrees) if negate => TagValue::Gps(-degrees),
+        Some(degrees) => TagValue::Gps(degrees),
+        None => value,
+    }
+}
+
+/// Stable identifier of the EXIF tag, e.g. `exif:DateTimeOriginal`.
+///
+/// The unknown tags are identified by their hexadecimal numbers, e.g. `exif:0xA500`.
+pub fn exif_tag_id(tag: Tag) -> String {
+    lookup_exif_tag(tag).map_or_else(
+        || format!("{}:0x{:04X}", EXIF_NAMESPACE, tag.number()),
+        |known| known.id().to_owned(),
+    )
+}
+
+/// Find the [EXIF tag][Tag] by its identifier, name, description or number.
+///
+/// See [`lookup_tag`] for the details.
+pub fn find_exif_tag(query: &str) -> Option<Tag> {
+    lookup_tag(query).map(KnownTag::tag)
+}
+
+/// Human-readable description of a tag by its identifier (if known).
+pub fn tag_description(id: &str) -> Option<&'static str> {
+    lookup_tag(id)
+        .filter(|known| known.id() == id)
+        .map(KnownTag::description)
+}
+
+/// Find the tag either by its identifier (`exif:DateTimeOriginal`)
+/// or, for the sake of compatibility, by:
+/// - the EXIF tag name or description (`Date and time of original data generation`);
+/// - the `ffmpeg` key without a namespace (`creation_time`).
+pub fn find_tag<'t>(tags: &'t Map<String, TagValue>, name: &str) -> Option<&'t TagValue> {
+    if let Some(value) = tags.get(name) {
+        return Some(value);
+    }
+    if let Some(known) = lookup_tag(name) {
+        if let Some(value) = tags.get(known.id()) {
+            return Some(value);
+        }
+    }
+    if !name.contains(':') {
+        return tags.get(&format!("{}:{}", FFMPEG_NAMESPACE, name));
+    }
+    None
+}
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct FFMpeg;
+
+impl FFMpeg {
+    /// Open the media container.
+    pub(crate) fn input<P>(self, path: &P) -> Result<Input, ffmpeg_next::Error>
+    where
+        P: AsRef<Path> + ?Sized,
+    {
+        ffmpeg_next::format::input(path)
+    }
+
+    fn get_metadata<P>(self, path: &P) -> Result<Map<String, TagValue>, ffmpeg_next::Error>
+    where
+        P: AsRef<Path> + ?Sized,
+    {
+        self.input(path).map(|ctx| {
+            ctx.metadata()
+                .into_iter()
+                .map(|(k, v)| {
+                    (
+                        format!("{}:{}", FFMPEG_NAMESPACE, k),
+                        TagValue::from_text(v),
+                    )
+                })
+                .collect()
+        })
+    }
+}
+
+pub(crate) static FFMPEG: Lazy<FFMpeg> = Lazy::new(|| {
+    ffmpeg_next::init().expect("could not initialize ffmpeg");
+    FFMpeg
+});
+
+impl Media {
+    /// Fetch the tags' collection from a media
+    /// merged with the tags from its [sidecars][Self::sidecars].
+    pub fn get_tags(&self) -> Result<Map<String, TagValue>, AnyError> {
+        self.merge_sidecar_tags(self.get_embedded_tags())
+    }
+
+    /// Fetch the tags' collection from a media file itself ignoring the sidecars.
+    ///
+    /// Besides the EXIF (or container metadata for videos),
+    /// the [XMP tags][Self::get_xmp_tags] and the [IPTC tags][get_iptc_tags] are included.
+    /// If the same tag is found in the EXIF and XMP, the EXIF one is used.
+    pub fn get_embedded_tags(&self) -> Result<Map<String, TagValue>, AnyError> {
+        let main: Result<Map<String, TagValue>, AnyError> = match self.type_ {
+            MediaType::Photo | MediaType::Animation => get_image_tags(self.path()),
+            MediaType::Video => FFMPEG.get_metadata(self.path()).map_err(Into::into),
+        };
+
+        let mut extra = Map::new();
+        let iptc = match self.type_ {
+            MediaType::Photo => get_iptc_tags(self.path()),
+            MediaType::Animation | MediaType::Video => Ok(Map::new()),
+        };
+        for (kind, tags) in [("IPTC", iptc), ("XMP", self.get_xmp_tags())] {
+            match tags {
+                Ok(tags) => extra.extend(tags),
+                Err(err) => debug!("Failed to get {} tags for {}: {:?}", kind, self, err),
+            }
+        }
+
+        match main {
+            Ok(tags) => {
+                extra.extend(tags);
+                Ok(extra)
+            }
+            Err(err) if extra.is_empty() => Err(err),
+            Err(err) => {
+                debug!("Using only the XMP/IPTC tags for {}: {:?}", self, err);
+                Ok(extra)
+            }
+        }
+    }
+
+    /// Fetch the tags from the XMP packet embedded into a media file.
+    ///
+    /// The tags are named as `prefix:Name`, e.g. `xmp:CreateDate` or `dc:subject`.
+    pub fn get_xmp_tags(&self) -> Result<Map<String, TagValue>, AnyError> {
+        match read_xmp_packet(self.path())? {
+            Some(packet) => parse_xmp(&packet),
+            None => Ok(Map::new()),
+        }
+    }
+}
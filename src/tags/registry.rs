@@ -0,0 +1,369 @@
+//! Registry of the EXIF tags known to the `exif` crate.
+//!
+//! The registry is built once on the first use from the static table of the tags
+//! and allows to look up the tags by their identifiers, names, descriptions or numbers.
+use std::{collections::HashMap as Map, fmt};
+
+use exif::{Context, Tag};
+use once_cell::sync::Lazy;
+
+use super::EXIF_NAMESPACE;
+
+/// The tags known to the `exif` crate ordered by their context and number.
+///
+/// The order of the contexts is the order of preference
+/// for the tags sharing the same number.
+static TAGS: &[Tag] = &[
+    // TIFF (IFD0)
+    Tag::ImageWidth,
+    Tag::ImageLength,
+    Tag::BitsPerSample,
+    Tag::Compression,
+    Tag::PhotometricInterpretation,
+    Tag::ImageDescription,
+    Tag::Make,
+    Tag::Model,
+    Tag::StripOffsets,
+    Tag::Orientation,
+    Tag::SamplesPerPixel,
+    Tag::RowsPerStrip,
+    Tag::StripByteCounts,
+    Tag::XResolution,
+    Tag::YResolution,
+    Tag::PlanarConfiguration,
+    Tag::ResolutionUnit,
+    Tag::TransferFunction,
+    Tag::Software,
+    Tag::DateTime,
+    Tag::Artist,
+    Tag::WhitePoint,
+    Tag::PrimaryChromaticities,
+    Tag::TileOffsets,
+    Tag::TileByteCounts,
+    Tag::JPEGInterchangeFormat,
+    Tag::JPEGInterchangeFormatLength,
+    Tag::YCbCrCoefficients,
+    Tag::YCbCrSubSampling,
+    Tag::YCbCrPositioning,
+    Tag::ReferenceBlackWhite,
+    Tag::Copyright,
+    Tag::ExifIFDPointer,
+    Tag::GPSInfoIFDPointer,
+    // Exif IFD
+    Tag::ExposureTime,
+    Tag::FNumber,
+    Tag::ExposureProgram,
+    Tag::SpectralSensitivity,
+    Tag::PhotographicSensitivity,
+    Tag::OECF,
+    Tag::SensitivityType,
+    Tag::StandardOutputSensitivity,
+    Tag::RecommendedExposureIndex,
+    Tag::ISOSpeed,
+    Tag::ISOSpeedLatitudeyyy,
+    Tag::ISOSpeedLatitudezzz,
+    Tag::ExifVersion,
+    Tag::DateTimeOriginal,
+    Tag::DateTimeDigitized,
+    Tag::OffsetTime,
+    Tag::OffsetTimeOriginal,
+    Tag::OffsetTimeDigitized,
+    Tag::ComponentsConfiguration,
+    Tag::CompressedBitsPerPixel,
+    Tag::ShutterSpeedValue,
+    Tag::ApertureValue,
+    Tag::BrightnessValue,
+    Tag::ExposureBiasValue,
+    Tag::MaxApertureValue,
+    Tag::SubjectDistance,
+    Tag::MeteringMode,
+    Tag::LightSource,
+    Tag::Flash,
+    Tag::FocalLength,
+    Tag::SubjectArea,
+    Tag::MakerNote,
+    Tag::UserComment,
+    Tag::SubSecTime,
+    Tag::SubSecTimeOriginal,
+    Tag::SubSecTimeDigitized,
+    Tag::Temperature,
+    Tag::Humidity,
+    Tag::Pressure,
+    Tag::WaterDepth,
+    Tag::Acceleration,
+    Tag::CameraElevationAngle,
+    Tag::FlashpixVersion,
+    Tag::ColorSpace,
+    Tag::PixelXDimension,
+    Tag::PixelYDimension,
+    Tag::RelatedSoundFile,
+    Tag::InteropIFDPointer,
+    Tag::FlashEnergy,
+    Tag::SpatialFrequencyResponse,
+    Tag::FocalPlaneXResolution,
+    Tag::FocalPlaneYResolution,
+    Tag::FocalPlaneResolutionUnit,
+    Tag::SubjectLocation,
+    Tag::ExposureIndex,
+    Tag::SensingMethod,
+    Tag::FileSource,
+    Tag::SceneType,
+    Tag::CFAPattern,
+    Tag::CustomRendered,
+    Tag::ExposureMode,
+    Tag::WhiteBalance,
+    Tag::DigitalZoomRatio,
+    Tag::FocalLengthIn35mmFilm,
+    Tag::SceneCaptureType,
+    Tag::GainControl,
+    Tag::Contrast,
+    Tag::Saturation,
+    Tag::Sharpness,
+    Tag::DeviceSettingDescription,
+    Tag::SubjectDistanceRange,
+    Tag::ImageUniqueID,
+    Tag::CameraOwnerName,
+    Tag::BodySerialNumber,
+    Tag::LensSpecification,
+    Tag::LensMake,
+    Tag::LensModel,
+    Tag::LensSerialNumber,
+    Tag::CompositeImage,
+    Tag::SourceImageNumberOfCompositeImage,
+    Tag::SourceExposureTimesOfCompositeImage,
+    Tag::Gamma,
+    // GPS IFD
+    Tag::GPSVersionID,
+    Tag::GPSLatitudeRef,
+    Tag::GPSLatitude,
+    Tag::GPSLongitudeRef,
+    Tag::GPSLongitude,
+    Tag::GPSAltitudeRef,
+    Tag::GPSAltitude,
+    Tag::GPSTimeStamp,
+    Tag::GPSSatellites,
+    Tag::GPSStatus,
+    Tag::GPSMeasureMode,
+    Tag::GPSDOP,
+    Tag::GPSSpeedRef,
+    Tag::GPSSpeed,
+    Tag::GPSTrackRef,
+    Tag::GPSTrack,
+    Tag::GPSImgDirectionRef,
+    Tag::GPSImgDirection,
+    Tag::GPSMapDatum,
+    Tag::GPSDestLatitudeRef,
+    Tag::GPSDestLatitude,
+    Tag::GPSDestLongitudeRef,
+    Tag::GPSDestLongitude,
+    Tag::GPSDestBearingRef,
+    Tag::GPSDestBearing,
+    Tag::GPSDestDistanceRef,
+    Tag::GPSDestDistance,
+    Tag::GPSProcessingMethod,
+    Tag::GPSAreaInformation,
+    Tag::GPSDateStamp,
+    Tag::GPSDifferential,
+    Tag::GPSHPositioningError,
+    // Interoperability IFD
+    Tag::InteroperabilityIndex,
+    Tag::InteroperabilityVersion,
+    Tag::RelatedImageFileFormat,
+    Tag::RelatedImageWidth,
+    Tag::RelatedImageLength,
+];
+
+#[derive(Debug, Clone)]
+/// The EXIF tag with all its names.
+pub struct KnownTag {
+    tag: Tag,
+    id: String,
+    name: String,
+    description: String,
+}
+
+impl KnownTag {
+    fn new(tag: Tag) -> Option<Self> {
+        let description = tag.description()?.to_owned();
+        let name = tag.to_string();
+        Some(Self {
+            tag,
+            id: format!("{}:{}", EXIF_NAMESPACE, name),
+            name,
+            description,
+        })
+    }
+
+    /// The tag itself.
+    pub fn tag(&self) -> Tag {
+        self.tag
+    }
+
+    /// Stable identifier of the tag, e.g. `exif:DateTimeOriginal`.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Name of the tag, e.g. `DateTimeOriginal`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Human-readable description of the tag,
+    /// e.g. `Date and time of original data generation`.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// The IFD the tag belongs to: `tiff`, `exif`, `gps` or `interop`.
+    pub fn context(&self) -> &'static str {
+        context_name(self.tag.context())
+    }
+
+    /// The number of the tag inside its IFD.
+    pub fn number(&self) -> u16 {
+        self.tag.number()
+    }
+}
+
+impl fmt::Display for KnownTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (0x{:04X} in {}): {}",
+            self.id,
+            self.number(),
+            self.context(),
+            self.description
+        )
+    }
+}
+
+fn context_name(context: Context) -> &'static str {
+    match context {
+        Context::Tiff => "tiff",
+        Context::Exif => "exif",
+        Context::Gps => "gps",
+        Context::Interop => "interop",
+        _ => "unknown",
+    }
+}
+
+#[derive(Debug)]
+struct Registry {
+    tags: Vec<KnownTag>,
+    /// Lowercase identifiers, names and descriptions.
+    by_text: Map<String, usize>,
+    by_tag: Map<Tag, usize>,
+    by_number: Map<u16, Vec<usize>>,
+}
+
+impl Registry {
+    fn build() -> Self {
+        let tags: Vec<_> = TAGS.iter().copied().filter_map(KnownTag::new).collect();
+
+        let mut by_text = Map::new();
+        let mut by_tag = Map::new();
+        let mut by_number: Map<_, Vec<_>> = Map::new();
+        for (i, known) in tags.iter().enumerate() {
+            for text in [&known.id, &known.name, &known.description] {
+                // the first context wins for the duplicates
+                by_text.entry(text.to_lowercase()).or_insert(i);
+            }
+            by_tag.insert(known.tag, i);
+            by_number.entry(known.number()).or_default().push(i);
+        }
+
+        Self {
+            tags,
+            by_text,
+            by_tag,
+            by_number,
+        }
+    }
+}
+
+static REGISTRY: Lazy<Registry> = Lazy::new(Registry::build);
+
+/// All the known EXIF tags ordered by their context and number.
+pub fn known_tags() -> &'static [KnownTag] {
+    &REGISTRY.tags
+}
+
+/// Find the known tag by any of:
+/// - the identifier (`exif:DateTimeOriginal`);
+/// - the name (`DateTimeOriginal`);
+/// - the description (`Date and time of original data generation`);
+/// - the number, decimal (`36867`) or hexadecimal (`0x9003`).
+///
+/// The textual lookup is case-insensitive.
+/// If multiple tags share the same number, the TIFF and EXIF ones are preferred.
+pub fn lookup_tag(query: &str) -> Option<&'static KnownTag> {
+    let registry = &*REGISTRY;
+    let query = query.trim();
+    if let Some(&i) = registry.by_text.get(&query.to_lowercase()) {
+        return Some(&registry.tags[i]);
+    }
+
+    let number = query
+        .strip_prefix("0x")
+        .or_else(|| query.strip_prefix("0X"))
+        .map_or_else(
+            || query.parse().ok(),
+            |hex| u16::from_str_radix(hex, 16).ok(),
+        )?;
+    lookup_number(number).next()
+}
+
+/// Find all the known tags having the number (in different contexts).
+pub fn lookup_number(number: u16) -> impl Iterator<Item = &'static KnownTag> {
+    let registry = &*REGISTRY;
+    registry
+        .by_number
+        .get(&number)
+        .into_iter()
+        .flatten()
+        .map(|&i| &registry.tags[i])
+}
+
+/// Find the registry entry of the tag.
+pub fn lookup_exif_tag(tag: Tag) -> Option<&'static KnownTag> {
+    let registry = &*REGISTRY;
+    registry.by_tag.get(&tag).map(|&i| &registry.tags[i])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_has_all_the_tags_of_the_exif_crate() {
+        let known: Vec<_> = [Context::Tiff, Context::Exif, Context::Gps, Context::Interop]
+            .into_iter()
+            .flat_map(|ctx| (0..=u16::MAX).map(move |i| Tag(ctx, i)))
+            .filter(|tag| tag.description().is_some())
+            .collect();
+        assert_eq!(TAGS, known);
+    }
+
+    #[test]
+    fn lookup_by_any_name_or_number() {
+        for query in [
+            "exif:DateTimeOriginal",
+            "datetimeoriginal",
+            "Date and time of original data generation",
+            "36867",
+            "0x9003",
+        ] {
+            assert_eq!(lookup_tag(query).unwrap().tag(), Tag::DateTimeOriginal);
+        }
+        assert!(lookup_tag("0x10000").is_none());
+    }
+
+    #[test]
+    fn lookup_number_prefers_the_earlier_contexts() {
+        let shared: Vec<_> = lookup_number(1).map(KnownTag::tag).collect();
+        assert_eq!(shared, [Tag::GPSLatitudeRef, Tag::InteroperabilityIndex]);
+        assert_eq!(lookup_tag("1").unwrap().tag(), Tag::GPSLatitudeRef);
+    }
+}
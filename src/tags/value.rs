@@ -0,0 +1,287 @@
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use exif::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+/// Typed value of a metadata tag.
+pub enum TagValue {
+    /// Integer number.
+    Integer(i64),
+    /// Exact fraction, e.g. the exposure time `1/250`.
+    Rational {
+        /// The numerator of a fraction.
+        numerator: i64,
+        /// The denominator of a fraction.
+        denominator: i64,
+    },
+    /// Floating point number.
+    Float(f64),
+    /// Arbitrary text.
+    Text(String),
+    /// Date and time (in the local time zone of the shot if known).
+    DateTime(NaiveDateTime),
+    /// GPS coordinate in decimal degrees: negative
+    /// for the Southern latitudes and for the Western longitudes.
+    Gps(f64),
+    /// Opaque binary data.
+    Binary(Vec<u8>),
+    /// Multiple values of a single tag.
+    List(Vec<TagValue>),
+}
+
+impl TagValue {
+    /// Parse the text value detecting the date and time in it.
+    pub(crate) fn from_text(text: impl Into<String>) -> Self {
+        let text = text.into();
+        parse_iso_datetime(&text).map_or(Self::Text(text), Self::DateTime)
+    }
+
+    /// The value as a string slice if it is a [text][Self::Text].
+    pub fn as_text(&self) -> Option<&str> {
+        if let Self::Text(text) = self {
+            Some(text)
+        } else {
+            None
+        }
+    }
+
+    /// The value as an integer (if it is a whole number).
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Integer(i) => Some(*i),
+            Self::Rational {
+                numerator,
+                denominator,
+            } if *denominator != 0 && numerator % denominator == 0 => Some(numerator / denominator),
+            Self::Text(text) => text.trim().parse().ok(),
+            Self::List(values) if values.len() == 1 => values[0].as_i64(),
+            _ => None,
+        }
+    }
+
+    /// The value as a floating point number (if it is a number).
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            #[allow(clippy::cast_precision_loss)]
+            Self::Integer(i) => Some(*i as f64),
+            #[allow(clippy::cast_precision_loss)]
+            Self::Rational {
+                numerator,
+                denominator,
+            } if *denominator != 0 => Some(*numerator as f64 / *denominator as f64),
+            Self::Float(f) | Self::Gps(f) => Some(*f),
+            Self::Text(text) => text.trim().parse().ok(),
+            Self::List(values) if values.len() == 1 => values[0].as_f64(),
+            _ => None,
+        }
+    }
+
+    /// The value as a date and time (if it is one).
+    pub fn as_datetime(&self) -> Option<NaiveDateTime> {
+        if let Self::DateTime(dt) = self {
+            Some(*dt)
+        } else {
+            None
+        }
+    }
+
+    /// Convert the raw EXIF value into the typed one.
+    pub(crate) fn from_exif(value: &Value) -> Self {
+        fn collect<T>(
+            values: impl ExactSizeIterator<Item = T>,
+            f: impl Fn(T) -> TagValue,
+        ) -> TagValue {
+            if values.len() == 1 {
+                values.map(f).next().expect("the only value exists")
+            } else {
+                TagValue::List(values.map(f).collect())
+            }
+        }
+
+        match value {
+            Value::Byte(v) => collect(v.iter(), |x| Self::Integer((*x).into())),
+            Value::Short(v) => collect(v.iter(), |x| Self::Integer((*x).into())),
+            Value::Long(v) => collect(v.iter(), |x| Self::Integer((*x).into())),
+            Value::SByte(v) => collect(v.iter(), |x| Self::Integer((*x).into())),
+            Value::SShort(v) => collect(v.iter(), |x| Self::Integer((*x).into())),
+            Value::SLong(v) => collect(v.iter(), |x| Self::Integer((*x).into())),
+            Value::Rational(v) => collect(v.iter(), |r| Self::Rational {
+                numerator: r.num.into(),
+                denominator: r.denom.into(),
+            }),
+            Value::SRational(v) => collect(v.iter(), |r| Self::Rational {
+                numerator: r.num.into(),
+                denominator: r.denom.into(),
+            }),
+            Value::Float(v) => collect(v.iter(), |x| Self::Float((*x).into())),
+            Value::Double(v) => collect(v.iter(), |x| Self::Float(*x)),
+            Value::Ascii(v) => collect(v.iter(), |bytes| {
+                let text = String::from_utf8_lossy(bytes);
+                let text = text.trim_end_matches('\0').trim();
+                parse_exif_datetime(text)
+                    .map_or_else(|| Self::Text(text.to_owned()), Self::DateTime)
+            }),
+            Value::Undefined(bytes, _) => Self::Binary(bytes.clone()),
+            Value::Unknown(..) => Self::Binary(vec![]),
+        }
+    }
+}
+
+impl fmt::Display for TagValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer(i) => write!(f, "{}", i),
+            Self::Rational {
+                numerator,
+                denominator,
+            } => {
+                if *denominator == 1 {
+                    write!(f, "{}", numerator)
+                } else {
+                    write!(f, "{}/{}", numerator, denominator)
+                }
+            }
+            Self::Float(x) => write!(f, "{}", x),
+            Self::Text(text) => f.write_str(text),
+            Self::DateTime(dt) => write!(f, "{}", dt.format("%Y-%m-%d %H:%M:%S")),
+            Self::Gps(x) => write!(f, "{:.6}", x),
+            Self::Binary(bytes) => {
+                let text = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+                if !text.is_empty() && text.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+                    f.write_str(&String::from_utf8_lossy(text))
+                } else {
+                    write!(f, "<{} bytes>", bytes.len())
+                }
+            }
+            Self::List(values) => {
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    v.fmt(f)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<String> for TagValue {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<&str> for TagValue {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_owned())
+    }
+}
+
+/// Parse the EXIF date (`2020:01:02`) or datetime (`2020:01:02 03:04:05`).
+fn parse_exif_datetime(text: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(text, "%Y:%m:%d %H:%M:%S")
+        .ok()
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(text, "%Y:%m:%d").ok()?;
+            date.and_hms_opt(0, 0, 0)
+        })
+}
+
+/// Parse the ISO 8601 date and time as used in XMP and video containers.
+///
+/// If the time zone is specified, the local time is used.
+pub(crate) fn parse_iso_datetime(text: &str) -> Option<NaiveDateTime> {
+    let text = text.trim();
+    // fast path for the obviously non-datetime values
+    if text.len() < 10 || text.as_bytes().get(4) != Some(&b'-') {
+        return None;
+    }
+
+    if let Ok(dt) = DateTime::parse_from_rfc3339(text) {
+        return Some(dt.naive_local());
+    }
+    if let Some(utc) = text.strip_suffix('Z') {
+        if let Some(dt) = parse_naive_iso(utc) {
+            return Some(dt);
+        }
+    }
+    for fmt in ["%Y-%m-%dT%H:%M:%S%.f%:z", "%Y-%m-%dT%H:%M%:z"] {
+        if let Ok(dt) = DateTime::parse_from_str(text, fmt) {
+            return Some(dt.naive_local());
+        }
+    }
+    parse_naive_iso(text)
+}
+
+fn parse_naive_iso(text: &str) -> Option<NaiveDateTime> {
+    for fmt in [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S%.f",
+    ] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(text, fmt) {
+            return Some(dt);
+        }
+    }
+    let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+    date.and_hms_opt(0, 0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datetime(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f").unwrap()
+    }
+
+    #[test]
+    fn iso_with_offset_keeps_local_time() {
+        let expected = TagValue::DateTime(datetime("2022-07-08 10:30:15"));
+        for text in [
+            "2022-07-08T10:30:15+03:00",
+            "2022-07-08T10:30:15-05:00",
+            "2022-07-08T10:30:15Z",
+        ] {
+            assert_eq!(TagValue::from_text(text), expected, "{}", text);
+        }
+        assert_eq!(
+            TagValue::from_text("2022-07-08T10:30+03:00"),
+            TagValue::DateTime(datetime("2022-07-08 10:30:00"))
+        );
+    }
+
+    #[test]
+    fn iso_without_offset() {
+        assert_eq!(
+            TagValue::from_text("2022-07-08T10:30:15.250"),
+            TagValue::DateTime(datetime("2022-07-08 10:30:15.250"))
+        );
+        assert_eq!(
+            TagValue::from_text(" 2022-07-08 10:30:15 "),
+            TagValue::DateTime(datetime("2022-07-08 10:30:15"))
+        );
+        assert_eq!(
+            TagValue::from_text("2022-07-08"),
+            TagValue::DateTime(datetime("2022-07-08 00:00:00"))
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for text in ["2022-07-08T10:30:15+03:00", "2022-07-08T10:30:15"] {
+            let value = TagValue::from_text(text);
+            assert_eq!(value.to_string(), "2022-07-08 10:30:15");
+            assert_eq!(TagValue::from_text(value.to_string()), value);
+        }
+    }
+
+    #[test]
+    fn not_a_datetime() {
+        for text in ["2022", "hello world", "2022-13-45", "1234-5678-90"] {
+            assert_eq!(TagValue::from_text(text), TagValue::Text(text.to_owned()));
+        }
+    }
+}
@@ -0,0 +1,708 @@
+//! Extensible Metadata Platform (XMP) packets
+//! written by the cameras and photo editors.
+use std::{
+    collections::HashMap as Map,
+    fs::{self, File},
+    io::{self, BufReader, Read as _, Seek as _, SeekFrom},
+    path::Path,
+};
+
+use exif::{Exif, Reader};
+use log::debug;
+use roxmltree::{Document, Node};
+
+use super::{read_jpeg_segment, read_jpeg_segments, TagValue};
+use crate::AnyError;
+
+const RDF_NS: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Well-known namespaces with their conventional prefixes,
+/// so the tags do not depend on the prefixes chosen by a writer.
+const KNOWN_NAMESPACES: &[(&str, &str)] = &[
+    ("http://ns.adobe.com/xap/1.0/", "xmp"),
+    ("http://purl.org/dc/elements/1.1/", "dc"),
+    ("http://ns.adobe.com/exif/1.0/", "exif"),
+    ("http://cipa.jp/exif/1.0/", "exifEX"),
+    ("http://ns.adobe.com/tiff/1.0/", "tiff"),
+    ("http://ns.adobe.com/photoshop/1.0/", "photoshop"),
+    ("http://ns.adobe.com/xap/1.0/mm/", "xmpMM"),
+    ("http://ns.adobe.com/xap/1.0/rights/", "xmpRights"),
+    ("http://ns.adobe.com/exif/1.0/aux/", "aux"),
+    ("http://ns.adobe.com/camera-raw-settings/1.0/", "crs"),
+    ("http://ns.adobe.com/lightroom/1.0/", "lr"),
+    ("http://www.digikam.org/ns/1.0/", "digiKam"),
+    ("http://ns.microsoft.com/photo/1.0/", "MicrosoftPhoto"),
+    (
+        "http://iptc.org/std/Iptc4xmpCore/1.0/xmlns/",
+        "Iptc4xmpCore",
+    ),
+    ("http://iptc.org/std/Iptc4xmpExt/2008-02-29/", "Iptc4xmpExt"),
+    (
+        "http://www.metadataworkinggroup.com/schemas/regions/",
+        "mwg-rs",
+    ),
+    ("http://ns.google.com/photos/1.0/camera/", "GCamera"),
+    ("http://ns.google.com/photos/1.0/container/", "Container"),
+    ("http://ns.google.com/photos/1.0/container/item/", "Item"),
+];
+
+/// The `uuid` box type of the XMP packet in the ISO base media files (MP4, MOV, HEIF).
+const BMFF_XMP_UUID: [u8; 16] = [
+    0xBE, 0x7A, 0xCF, 0xCB, 0x97, 0xA9, 0x42, 0xE8, 0x9C, 0x71, 0x99, 0x94, 0x91, 0xE3, 0xAF, 0xAC,
+];
+
+/// The types of the first box of an ISO base media file.
+///
+/// Besides the usual `ftyp`, the older QuickTime files
+/// could start with any top-level box.
+const BMFF_FIRST_BOXES: &[&[u8]] = &[
+    b"ftyp", b"moov", b"wide", b"mdat", b"free", b"skip", b"pnot",
+];
+
+/// Find the XMP packet embedded into a media file.
+///
+/// The container format is detected by the file signature:
+/// - JPEG: the `APP1` segment;
+/// - PNG: the `iTXt` chunk;
+/// - ISO base media (MP4, MOV, HEIF): the `uuid` box or the `moov/udta/XMP_` box;
+/// - GIF: the raw packet anywhere in the file.
+///
+/// The other formats (e.g. AVI or MKV) are not searched
+/// to avoid reading the large files completely.
+pub(crate) fn read_xmp_packet(path: &Path) -> io::Result<Option<String>> {
+    let mut signature = [0; 8];
+    let read = File::open(path)?.read(&mut signature)?;
+    let signature = &signature[..read];
+
+    if signature.starts_with(&[0xFF, 0xD8]) {
+        jpeg_xmp_packet(path)
+    } else if signature.starts_with(b"\x89PNG") {
+        png_xmp_packet(path)
+    } else if signature
+        .get(4..8)
+        .is_some_and(|box_type| BMFF_FIRST_BOXES.contains(&box_type))
+    {
+        bmff_xmp_packet(path)
+    } else if signature.starts_with(b"GIF8") {
+        raw_xmp_packet(path)
+    } else {
+        Ok(None)
+    }
+}
+
+const JPEG_APP1: u8 = 0xE1;
+const JPEG_EXIF_SIGNATURE: &[u8] = b"Exif\0\0";
+const JPEG_XMP_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Find the XMP packet in the `APP1` segments of a JPEG file.
+fn jpeg_xmp_packet(path: &Path) -> io::Result<Option<String>> {
+    let packet = read_jpeg_segment(path, JPEG_APP1, JPEG_XMP_SIGNATURE)?;
+    Ok(packet.map(|p| String::from_utf8_lossy(&p).into_owned()))
+}
+
+/// Read both the EXIF and the XMP packet of a JPEG file in a single pass.
+///
+/// Returns `None` if the file is not a JPEG.
+pub(crate) fn jpeg_exif_and_xmp(path: &Path) -> io::Result<Option<(Option<Exif>, Option<String>)>> {
+    let wanted = [
+        (JPEG_APP1, JPEG_EXIF_SIGNATURE),
+        (JPEG_APP1, JPEG_XMP_SIGNATURE),
+    ];
+    let Some(segments) = read_jpeg_segments(path, &wanted)? else {
+        return Ok(None);
+    };
+    let mut segments = segments.into_iter();
+    let (exif, xmp) = (segments.next().flatten(), segments.next().flatten());
+    let exif = exif.and_then(|data| {
+        Reader::new()
+            .read_raw(data)
+            .map_err(|err| debug!("Failed to parse EXIF of {}: {:?}", path.display(), err))
+            .ok()
+    });
+    Ok(Some((
+        exif,
+        xmp.map(|p| String::from_utf8_lossy(&p).into_owned()),
+    )))
+}
+
+/// Find the XMP packet in the `iTXt` chunk of a PNG file.
+fn png_xmp_packet(path: &Path) -> io::Result<Option<String>> {
+    const XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp\0";
+
+    let mut file = BufReader::new(File::open(path)?);
+    file.seek_relative(8)?;
+    loop {
+        let mut header = [0; 8];
+        if let Err(err) = file.read_exact(&mut header) {
+            return if err.kind() == io::ErrorKind::UnexpectedEof {
+                Ok(None)
+            } else {
+                Err(err)
+            };
+        }
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let chunk_type = &header[4..];
+        if chunk_type == b"IEND" {
+            return Ok(None);
+        }
+        if chunk_type != b"iTXt" {
+            // skip the data and the CRC
+            file.seek_relative(i64::from(len) + 4)?;
+            continue;
+        }
+
+        let mut data = vec![];
+        (&mut file).take(u64::from(len)).read_to_end(&mut data)?;
+        file.seek_relative(4)?;
+        let Some(rest) = data.strip_prefix(XMP_KEYWORD) else {
+            continue;
+        };
+        // compression flag, compression method, language tag and translated keyword
+        let [compressed, _method, rest @ ..] = rest else {
+            continue;
+        };
+        if *compressed != 0 {
+            debug!("Compressed XMP in PNG is not supported: {}", path.display());
+            return Ok(None);
+        }
+        let mut parts = rest.splitn(3, |b| *b == 0);
+        let text = parts.nth(2).unwrap_or_default();
+        return Ok(Some(String::from_utf8_lossy(text).into_owned()));
+    }
+}
+
+/// Find the XMP packet in the boxes of an ISO base media file.
+fn bmff_xmp_packet(path: &Path) -> io::Result<Option<String>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let file_size = file.get_ref().metadata()?.len();
+    let packet = find_bmff_xmp(&mut file, file_size)?;
+    Ok(packet.map(|p| String::from_utf8_lossy(&p).into_owned()))
+}
+
+/// Walk the boxes till the `end` position looking for the XMP ones.
+fn find_bmff_xmp(file: &mut BufReader<File>, end: u64) -> io::Result<Option<Vec<u8>>> {
+    let mut pos = file.stream_position()?;
+    while pos + 8 <= end {
+        let mut header = [0; 8];
+        file.read_exact(&mut header)?;
+        let mut header_len = 8;
+        let size = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => end - pos,
+            1 => {
+                let mut large_size = [0; 8];
+                file.read_exact(&mut large_size)?;
+                header_len += 8;
+                u64::from_be_bytes(large_size)
+            }
+            size => u64::from(size),
+        };
+        if size < header_len {
+            debug!("Invalid box size {} at {}", size, pos);
+            return Ok(None);
+        }
+        // a corrupt size must neither go back nor leave the parent box
+        let Some(box_end) = pos
+            .checked_add(size)
+            .filter(|&box_end| box_end > pos && box_end <= end)
+        else {
+            debug!(
+                "Box at {} of size {} overruns its parent ending at {}",
+                pos, size, end
+            );
+            return Ok(None);
+        };
+        let data_len = size - header_len;
+
+        match &header[4..] {
+            b"uuid" if data_len >= 16 => {
+                let mut uuid = [0; 16];
+                file.read_exact(&mut uuid)?;
+                if uuid == BMFF_XMP_UUID {
+                    let mut packet = vec![];
+                    file.take(data_len - 16).read_to_end(&mut packet)?;
+                    return Ok(Some(packet));
+                }
+            }
+            b"XMP_" => {
+                let mut packet = vec![];
+                file.take(data_len).read_to_end(&mut packet)?;
+                return Ok(Some(packet));
+            }
+            b"moov" | b"udta" => {
+                if let Some(packet) = find_bmff_xmp(file, box_end)? {
+                    return Ok(Some(packet));
+                }
+            }
+            _ => {}
+        }
+
+        file.seek(SeekFrom::Start(box_end))?;
+        pos = box_end;
+    }
+    Ok(None)
+}
+
+/// Find the raw XMP packet anywhere in the GIF file (in the application extension).
+fn raw_xmp_packet(path: &Path) -> io::Result<Option<String>> {
+    const START: &[u8] = b"<x:xmpmeta";
+    const END: &[u8] = b"</x:xmpmeta>";
+
+    let content = fs::read(path)?;
+    let Some(start) = find_bytes(&content, START) else {
+        return Ok(None);
+    };
+    let Some(len) = find_bytes(&content[start..], END) else {
+        return Ok(None);
+    };
+    let packet = &content[start..start + len + END.len()];
+    Ok(Some(String::from_utf8_lossy(packet).into_owned()))
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Parse the XMP packet into the flat collection of properties.
+///
+/// The properties are named as `prefix:Name`, the arrays
+/// are represented as [lists][TagValue::List], and the fields
+/// of the structures are named as `prefix:Name/prefix:Field`.
+pub(crate) fn parse_xmp(packet: &str) -> Result<Map<String, TagValue>, AnyError> {
+    let packet = packet.trim_end_matches(['\0', ' ', '\n', '\r', '\t']);
+    let doc = Document::parse(packet)?;
+
+    let mut properties = Map::new();
+    for desc in doc
+        .descendants()
+        .filter(|n| n.has_tag_name((RDF_NS, "Description")))
+    {
+        // skip the nested descriptions, they are handled as the structures
+        if desc
+            .ancestors()
+            .skip(1)
+            .any(|n| n.has_tag_name((RDF_NS, "Description")))
+        {
+            continue;
+        }
+        collect_struct(desc, "", &mut properties);
+    }
+    Ok(properties)
+}
+
+fn qualified_name(node: Node<'_, '_>, namespace: Option<&str>, local: &str) -> String {
+    let prefix = namespace.and_then(|ns| {
+        KNOWN_NAMESPACES
+            .iter()
+            .find_map(|(uri, prefix)| (*uri == ns).then_some(*prefix))
+            .or_else(|| node.lookup_prefix(ns))
+    });
+    match prefix {
+        Some(prefix) => format!("{prefix}:{local}"),
+        None => local.to_owned(),
+    }
+}
+
+/// Whether the attribute is an XMP property
+/// and not a part of the RDF/XML syntax.
+fn is_property_attribute(attr: &roxmltree::Attribute<'_, '_>) -> bool {
+    attr.namespace()
+        .is_some_and(|ns| ns != RDF_NS && ns != XML_NS)
+}
+
+/// Collect both the attributes and the child elements
+/// of the `rdf:Description` or a structure.
+fn collect_struct(node: Node<'_, '_>, path: &str, properties: &mut Map<String, TagValue>) {
+    for attr in node.attributes().filter(is_property_attribute) {
+        let name = qualified_name(node, attr.namespace(), attr.name());
+        let name = format!("{path}{name}");
+        let value = property_value(&name, attr.value());
+        properties.insert(name, value);
+    }
+
+    for child in node.children().filter(Node::is_element) {
+        let name = qualified_name(child, child.tag_name().namespace(), child.tag_name().name());
+        collect_property(child, &format!("{path}{name}"), properties);
+    }
+}
+
+fn collect_property(node: Node<'_, '_>, name: &str, properties: &mut Map<String, TagValue>) {
+    let is_struct = node
+        .attribute((RDF_NS, "parseType"))
+        .is_some_and(|t| t == "Resource")
+        || node.attributes().any(|a| is_property_attribute(&a));
+    if is_struct {
+        collect_struct(node, &format!("{name}/"), properties);
+        return;
+    }
+
+    let Some(inner) = node.children().find(Node::is_element) else {
+        if let Some(text) = node.text().map(str::trim).filter(|t| !t.is_empty()) {
+            properties.insert(name.to_owned(), property_value(name, text));
+        }
+        return;
+    };
+
+    if inner.has_tag_name((RDF_NS, "Description")) {
+        collect_struct(inner, &format!("{name}/"), properties);
+        return;
+    }
+
+    let is_array = ["Bag", "Seq", "Alt"]
+        .iter()
+        .any(|kind| inner.has_tag_name((RDF_NS, *kind)));
+    if !is_array {
+        collect_property(inner, name, properties);
+        return;
+    }
+
+    let items: Vec<_> = inner
+        .children()
+        .filter(|n| n.has_tag_name((RDF_NS, "li")))
+        .collect();
+    let mut simple_values = vec![];
+    for (i, item) in items.iter().enumerate() {
+        if item.children().any(|n| n.is_element())
+            || item.attributes().any(|a| is_property_attribute(&a))
+        {
+            collect_property(*item, &format!("{name}[{}]", i + 1), properties);
+        } else if let Some(text) = item.text().map(str::trim).filter(|t| !t.is_empty()) {
+            simple_values.push(text);
+        }
+    }
+    if !simple_values.is_empty() {
+        // only the default language makes sense for the alternatives
+        let value = if inner.has_tag_name((RDF_NS, "Alt")) {
+            property_value(name, simple_values[0])
+        } else {
+            TagValue::List(simple_values.into_iter().map(TagValue::from_text).collect())
+        };
+        properties.insert(name.to_owned(), value);
+    }
+}
+
+/// Convert the text of a simple property into the typed value.
+fn property_value(name: &str, text: &str) -> TagValue {
+    if matches!(name, "exif:GPSLatitude" | "exif:GPSLongitude") {
+        if let Some(degrees) = parse_xmp_coordinate(text) {
+            return TagValue::Gps(degrees);
+        }
+    }
+    if name == "exif:GPSAltitude" {
+        if let Some((numerator, denominator)) = text.trim().split_once('/') {
+            if let (Ok(numerator), Ok(denominator)) = (numerator.parse(), denominator.parse()) {
+                return TagValue::Rational {
+                    numerator,
+                    denominator,
+                };
+            }
+        }
+    }
+    TagValue::from_text(text)
+}
+
+/// Parse the XMP GPS coordinate written either as
+/// `DDD,MM.mmk` or as `DDD,MM,SSk` where `k` is one of `NSEW`.
+fn parse_xmp_coordinate(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let direction = text.chars().last()?;
+    let negate = match direction.to_ascii_uppercase() {
+        'N' | 'E' => false,
+        'S' | 'W' => true,
+        _ => return None,
+    };
+    let parts: Vec<f64> = text[..text.len() - direction.len_utf8()]
+        .split(',')
+        .map(|part| part.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    if parts.is_empty() || parts.len() > 3 {
+        return None;
+    }
+    let degrees: f64 = parts
+        .iter()
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(part, divisor)| part / divisor)
+        .sum();
+    Some(if negate { -degrees } else { degrees })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    const PACKET: &str = r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description rdf:about=""
+        xmlns:adobe="http://ns.adobe.com/xap/1.0/"
+        xmlns:dc="http://purl.org/dc/elements/1.1/"
+        xmlns:exif="http://ns.adobe.com/exif/1.0/"
+        xmlns:my="http://example.com/ns/"
+        xmlns:Iptc4xmpCore="http://iptc.org/std/Iptc4xmpCore/1.0/xmlns/"
+        xmlns:mwg-rs="http://www.metadataworkinggroup.com/schemas/regions/"
+        xmlns:stArea="http://ns.adobe.com/xmp/sType/Area#"
+        adobe:Rating="4"
+        adobe:CreateDate="2022-07-08T10:30:15+03:00"
+        exif:GPSLatitude="50,27.006N"
+        exif:GPSLongitude="30,31,24.3W"
+        exif:GPSAltitude="1795/10"
+        my:Custom="value">
+      <dc:subject>
+        <rdf:Bag>
+          <rdf:li>travel</rdf:li>
+          <rdf:li>mountains</rdf:li>
+        </rdf:Bag>
+      </dc:subject>
+      <dc:title>
+        <rdf:Alt>
+          <rdf:li xml:lang="x-default">Title</rdf:li>
+          <rdf:li xml:lang="uk">Назва</rdf:li>
+        </rdf:Alt>
+      </dc:title>
+      <Iptc4xmpCore:Location>Old Town</Iptc4xmpCore:Location>
+      <Iptc4xmpCore:CreatorContactInfo rdf:parseType="Resource">
+        <Iptc4xmpCore:CiAdrCity>Kyiv</Iptc4xmpCore:CiAdrCity>
+      </Iptc4xmpCore:CreatorContactInfo>
+      <mwg-rs:Regions>
+        <rdf:Description>
+          <mwg-rs:RegionList>
+            <rdf:Bag>
+              <rdf:li mwg-rs:Name="Alice" mwg-rs:Type="Face">
+                <mwg-rs:Area stArea:x="0.5" stArea:y="0.25"/>
+              </rdf:li>
+              <rdf:li>
+                <rdf:Description mwg-rs:Name="Bob"/>
+              </rdf:li>
+            </rdf:Bag>
+          </mwg-rs:RegionList>
+        </rdf:Description>
+      </mwg-rs:Regions>
+    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#;
+
+    fn text(value: &str) -> TagValue {
+        TagValue::Text(value.into())
+    }
+
+    fn temp_file(name: &str, content: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("foton-xmp-{}-{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    /// Read the packet from the file removing it afterwards.
+    fn read_packet(name: &str, content: &[u8]) -> Option<String> {
+        let path = temp_file(name, content);
+        let packet = read_xmp_packet(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        packet
+    }
+
+    fn bmff_box(box_type: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut result = u32::try_from(8 + data.len())
+            .unwrap()
+            .to_be_bytes()
+            .to_vec();
+        result.extend_from_slice(box_type);
+        result.extend_from_slice(data);
+        result
+    }
+
+    #[test]
+    fn attributes_and_elements() {
+        let properties = parse_xmp(PACKET).unwrap();
+        // the prefix is normalized by the namespace
+        assert_eq!(properties["xmp:Rating"], text("4"));
+        assert_eq!(
+            properties["xmp:CreateDate"],
+            TagValue::DateTime(
+                NaiveDate::from_ymd_opt(2022, 7, 8)
+                    .unwrap()
+                    .and_hms_opt(10, 30, 15)
+                    .unwrap()
+            )
+        );
+        // the unknown namespace keeps the prefix of the writer
+        assert_eq!(properties["my:Custom"], text("value"));
+        assert_eq!(properties["Iptc4xmpCore:Location"], text("Old Town"));
+        assert!(!properties.contains_key("rdf:about"));
+    }
+
+    #[test]
+    fn arrays_and_alternatives() {
+        let properties = parse_xmp(PACKET).unwrap();
+        assert_eq!(
+            properties["dc:subject"],
+            TagValue::List(vec![text("travel"), text("mountains")])
+        );
+        assert_eq!(properties["dc:title"], text("Title"));
+    }
+
+    #[test]
+    fn structures_and_arrays_of_structures() {
+        let properties = parse_xmp(PACKET).unwrap();
+        assert_eq!(
+            properties["Iptc4xmpCore:CreatorContactInfo/Iptc4xmpCore:CiAdrCity"],
+            text("Kyiv")
+        );
+        let region = "mwg-rs:Regions/mwg-rs:RegionList[1]/";
+        assert_eq!(properties[&format!("{region}mwg-rs:Name")], text("Alice"));
+        assert_eq!(properties[&format!("{region}mwg-rs:Type")], text("Face"));
+        assert_eq!(
+            properties[&format!("{region}mwg-rs:Area/stArea:x")],
+            text("0.5")
+        );
+        assert_eq!(
+            properties["mwg-rs:Regions/mwg-rs:RegionList[2]/mwg-rs:Name"],
+            text("Bob")
+        );
+        assert!(!properties.contains_key("mwg-rs:Regions/mwg-rs:RegionList"));
+    }
+
+    #[test]
+    fn gps_coordinates() {
+        let properties = parse_xmp(PACKET).unwrap();
+        let TagValue::Gps(latitude) = properties["exif:GPSLatitude"] else {
+            panic!("not a coordinate: {:?}", properties["exif:GPSLatitude"]);
+        };
+        assert!((latitude - 50.4501).abs() < 1e-9);
+        let TagValue::Gps(longitude) = properties["exif:GPSLongitude"] else {
+            panic!("not a coordinate: {:?}", properties["exif:GPSLongitude"]);
+        };
+        assert!((longitude + 30.523_416_666).abs() < 1e-6);
+        assert_eq!(
+            properties["exif:GPSAltitude"],
+            TagValue::Rational {
+                numerator: 1795,
+                denominator: 10
+            }
+        );
+
+        assert_eq!(parse_xmp_coordinate("10S"), Some(-10.0));
+        assert_eq!(parse_xmp_coordinate("10,30e"), Some(10.5));
+        assert_eq!(parse_xmp_coordinate("10,30"), None);
+        assert_eq!(parse_xmp_coordinate("N"), None);
+        assert_eq!(parse_xmp_coordinate("1,2,3,4N"), None);
+    }
+
+    #[test]
+    fn padded_packet() {
+        let packet = format!("{}\n{}\0\0", PACKET, " ".repeat(100));
+        assert_eq!(
+            parse_xmp(&packet).unwrap().len(),
+            parse_xmp(PACKET).unwrap().len()
+        );
+        assert!(parse_xmp("<x:xmpmeta").is_err());
+    }
+
+    #[test]
+    fn packet_in_jpeg() {
+        let signature = b"http://ns.adobe.com/xap/1.0/\0";
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        let len = u16::try_from(2 + signature.len() + PACKET.len()).unwrap();
+        jpeg.extend_from_slice(&len.to_be_bytes());
+        jpeg.extend_from_slice(signature);
+        jpeg.extend_from_slice(PACKET.as_bytes());
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0, 2, 0xFF, 0xD9]);
+        assert_eq!(read_packet("jpeg", &jpeg).as_deref(), Some(PACKET));
+    }
+
+    #[test]
+    fn packet_in_png() {
+        let chunk = |chunk_type: &[u8], data: &[u8]| {
+            let mut chunk = u32::try_from(data.len()).unwrap().to_be_bytes().to_vec();
+            chunk.extend_from_slice(chunk_type);
+            chunk.extend_from_slice(data);
+            // the CRC is not checked
+            chunk.extend_from_slice(&[0; 4]);
+            chunk
+        };
+        let itxt = [
+            &b"XML:com.adobe.xmp\0"[..],
+            // not compressed, no language and translated keyword
+            &[0, 0, 0, 0],
+            PACKET.as_bytes(),
+        ]
+        .concat();
+        let png = [
+            &b"\x89PNG\r\n\x1a\n"[..],
+            &chunk(b"IHDR", &[0; 13]),
+            &chunk(b"tEXt", b"Comment\0text"),
+            &chunk(b"iTXt", &itxt),
+            &chunk(b"IEND", &[]),
+        ]
+        .concat();
+        assert_eq!(read_packet("png", &png).as_deref(), Some(PACKET));
+    }
+
+    #[test]
+    fn packet_in_uuid_box() {
+        let uuid = [&BMFF_XMP_UUID[..], PACKET.as_bytes()].concat();
+        let mp4 = [
+            bmff_box(b"ftyp", b"isom\0\0\0\0"),
+            bmff_box(b"mdat", &[0; 100]),
+            bmff_box(b"uuid", &[1; 20]),
+            bmff_box(b"uuid", &uuid),
+        ]
+        .concat();
+        assert_eq!(read_packet("mp4", &mp4).as_deref(), Some(PACKET));
+    }
+
+    #[test]
+    fn packet_in_quicktime_user_data() {
+        let udta = bmff_box(b"udta", &bmff_box(b"XMP_", PACKET.as_bytes()));
+        let moov = bmff_box(b"moov", &[bmff_box(b"mvhd", &[0; 20]), udta].concat());
+        // the old QuickTime files without `ftyp`
+        let mov = [bmff_box(b"wide", &[]), moov, bmff_box(b"mdat", &[0; 10])].concat();
+        assert_eq!(read_packet("mov", &mov).as_deref(), Some(PACKET));
+
+        let broken = [bmff_box(b"ftyp", b"qt  "), 4_u32.to_be_bytes().to_vec()].concat();
+        assert_eq!(read_packet("broken.mov", &broken), None);
+    }
+
+    #[test]
+    fn bogus_large_size() {
+        let large = [&1_u32.to_be_bytes()[..], b"mdat", &u64::MAX.to_be_bytes()].concat();
+        let mp4 = [bmff_box(b"ftyp", b"isom\0\0\0\0"), large, vec![0; 16]].concat();
+        assert_eq!(read_packet("large.mp4", &mp4), None);
+    }
+
+    #[test]
+    fn box_overruns_its_parent() {
+        let mut udta = bmff_box(b"udta", &bmff_box(b"XMP_", PACKET.as_bytes()));
+        let overrun = u32::try_from(udta.len() + 100).unwrap();
+        udta[..4].copy_from_slice(&overrun.to_be_bytes());
+        let moov = bmff_box(b"moov", &udta);
+        let mov = [bmff_box(b"ftyp", b"qt  "), moov, vec![0; 200]].concat();
+        assert_eq!(read_packet("overrun.mov", &mov), None);
+    }
+
+    #[test]
+    fn packet_in_gif() {
+        let start = PACKET.find("<x:xmpmeta").unwrap();
+        let end = PACKET.find("<?xpacket end").unwrap();
+        let gif = [
+            &b"GIF89a\x01\x00\x01\x00\x00\x00\x00!\xFF\x0BXMP DataXMP"[..],
+            PACKET.as_bytes(),
+            b"\x00;",
+        ]
+        .concat();
+        assert_eq!(
+            read_packet("gif", &gif).as_deref(),
+            Some(PACKET[start..end].trim_end())
+        );
+    }
+
+    #[test]
+    fn other_formats_are_not_scanned() {
+        let avi = [&b"RIFF\0\0\0\0AVI "[..], PACKET.as_bytes()].concat();
+        assert_eq!(read_packet("avi", &avi), None);
+        assert_eq!(read_packet("empty", &[]), None);
+    }
+}
@@ -0,0 +1,274 @@
+//! Basic technical facts about a media: dimensions, orientation,
+//! camera, lens and the exposure settings.
+use std::{collections::HashMap, fmt};
+
+use image::{DynamicImage, ImageReader};
+use log::{debug, warn};
+
+use crate::{
+    file_types::{Media, MediaType},
+    tags::{find_tag, TagValue},
+    AnyError,
+};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+/// How the stored image should be transformed to be displayed properly
+/// (the EXIF `Orientation` tag).
+pub enum Orientation {
+    /// No transformation required.
+    Normal,
+    /// Flip horizontally.
+    MirrorHorizontal,
+    /// Rotate by 180°.
+    Rotate180,
+    /// Flip vertically.
+    MirrorVertical,
+    /// Flip horizontally and rotate by 270° clockwise.
+    MirrorHorizontalRotate270,
+    /// Rotate by 90° clockwise.
+    Rotate90,
+    /// Flip horizontally and rotate by 90° clockwise.
+    MirrorHorizontalRotate90,
+    /// Rotate by 270° clockwise.
+    Rotate270,
+}
+
+impl Orientation {
+    /// Convert the value of the EXIF `Orientation` tag (1-8).
+    pub fn from_exif(value: i64) -> Option<Self> {
+        Some(match value {
+            1 => Self::Normal,
+            2 => Self::MirrorHorizontal,
+            3 => Self::Rotate180,
+            4 => Self::MirrorVertical,
+            5 => Self::MirrorHorizontalRotate270,
+            6 => Self::Rotate90,
+            7 => Self::MirrorHorizontalRotate90,
+            8 => Self::Rotate270,
+            _ => return None,
+        })
+    }
+
+    /// Convert the clockwise rotation of a video stream (in degrees).
+    pub fn from_rotation(degrees: i32) -> Option<Self> {
+        Some(match degrees.rem_euclid(360) {
+            0 => Self::Normal,
+            90 => Self::Rotate90,
+            180 => Self::Rotate180,
+            270 => Self::Rotate270,
+            _ => return None,
+        })
+    }
+
+    /// Transform the stored image to be displayed properly.
+    pub fn apply(self, image: DynamicImage) -> DynamicImage {
+        match self {
+            Self::Normal => image,
+            Self::MirrorHorizontal => image.fliph(),
+            Self::Rotate180 => image.rotate180(),
+            Self::MirrorVertical => image.flipv(),
+            Self::MirrorHorizontalRotate270 => image.fliph().rotate270(),
+            Self::Rotate90 => image.rotate90(),
+            Self::MirrorHorizontalRotate90 => image.fliph().rotate90(),
+            Self::Rotate270 => image.rotate270(),
+        }
+    }
+
+    /// Whether the width and height are swapped when displaying.
+    pub fn swaps_dimensions(self) -> bool {
+        matches!(
+            self,
+            Self::MirrorHorizontalRotate270
+                | Self::Rotate90
+                | Self::MirrorHorizontalRotate90
+                | Self::Rotate270
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Technical metadata of a media.
+///
+/// Every field is optional as it could be missing in the metadata.
+pub struct Technical {
+    /// Width of the stored image (or video frames) in pixels.
+    pub width: Option<u32>,
+    /// Height of the stored image (or video frames) in pixels.
+    pub height: Option<u32>,
+    /// How the image should be transformed to be displayed.
+    pub orientation: Option<Orientation>,
+    /// Manufacturer of the camera.
+    pub make: Option<String>,
+    /// Model of the camera.
+    pub model: Option<String>,
+    /// Name of the lens.
+    pub lens: Option<String>,
+    /// ISO speed.
+    pub iso: Option<u32>,
+    /// The f-number, e.g. `2.8` for `f/2.8`.
+    pub aperture: Option<f64>,
+    /// Exposure time (shutter speed) in seconds.
+    pub exposure_time: Option<f64>,
+    /// Actual focal length in millimeters.
+    pub focal_length: Option<f64>,
+    /// The 35mm film equivalent focal length in millimeters.
+    pub focal_length_35mm: Option<u32>,
+}
+
+impl Technical {
+    /// Width and height as displayed, i.e. taking the orientation into account.
+    pub fn display_size(&self) -> Option<(u32, u32)> {
+        let (width, height) = (self.width?, self.height?);
+        if self.orientation.is_some_and(Orientation::swaps_dimensions) {
+            Some((height, width))
+        } else {
+            Some((width, height))
+        }
+    }
+
+    /// The number of pixels in megapixels.
+    pub fn megapixels(&self) -> Option<f64> {
+        let (width, height) = (self.width?, self.height?);
+        Some(f64::from(width) * f64::from(height) / 1e6)
+    }
+
+    /// Camera make and model, without the duplicate make
+    /// like in `Canon` `Canon EOS R`.
+    pub fn camera(&self) -> Option<String> {
+        match (self.make.as_deref(), self.model.as_deref()) {
+            (Some(make), Some(model)) if model.starts_with(make) => Some(model.to_owned()),
+            (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+            (make, model) => make.or(model).map(ToOwned::to_owned),
+        }
+    }
+
+    fn from_tags(tags: &HashMap<String, TagValue>) -> Self {
+        let get = |names: &[&str]| names.iter().find_map(|name| find_tag(tags, name));
+        let text = |names: &[&str]| {
+            get(names)
+                .map(ToString::to_string)
+                .map(|text| text.trim().to_owned())
+                .filter(|text| !text.is_empty())
+        };
+        let integer = |names: &[&str]| {
+            get(names)
+                .and_then(TagValue::as_i64)
+                .and_then(|value| u32::try_from(value).ok())
+                .filter(|value| *value > 0)
+        };
+        let float = |names: &[&str]| {
+            get(names)
+                .and_then(TagValue::as_f64)
+                .filter(|value| *value > 0.0)
+        };
+
+        Self {
+            width: integer(&["exif:PixelXDimension", "exif:ImageWidth"]),
+            height: integer(&["exif:PixelYDimension", "exif:ImageLength"]),
+            orientation: get(&["exif:Orientation"])
+                .and_then(TagValue::as_i64)
+                .and_then(Orientation::from_exif),
+            make: text(&["exif:Make", "ffmpeg:com.apple.quicktime.make"]),
+            model: text(&["exif:Model", "ffmpeg:com.apple.quicktime.model"]),
+            lens: text(&["exif:LensModel", "canon:LensModel", "aux:Lens"]),
+            iso: integer(&["exif:PhotographicSensitivity"]),
+            aperture: float(&["exif:FNumber"]),
+            exposure_time: float(&["exif:ExposureTime"]),
+            focal_length: float(&["exif:FocalLength"]),
+            focal_length_35mm: integer(&["exif:FocalLengthIn35mmFilm"]),
+        }
+    }
+}
+
+impl fmt::Display for Technical {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![];
+        if let Some((width, height)) = self.display_size() {
+            parts.push(format!("{}x{}", width, height));
+        }
+        parts.extend(self.camera());
+        parts.extend(self.lens.clone());
+        if let Some(iso) = self.iso {
+            parts.push(format!("ISO {}", iso));
+        }
+        if let Some(aperture) = self.aperture {
+            parts.push(format!("f/{:.1}", aperture));
+        }
+        if let Some(time) = self.exposure_time {
+            parts.push(if time < 1.0 {
+                format!("1/{:.0} s", 1.0 / time)
+            } else {
+                format!("{} s", time)
+            });
+        }
+        if let Some(focal) = self.focal_length {
+            parts.push(format!("{} mm", focal));
+        }
+        if parts.is_empty() {
+            f.write_str("-")
+        } else {
+            f.write_str(&parts.join(", "))
+        }
+    }
+}
+
+impl Media {
+    /// Retrieves the [technical metadata][Technical] of the media.
+    pub fn technical(&self) -> Technical {
+        let all_tags = self
+            .get_tags()
+            .map_err(|err| {
+                warn!("Failed to get technical tags for {}: {:?}", self, err);
+            })
+            .unwrap_or_default();
+        self.technical_with_tags(&all_tags)
+    }
+
+    /// Retrieves the [technical metadata][Technical] of the media
+    /// from the already collected tags.
+    ///
+    /// If the tags lack the dimensions, they are decoded
+    /// from the image header (or from the video stream).
+    pub fn technical_with_tags(&self, tags: &HashMap<String, TagValue>) -> Technical {
+        let mut technical = Technical::from_tags(tags);
+        if technical.width.is_some() && technical.height.is_some() {
+            return technical;
+        }
+
+        let decoded = match self.type_ {
+            MediaType::Photo | MediaType::Animation => self.decode_image_dimensions(),
+            MediaType::Video => self.decode_video_dimensions(&mut technical),
+        };
+        match decoded {
+            Ok((0, _) | (_, 0)) => debug!("Empty dimensions of {}", self),
+            Ok((width, height)) => {
+                technical.width = Some(width);
+                technical.height = Some(height);
+            }
+            Err(err) => debug!("Failed to decode dimensions of {}: {:?}", self, err),
+        }
+        technical
+    }
+
+    fn decode_image_dimensions(&self) -> Result<(u32, u32), AnyError> {
+        let reader = ImageReader::open(self.path())?.with_guessed_format()?;
+        Ok(reader.into_dimensions()?)
+    }
+
+    /// Take the dimensions from the main video stream
+    /// filling the orientation from the stream's rotation.
+    fn decode_video_dimensions(&self, technical: &mut Technical) -> Result<(u32, u32), AnyError> {
+        let info = self.video_info()?;
+        let stream = info.video_stream().ok_or("no video stream")?;
+        if technical.orientation.is_none() {
+            technical.orientation = stream
+                .rotation
+                .and_then(Orientation::from_rotation)
+                .filter(|orientation| *orientation != Orientation::Normal);
+        }
+        Ok((
+            stream.width.ok_or("unknown width")?,
+            stream.height.ok_or("unknown height")?,
+        ))
+    }
+}
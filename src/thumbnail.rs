@@ -0,0 +1,498 @@
+//! Downscaled previews of the photos and videos
+//! stored in a content-addressed cache directory.
+//!
+//! Every thumbnail is named by the hash of the media content,
+//! so the renamed or duplicated files share the thumbnails.
+//! The index of the cache remembers the size and the modification time
+//! of every media to avoid hashing the unchanged files again.
+use std::{
+    collections::{HashMap as Map, HashSet},
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use image::{
+    codecs::{jpeg::JpegEncoder, webp::WebPEncoder},
+    DynamicImage, ImageReader,
+};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    file_types::{Media, MediaType},
+    tags::read_exif,
+    technical::Orientation,
+    AnyError,
+};
+
+/// The file with the hashes of the media inside the cache directory.
+const INDEX_NAME: &str = "index.json";
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// The image format of the thumbnails.
+pub enum ThumbnailFormat {
+    #[default]
+    /// Lossy JPEG with the configured quality.
+    Jpeg,
+    /// Lossless WebP.
+    WebP,
+}
+
+impl ThumbnailFormat {
+    /// The extension of the thumbnail files.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::WebP => "webp",
+        }
+    }
+}
+
+impl FromStr for ThumbnailFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "jpeg" | "jpg" => Ok(Self::Jpeg),
+            "webp" => Ok(Self::WebP),
+            _ => Err(format!(
+                "unknown thumbnail format {:?}, expected jpeg or webp",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// How to generate the thumbnails.
+pub struct ThumbnailOptions {
+    #[serde(default = "ThumbnailOptions::default_sizes")]
+    /// The longest side of every generated thumbnail in pixels.
+    pub sizes: Vec<u32>,
+
+    #[serde(default)]
+    /// The image format of the thumbnails.
+    pub format: ThumbnailFormat,
+
+    #[serde(default = "ThumbnailOptions::default_quality")]
+    /// The JPEG quality (1-100).
+    pub quality: u8,
+}
+
+impl ThumbnailOptions {
+    fn default_sizes() -> Vec<u32> {
+        vec![256, 1024]
+    }
+
+    const fn default_quality() -> u8 {
+        85
+    }
+}
+
+impl Default for ThumbnailOptions {
+    fn default() -> Self {
+        Self {
+            sizes: Self::default_sizes(),
+            format: ThumbnailFormat::default(),
+            quality: Self::default_quality(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct IndexEntry {
+    len: u64,
+    modified: SystemTime,
+    hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The thumbnails of a single media.
+pub struct Thumbnails {
+    /// The hash of the media content.
+    pub hash: String,
+    /// The thumbnail files by their sizes (ascending).
+    pub paths: Vec<(u32, PathBuf)>,
+    /// Whether the thumbnails were generated just now (otherwise, taken from the cache).
+    pub generated: bool,
+}
+
+impl Thumbnails {
+    /// The smallest thumbnail not smaller than the `size` (or the largest one).
+    pub fn at_least(&self, size: u32) -> Option<&Path> {
+        self.paths
+            .iter()
+            .find(|(s, _)| *s >= size)
+            .or_else(|| self.paths.last())
+            .map(|(_, path)| path.as_path())
+    }
+}
+
+#[derive(Debug)]
+/// The directory with the thumbnails.
+pub struct ThumbnailCache {
+    dir: PathBuf,
+    options: ThumbnailOptions,
+    index: Map<PathBuf, IndexEntry>,
+    changed: bool,
+}
+
+impl ThumbnailCache {
+    /// Open (or create) the cache directory.
+    pub fn open(dir: &Path, options: &ThumbnailOptions) -> Result<Self, AnyError> {
+        if options.sizes.is_empty() || options.sizes.contains(&0) {
+            return Err("The thumbnail sizes should be positive".into());
+        }
+        fs::create_dir_all(dir)?;
+        let index_path = dir.join(INDEX_NAME);
+        let mut index: Map<PathBuf, IndexEntry> = match fs::read(&index_path) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|err| {
+                warn!("Ignoring the broken {}: {}", index_path.display(), err);
+                Map::new()
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Map::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let before = index.len();
+        index.retain(|path, entry| {
+            let valid = is_hash(&entry.hash);
+            if !valid {
+                warn!(
+                    "Ignoring the invalid hash {:?} of {} in {}",
+                    entry.hash,
+                    path.display(),
+                    index_path.display()
+                );
+            }
+            valid
+        });
+        let changed = index.len() != before;
+
+        let mut options = options.clone();
+        options.sizes.sort_unstable();
+        options.sizes.dedup();
+        Ok(Self {
+            dir: dir.to_owned(),
+            options,
+            index,
+            changed,
+        })
+    }
+
+    /// The cache directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Get the thumbnails of the media generating them if they are missing
+    /// or the media has been changed (or if the `force` is set).
+    pub fn get(&mut self, media: &Media, force: bool) -> Result<Thumbnails, AnyError> {
+        let metadata = fs::metadata(media.path())?;
+        let (len, modified) = (metadata.len(), metadata.modified()?);
+        let known = self
+            .index
+            .get(media.path())
+            .filter(|entry| entry.len == len && entry.modified == modified)
+            .map(|entry| entry.hash.clone());
+        let hash = if let Some(hash) = known {
+            hash
+        } else {
+            let hash = hash_file(media.path())?;
+            self.index.insert(
+                media.path().to_owned(),
+                IndexEntry {
+                    len,
+                    modified,
+                    hash: hash.clone(),
+                },
+            );
+            self.changed = true;
+            hash
+        };
+
+        let paths: Vec<_> = self
+            .options
+            .sizes
+            .iter()
+            .map(|size| (*size, self.thumbnail_path(&hash, *size)))
+            .collect();
+        let fresh = !force && paths.iter().all(|(_, path)| path.exists());
+        if !fresh {
+            let image = decode(media)?;
+            for (size, path) in &paths {
+                self.save(&image, *size, path)?;
+            }
+            debug!("Generated thumbnails of {}", media);
+        }
+        Ok(Thumbnails {
+            hash,
+            paths,
+            generated: !fresh,
+        })
+    }
+
+    /// Forget the media not satisfying the `keep` predicate (e.g. deleted ones).
+    ///
+    /// The thumbnails not referenced from the index anymore are deleted
+    /// as well as the ones of the sizes or the format not used anymore,
+    /// the number of deleted files is returned.
+    pub fn prune(&mut self, keep: impl Fn(&Path) -> bool) -> Result<usize, AnyError> {
+        let before = self.index.len();
+        self.index.retain(|path, _| keep(path));
+        self.changed |= self.index.len() != before;
+
+        let used: HashSet<_> = self
+            .index
+            .values()
+            .flat_map(|entry| {
+                self.options
+                    .sizes
+                    .iter()
+                    .map(|size| self.thumbnail_path(&entry.hash, *size))
+            })
+            .collect();
+        let mut removed = 0;
+        for shard in fs::read_dir(&self.dir)? {
+            let shard = shard?.path();
+            // only the `{ab}` directories are created by the cache
+            let is_shard = shard
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.len() == 2 && name.bytes().all(|b| b.is_ascii_hexdigit()));
+            if !is_shard || !shard.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(&shard)? {
+                let file = file?.path();
+                if !used.contains(&file) {
+                    fs::remove_file(&file)?;
+                    removed += 1;
+                }
+            }
+        }
+        info!("Removed {} unused thumbnails", removed);
+        Ok(removed)
+    }
+
+    /// Write the index if it has been changed.
+    pub fn save_index(&mut self) -> Result<(), AnyError> {
+        if self.changed {
+            let path = self.dir.join(INDEX_NAME);
+            let tmp = path.with_extension("json.tmp");
+            fs::write(&tmp, serde_json::to_vec(&self.index)?)?;
+            fs::rename(&tmp, &path)?;
+            self.changed = false;
+        }
+        Ok(())
+    }
+
+    /// `{dir}/{ab}/{abcdef...}_{size}.{ext}`
+    fn thumbnail_path(&self, hash: &str, size: u32) -> PathBuf {
+        self.dir.join(&hash[..2]).join(format!(
+            "{}_{}.{}",
+            hash,
+            size,
+            self.options.format.extension()
+        ))
+    }
+
+    fn save(&self, image: &DynamicImage, size: u32, path: &Path) -> Result<(), AnyError> {
+        let resized = if image.width() > size || image.height() > size {
+            image.thumbnail(size, size)
+        } else {
+            image.clone()
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        match self.options.format {
+            ThumbnailFormat::Jpeg => {
+                let encoder = JpegEncoder::new_with_quality(&mut writer, self.options.quality);
+                resized.to_rgb8().write_with_encoder(encoder)?;
+            }
+            ThumbnailFormat::WebP => {
+                let encoder = WebPEncoder::new_lossless(&mut writer);
+                resized.to_rgba8().write_with_encoder(encoder)?;
+            }
+        }
+        writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// The hex-encoded BLAKE3 hash of the file content.
+fn hash_file(path: &Path) -> Result<String, AnyError> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(File::open(path)?)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Whether the string looks like a [hex-encoded hash][hash_file].
+fn is_hash(s: &str) -> bool {
+    s.len() == blake3::OUT_LEN * 2 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Decode the image (or the video frame) as it should be displayed.
+fn decode(media: &Media) -> Result<DynamicImage, AnyError> {
+    match media.type_ {
+        MediaType::Video => media.video_frame(),
+        MediaType::Photo | MediaType::Animation => {
+            let image = ImageReader::open(media.path())?
+                .with_guessed_format()?
+                .decode()?;
+            let orientation = read_exif(media.path())
+                .ok()
+                .and_then(|exif| {
+                    let field = exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?;
+                    field.value.get_uint(0)
+                })
+                .and_then(|value| Orientation::from_exif(i64::from(value)));
+            Ok(match orientation {
+                Some(orientation) => orientation.apply(image),
+                None => image,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use image::RgbImage;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("foton-thumbs-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn photo(dir: &Path, name: &str, width: u32) -> Media {
+        let path = dir.join(name);
+        RgbImage::new(width, 10).save(&path).unwrap();
+        Media {
+            type_: MediaType::Photo,
+            path,
+        }
+    }
+
+    fn options(sizes: &[u32], format: ThumbnailFormat) -> ThumbnailOptions {
+        ThumbnailOptions {
+            sizes: sizes.to_vec(),
+            format,
+            ..ThumbnailOptions::default()
+        }
+    }
+
+    #[test]
+    fn fresh_thumbnails_are_reused() {
+        let dir = temp_dir("fresh");
+        let media = photo(&dir, "a.png", 20);
+        let options = options(&[8, 16], ThumbnailFormat::Jpeg);
+
+        let mut cache = ThumbnailCache::open(&dir.join("cache"), &options).unwrap();
+        let first = cache.get(&media, false).unwrap();
+        assert!(first.generated);
+        assert_eq!(first.hash.len(), 64);
+        assert!(first.paths.iter().all(|(_, path)| path.exists()));
+        assert!(!cache.get(&media, false).unwrap().generated);
+        assert!(cache.get(&media, true).unwrap().generated);
+        cache.save_index().unwrap();
+
+        // the index survives reopening
+        let mut cache = ThumbnailCache::open(&dir.join("cache"), &options).unwrap();
+        let again = cache.get(&media, false).unwrap();
+        assert_eq!(
+            again,
+            Thumbnails {
+                generated: false,
+                ..first.clone()
+            }
+        );
+
+        // the changed media gets the new thumbnails
+        photo(&dir, "a.png", 30);
+        let changed = cache.get(&media, false).unwrap();
+        assert!(changed.generated);
+        assert_ne!(changed.hash, first.hash);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_hashes_are_ignored() {
+        let dir = temp_dir("invalid");
+        let media = photo(&dir, "a.png", 20);
+        let metadata = fs::metadata(media.path()).unwrap();
+        let cache_dir = dir.join("cache");
+        fs::create_dir_all(&cache_dir).unwrap();
+        let index: Map<_, _> = [(
+            media.path().to_owned(),
+            IndexEntry {
+                len: metadata.len(),
+                modified: metadata.modified().unwrap(),
+                hash: "é".to_owned(),
+            },
+        )]
+        .into();
+        fs::write(
+            cache_dir.join(INDEX_NAME),
+            serde_json::to_vec(&index).unwrap(),
+        )
+        .unwrap();
+
+        let mut cache = ThumbnailCache::open(&cache_dir, &ThumbnailOptions::default()).unwrap();
+        assert!(cache.index.is_empty());
+        let thumbnails = cache.get(&media, false).unwrap();
+        assert!(thumbnails.generated);
+        assert!(is_hash(&thumbnails.hash));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prune() {
+        let dir = temp_dir("prune");
+        let cache_dir = dir.join("cache");
+        let kept = photo(&dir, "kept.png", 20);
+        let deleted = photo(&dir, "deleted.png", 30);
+
+        let old = options(&[8, 16], ThumbnailFormat::Jpeg);
+        let mut cache = ThumbnailCache::open(&cache_dir, &old).unwrap();
+        let old_kept = cache.get(&kept, false).unwrap();
+        let old_deleted = cache.get(&deleted, false).unwrap();
+        cache.save_index().unwrap();
+
+        // the size 16 is kept, the size 8 and the JPEG ones are not used anymore
+        let new = options(&[16, 32], ThumbnailFormat::WebP);
+        let mut cache = ThumbnailCache::open(&cache_dir, &new).unwrap();
+        let new_kept = cache.get(&kept, false).unwrap();
+        let stray = cache_dir.join("notes.txt");
+        fs::write(&stray, "not a thumbnail").unwrap();
+
+        let removed = cache.prune(|path| path == kept.path()).unwrap();
+        assert_eq!(removed, 4);
+        assert!(old_kept
+            .paths
+            .iter()
+            .chain(&old_deleted.paths)
+            .all(|(_, path)| !path.exists()));
+        assert!(new_kept.paths.iter().all(|(_, path)| path.exists()));
+        assert!(stray.exists());
+        assert_eq!(cache.prune(|_| true).unwrap(), 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
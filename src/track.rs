@@ -0,0 +1,219 @@
+//! GPS tracks recorded separately from the media (e.g. by a phone)
+//! in the GPX or KML format.
+use std::{fs, path::Path};
+
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use log::debug;
+use roxmltree::{Document, Node};
+
+use crate::{
+    event::space::{InfoSource as LocationSource, Location},
+    AnyError,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+/// Single recorded position.
+pub struct TrackPoint {
+    /// The time of the record.
+    pub time: DateTime<Utc>,
+    /// Latitude in degrees.
+    pub latitude: f64,
+    /// Longitude in degrees.
+    pub longitude: f64,
+    /// Altitude in meters above the sea level.
+    pub altitude: Option<f64>,
+}
+
+impl TrackPoint {
+    /// The position as if it was [embedded][LocationSource::Embedded] into the media.
+    pub fn location(&self) -> Location {
+        Location::new(
+            self.latitude,
+            self.longitude,
+            self.altitude,
+            LocationSource::Embedded,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// The recorded positions ordered by time.
+pub struct Track {
+    points: Vec<TrackPoint>,
+}
+
+impl Track {
+    /// Read the track from a `.gpx` or `.kml` file.
+    pub fn load(path: &Path) -> Result<Self, AnyError> {
+        let content = fs::read_to_string(path)?;
+        let ext = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        match ext.as_deref() {
+            Some("gpx") => Self::from_gpx(&content),
+            Some("kml") => Self::from_kml(&content),
+            _ => Err(format!("Unknown track format: {}", path.display()).into()),
+        }
+    }
+
+    /// Parse the points (`trkpt`) of all the tracks in a GPX document.
+    ///
+    /// The points without time are skipped.
+    pub fn from_gpx(content: &str) -> Result<Self, AnyError> {
+        let doc = Document::parse(content)?;
+        let points = doc
+            .descendants()
+            .filter(|node| node.tag_name().name() == "trkpt")
+            .filter_map(|node| {
+                let latitude = node.attribute("lat")?.trim().parse().ok()?;
+                let longitude = node.attribute("lon")?.trim().parse().ok()?;
+                let time = parse_time(child_text(node, "time")?)?;
+                let altitude = child_text(node, "ele").and_then(|ele| ele.trim().parse().ok());
+                Some(TrackPoint {
+                    time,
+                    latitude,
+                    longitude,
+                    altitude,
+                })
+            });
+        Ok(Self::new(points))
+    }
+
+    /// Parse the timed points of a KML document:
+    /// either the `gx:Track` (as exported by the Google Timeline)
+    /// or the `Placemark` with both `TimeStamp` and `Point`.
+    pub fn from_kml(content: &str) -> Result<Self, AnyError> {
+        let doc = Document::parse(content)?;
+        let mut points = vec![];
+        for node in doc.descendants() {
+            match node.tag_name().name() {
+                "Track" => {
+                    let times = children(node, "when").filter_map(|n| parse_time(n.text()?));
+                    let coords = children(node, "coord").map(|n| {
+                        parse_coordinates(n.text().unwrap_or_default().split_whitespace())
+                    });
+                    points.extend(times.zip(coords).filter_map(|(time, coords)| {
+                        let (longitude, latitude, altitude) = coords?;
+                        Some(TrackPoint {
+                            time,
+                            latitude,
+                            longitude,
+                            altitude,
+                        })
+                    }));
+                }
+                "Placemark" => {
+                    let time = node
+                        .descendants()
+                        .find(|n| n.tag_name().name() == "TimeStamp")
+                        .and_then(|n| parse_time(child_text(n, "when")?));
+                    let coords = node
+                        .descendants()
+                        .find(|n| n.tag_name().name() == "Point")
+                        .and_then(|n| parse_coordinates(child_text(n, "coordinates")?.split(',')));
+                    if let (Some(time), Some((longitude, latitude, altitude))) = (time, coords) {
+                        points.push(TrackPoint {
+                            time,
+                            latitude,
+                            longitude,
+                            altitude,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(Self::new(points))
+    }
+
+    fn new(points: impl IntoIterator<Item = TrackPoint>) -> Self {
+        let mut track = Self::default();
+        track.extend(points);
+        track
+    }
+
+    /// Add the points of another track.
+    pub fn extend(&mut self, points: impl IntoIterator<Item = TrackPoint>) {
+        self.points.extend(points);
+        self.points.sort_by_key(|point| point.time);
+    }
+
+    /// All the points ordered by time.
+    pub fn points(&self) -> &[TrackPoint] {
+        &self.points
+    }
+
+    /// Find the position at the given time.
+    ///
+    /// The position is linearly interpolated between the surrounding points
+    /// if they are not farther from each other than the `max_gap`.
+    /// Otherwise, the closest of them is used if it is not farther than the `max_gap`
+    /// from the time (this also applies to the time before or after the whole track).
+    pub fn locate(&self, time: DateTime<Utc>, max_gap: TimeDelta) -> Option<TrackPoint> {
+        let next_index = self.points.partition_point(|point| point.time < time);
+        let prev = next_index
+            .checked_sub(1)
+            .and_then(|index| self.points.get(index));
+        let next = self.points.get(next_index);
+
+        match (prev, next) {
+            (_, Some(next)) if next.time == time => Some(*next),
+            (Some(prev), Some(next)) if next.time - prev.time <= max_gap => {
+                let total = (next.time - prev.time).as_seconds_f64();
+                let ratio = (time - prev.time).as_seconds_f64() / total;
+                let lerp = |from: f64, to: f64| from + (to - from) * ratio;
+                Some(TrackPoint {
+                    time,
+                    latitude: lerp(prev.latitude, next.latitude),
+                    longitude: lerp(prev.longitude, next.longitude),
+                    altitude: prev
+                        .altitude
+                        .zip(next.altitude)
+                        .map(|(from, to)| lerp(from, to)),
+                })
+            }
+            _ => [prev, next]
+                .into_iter()
+                .flatten()
+                .filter(|point| (point.time - time).abs() <= max_gap)
+                .min_by_key(|point| (point.time - time).abs())
+                .map(|point| TrackPoint { time, ..*point }),
+        }
+    }
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.tag_name().name() == name)
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &'a str) -> Option<&'a str> {
+    children(node, name).next()?.text()
+}
+
+/// Parse the ISO 8601 time assuming UTC if the time zone is not specified.
+fn parse_time(text: &str) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    let time = DateTime::parse_from_rfc3339(text)
+        .map(|time| time.to_utc())
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f").map(|t| t.and_utc())
+        });
+    if let Err(err) = &time {
+        debug!("Invalid track time {:?}: {}", text, err);
+    }
+    time.ok()
+}
+
+/// Parse the KML `longitude, latitude[, altitude]` triple.
+fn parse_coordinates<'a>(
+    mut parts: impl Iterator<Item = &'a str>,
+) -> Option<(f64, f64, Option<f64>)> {
+    let mut next = || parts.next().and_then(|part| part.trim().parse().ok());
+    let longitude = next()?;
+    let latitude = next()?;
+    Some((longitude, latitude, next()))
+}
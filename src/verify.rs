@@ -0,0 +1,319 @@
+//! Integrity checks of the media files damaged by a failing storage
+//! or an interrupted copying: the empty, truncated or corrupt files.
+//!
+//! The photos are decoded completely, the videos are demuxed
+//! reading every packet of the container.
+use std::{
+    error::Error,
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek},
+    path::{Component, Path},
+};
+
+use image::{codecs::gif::GifDecoder, AnimationDecoder as _, ImageError, ImageFormat, ImageReader};
+use log::debug;
+
+use crate::{
+    file_types::{Media, MediaType},
+    tags::FFMPEG,
+};
+
+/// The JPEG start of image marker.
+const JPEG_SOI: [u8; 2] = [0xFF, 0xD8];
+/// The second byte of the JPEG end of image marker.
+const JPEG_EOI: u8 = 0xD9;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// The outcome of the check of an undamaged file.
+pub enum Integrity {
+    /// The whole content has been decoded (or demuxed) successfully.
+    Intact,
+    /// The format could not be decoded (e.g. HEIC), so the file was not checked.
+    Unsupported,
+}
+
+#[derive(Debug)]
+/// The damage found in a media file.
+pub enum Defect {
+    /// The file has no content.
+    Empty,
+    /// The content ends prematurely.
+    Truncated(String),
+    /// The content could not be decoded.
+    Corrupt(String),
+    /// The file could not be read.
+    Io(io::Error),
+}
+
+impl fmt::Display for Defect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("empty file"),
+            Self::Truncated(reason) => write!(f, "truncated ({})", reason),
+            Self::Corrupt(reason) => write!(f, "corrupt ({})", reason),
+            Self::Io(err) => write!(f, "unreadable ({})", err),
+        }
+    }
+}
+
+impl Error for Defect {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Defect {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<ImageError> for Defect {
+    fn from(err: ImageError) -> Self {
+        match err {
+            ImageError::IoError(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                Self::Truncated(err.to_string())
+            }
+            ImageError::IoError(err) => Self::Io(err),
+            err => Self::Corrupt(err.to_string()),
+        }
+    }
+}
+
+impl Media {
+    /// Check whether the file could be displayed completely.
+    ///
+    /// The lenient decoders fill the missing part of a truncated JPEG
+    /// with grey, so the end of image marker is required additionally.
+    pub fn verify(&self) -> Result<Integrity, Defect> {
+        if fs::metadata(self.path())?.len() == 0 {
+            return Err(Defect::Empty);
+        }
+        match self.type_ {
+            MediaType::Photo | MediaType::Animation => verify_image(self.path()),
+            MediaType::Video => self.demux(),
+        }
+    }
+
+    /// Move the media along with its sidecars into the `dir`
+    /// reproducing the full path of its directory inside,
+    /// so the files with the same names do not clash.
+    ///
+    /// Returns the moved [`Media`].
+    pub fn quarantine(&self, dir: &Path) -> io::Result<Self> {
+        let mut target = dir.to_path_buf();
+        if let Some(parent) = self.path().parent() {
+            target.extend(parent.components().filter_map(|c| match c {
+                Component::Normal(name) => Some(name),
+                _ => None,
+            }));
+        }
+        self.move_to(&target)
+    }
+
+    /// Read all the packets of the container.
+    fn demux(&self) -> Result<Integrity, Defect> {
+        let mut input = FFMPEG
+            .input(self.path())
+            .map_err(|err| Defect::Corrupt(err.to_string()))?;
+        let mut packets = 0_usize;
+        loop {
+            // the `packets()` iterator silently skips the errors
+            let mut packet = ffmpeg_next::Packet::empty();
+            match packet.read(&mut input) {
+                Ok(()) => {
+                    if packet.is_corrupt() {
+                        return Err(Defect::Corrupt(format!(
+                            "damaged packet of the stream #{} at byte {}",
+                            packet.stream(),
+                            packet.position()
+                        )));
+                    }
+                    packets += 1;
+                }
+                Err(ffmpeg_next::Error::Eof) => break,
+                Err(err) => {
+                    return Err(Defect::Corrupt(format!(
+                        "{} after {} packets",
+                        err, packets
+                    )))
+                }
+            }
+        }
+        debug!("{}: demuxed {} packets", self, packets);
+        if packets == 0 {
+            return Err(Defect::Truncated("no media data".into()));
+        }
+        Ok(Integrity::Intact)
+    }
+}
+
+fn verify_image(path: &Path) -> Result<Integrity, Defect> {
+    // the format is detected by the content only, not by the extension
+    let reader = ImageReader::new(BufReader::new(File::open(path)?)).with_guessed_format()?;
+    let Some(format) = reader.format() else {
+        return Ok(Integrity::Unsupported);
+    };
+    if format == ImageFormat::Jpeg && !has_jpeg_end(&mut BufReader::new(File::open(path)?))? {
+        return Err(Defect::Truncated("no end of image marker".into()));
+    }
+    let decoded = if format == ImageFormat::Gif {
+        // every frame of an animation
+        GifDecoder::new(reader.into_inner())
+            .and_then(|decoder| decoder.into_frames().collect_frames())
+            .map(|frames| debug!("Decoded {} frames", frames.len()))
+    } else {
+        reader.decode().map(|_| ())
+    };
+    match decoded {
+        Ok(_) => Ok(Integrity::Intact),
+        Err(ImageError::Unsupported(err)) => {
+            debug!("Not verified: {}", err);
+            Ok(Integrity::Unsupported)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Whether the end of image marker follows the image data.
+///
+/// The marker segments are skipped by their lengths, so the embedded EXIF thumbnail
+/// (having its own end marker) is not mistaken for the end of the image.
+/// Inside the entropy-coded data `0xFF` is always followed by `0x00`
+/// or a restart marker, so the end marker could not appear there by chance.
+fn has_jpeg_end<R: Read + Seek>(reader: &mut BufReader<R>) -> io::Result<bool> {
+    let mut soi = [0; 2];
+    for byte in &mut soi {
+        let Some(next) = read_byte(reader)? else {
+            return Ok(false);
+        };
+        *byte = next;
+    }
+    if soi != JPEG_SOI {
+        return Ok(false);
+    }
+
+    let mut after_ff = false;
+    while let Some(byte) = read_byte(reader)? {
+        if !after_ff {
+            // the entropy-coded data
+            after_ff = byte == 0xFF;
+            continue;
+        }
+        match byte {
+            JPEG_EOI => return Ok(true),
+            // fill byte
+            0xFF => {}
+            // stuffed zero, temporary and restart markers without a payload
+            0x00 | 0x01 | 0xD0..=0xD7 => after_ff = false,
+            _ => {
+                let (Some(high), Some(low)) = (read_byte(reader)?, read_byte(reader)?) else {
+                    return Ok(false);
+                };
+                // the length includes its own two bytes
+                reader.seek_relative(i64::from(u16::from_be_bytes([high, low])) - 2)?;
+                after_ff = false;
+            }
+        }
+    }
+    Ok(false)
+}
+
+fn read_byte(reader: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match reader.read_exact(&mut byte) {
+        Ok(()) => Ok(Some(byte[0])),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, io::Cursor, path::PathBuf, process};
+
+    use image::RgbImage;
+
+    use super::*;
+
+    /// SOI, APP1 with an embedded thumbnail, SOS and the entropy-coded data.
+    fn jpeg_without_end() -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8];
+        let thumbnail = [0xFF, 0xD8, 0x12, 0x34, 0xFF, 0xD9];
+        data.extend_from_slice(&[0xFF, 0xE1, 0x00, 2 + 6]);
+        data.extend_from_slice(&thumbnail);
+        data.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x04, 0x01, 0x02]);
+        // the stuffed zero, a restart marker and a fill byte
+        data.extend_from_slice(&[0x11, 0xFF, 0x00, 0x22, 0xFF, 0xD0, 0x33, 0xFF]);
+        data
+    }
+
+    fn has_end(data: &[u8]) -> bool {
+        has_jpeg_end(&mut BufReader::new(Cursor::new(data))).unwrap()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("foton-verify-{}-{}", process::id(), name))
+    }
+
+    #[test]
+    fn jpeg_end_marker() {
+        let mut data = jpeg_without_end();
+        // only the thumbnail's marker is present
+        assert!(!has_end(&data));
+        data.push(0xD9);
+        assert!(has_end(&data));
+
+        assert!(!has_end(&[]));
+        assert!(!has_end(&[0xFF]));
+        assert!(!has_end(b"\x89PNG\r\n"));
+        // the segment length overruns the file
+        assert!(!has_end(&[0xFF, 0xD8, 0xFF, 0xE1, 0x10, 0x00, 0xFF, 0xD9]));
+        assert!(!has_end(&[0xFF, 0xD8, 0xFF, 0xE1, 0x10]));
+    }
+
+    #[test]
+    fn verify_files() {
+        let image = RgbImage::from_fn(64, 48, |x, y| image::Rgb([x as u8 * 4, y as u8 * 5, 128]));
+        let jpeg = temp_path("intact.jpg");
+        let png = temp_path("intact.png");
+        image.save(&jpeg).unwrap();
+        image.save(&png).unwrap();
+        let photo = |path: &Path| Media {
+            type_: MediaType::Photo,
+            path: path.to_owned(),
+        };
+        assert_eq!(photo(&jpeg).verify().unwrap(), Integrity::Intact);
+        assert_eq!(photo(&png).verify().unwrap(), Integrity::Intact);
+
+        let content = fs::read(&jpeg).unwrap();
+        let truncated = temp_path("truncated.jpg");
+        fs::write(&truncated, &content[..content.len() - 10]).unwrap();
+        assert!(matches!(
+            photo(&truncated).verify(),
+            Err(Defect::Truncated(_))
+        ));
+
+        let empty = temp_path("empty.jpg");
+        fs::write(&empty, "").unwrap();
+        assert!(matches!(photo(&empty).verify(), Err(Defect::Empty)));
+
+        // the extension does not make it a JPEG
+        let unknown = temp_path("unknown.jpg");
+        fs::write(&unknown, "not an image").unwrap();
+        assert_eq!(photo(&unknown).verify().unwrap(), Integrity::Unsupported);
+
+        assert!(matches!(
+            photo(&temp_path("missing.jpg")).verify(),
+            Err(Defect::Io(_))
+        ));
+
+        for path in [jpeg, png, truncated, empty, unknown] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,364 @@
+//! Technical metadata of the video files: duration,
+//! resolution, codecs and other properties of the streams.
+use std::{fmt, time::Duration};
+
+use chrono::NaiveDateTime;
+use ffmpeg_next::{
+    codec, format::Pixel, frame, media, packet::side_data, software::scaling, Rational, Stream,
+};
+use image::{DynamicImage, RgbImage};
+use log::debug;
+
+use crate::{
+    file_types::{Media, MediaType},
+    tags::{TagValue, FFMPEG},
+    technical::Orientation,
+    AnyError,
+};
+
+/// The units of the container's duration (`AV_TIME_BASE`).
+const AV_TIME_BASE: f64 = 1_000_000.0;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+/// Type of a stream inside the media container.
+pub enum StreamKind {
+    /// Video track.
+    Video,
+    /// Audio track.
+    Audio,
+    /// Subtitles.
+    Subtitle,
+    /// Arbitrary data, e.g. timecodes or camera telemetry.
+    Data,
+    /// Attachments or unknown streams.
+    Other,
+}
+
+impl From<media::Type> for StreamKind {
+    fn from(value: media::Type) -> Self {
+        match value {
+            media::Type::Video => Self::Video,
+            media::Type::Audio => Self::Audio,
+            media::Type::Subtitle => Self::Subtitle,
+            media::Type::Data => Self::Data,
+            media::Type::Unknown | media::Type::Attachment => Self::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Properties of a single stream.
+///
+/// The video-specific and audio-specific fields
+/// are only filled for the corresponding streams.
+pub struct StreamInfo {
+    /// Index of the stream in the container.
+    pub index: usize,
+    /// Type of the stream.
+    pub kind: StreamKind,
+    /// Name of the codec, e.g. `h264` or `aac`.
+    pub codec: String,
+    /// Duration of the stream.
+    pub duration: Option<Duration>,
+    /// Bitrate in bits per second.
+    pub bit_rate: Option<u64>,
+    /// The stream-level creation time (usually in UTC).
+    pub creation_time: Option<NaiveDateTime>,
+    /// Width of the encoded frames in pixels.
+    pub width: Option<u32>,
+    /// Height of the encoded frames in pixels.
+    pub height: Option<u32>,
+    /// Frames per second.
+    pub frame_rate: Option<f64>,
+    /// Clockwise rotation (in degrees) to apply when displaying the video.
+    pub rotation: Option<i32>,
+    /// Number of audio channels.
+    pub channels: Option<u16>,
+    /// Audio sample rate in Hz.
+    pub sample_rate: Option<u32>,
+}
+
+impl StreamInfo {
+    /// Width and height as displayed, i.e. taking the rotation into account.
+    pub fn display_size(&self) -> Option<(u32, u32)> {
+        let (width, height) = (self.width?, self.height?);
+        if self.rotation.is_some_and(|r| r % 180 == 90) {
+            Some((height, width))
+        } else {
+            Some((width, height))
+        }
+    }
+}
+
+impl fmt::Display for StreamInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} {:?} {}", self.index, self.kind, self.codec)?;
+        if let (Some(width), Some(height)) = (self.width, self.height) {
+            write!(f, " {}x{}", width, height)?;
+        }
+        if let Some(fps) = self.frame_rate {
+            write!(f, " {:.2} fps", fps)?;
+        }
+        if let Some(rotation) = self.rotation.filter(|r| *r != 0) {
+            write!(f, " rotated {}°", rotation)?;
+        }
+        if let Some(channels) = self.channels {
+            write!(f, " {} ch", channels)?;
+        }
+        if let Some(rate) = self.sample_rate {
+            write!(f, " {} Hz", rate)?;
+        }
+        if let Some(bit_rate) = self.bit_rate {
+            write!(f, " {} kb/s", bit_rate / 1000)?;
+        }
+        if let Some(duration) = self.duration {
+            write!(f, " {:.3}s", duration.as_secs_f64())?;
+        }
+        if let Some(time) = self.creation_time {
+            write!(f, " created {}", time)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Technical metadata of a video file.
+pub struct VideoInfo {
+    /// Duration of the whole container.
+    pub duration: Option<Duration>,
+    /// Overall bitrate in bits per second.
+    pub bit_rate: Option<u64>,
+    /// All the streams of the container.
+    pub streams: Vec<StreamInfo>,
+}
+
+impl VideoInfo {
+    /// The first video stream (the main one).
+    pub fn video_stream(&self) -> Option<&StreamInfo> {
+        self.streams.iter().find(|s| s.kind == StreamKind::Video)
+    }
+
+    /// All the audio streams.
+    pub fn audio_streams(&self) -> impl Iterator<Item = &StreamInfo> {
+        self.streams.iter().filter(|s| s.kind == StreamKind::Audio)
+    }
+
+    /// Duration of the container or the longest stream if the former is unknown.
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+            .or_else(|| self.streams.iter().filter_map(|s| s.duration).max())
+    }
+
+    /// Width and height of the main video stream as displayed.
+    pub fn resolution(&self) -> Option<(u32, u32)> {
+        self.video_stream()?.display_size()
+    }
+}
+
+impl fmt::Display for VideoInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((width, height)) = self.resolution() {
+            write!(f, "{}x{}", width, height)?;
+        } else {
+            f.write_str("no video")?;
+        }
+        if let Some(fps) = self.video_stream().and_then(|s| s.frame_rate) {
+            write!(f, " @ {:.2} fps", fps)?;
+        }
+        if let Some(duration) = self.duration() {
+            write!(f, ", {:.3}s", duration.as_secs_f64())?;
+        }
+        if let Some(bit_rate) = self.bit_rate {
+            write!(f, ", {} kb/s", bit_rate / 1000)?;
+        }
+        write!(f, ", {} stream(s)", self.streams.len())
+    }
+}
+
+impl Media {
+    /// Read the technical metadata of a video (or an animation)
+    /// including the properties of every stream.
+    pub fn video_info(&self) -> Result<VideoInfo, AnyError> {
+        if self.type_ == MediaType::Photo {
+            return Err(format!("{} is not a video", self).into());
+        }
+
+        let input = FFMPEG.input(self.path())?;
+        #[allow(clippy::cast_precision_loss)]
+        let duration = Some(input.duration())
+            .filter(|d| *d > 0)
+            .map(|d| Duration::from_secs_f64(d as f64 / AV_TIME_BASE));
+        let bit_rate = u64::try_from(input.bit_rate()).ok().filter(|b| *b > 0);
+        let streams = input
+            .streams()
+            .map(|stream| {
+                let info = stream_info(&stream);
+                debug!("{}: {}", self, info);
+                info
+            })
+            .collect();
+
+        Ok(VideoInfo {
+            duration,
+            bit_rate,
+            streams,
+        })
+    }
+
+    /// Decode a representative frame of the video
+    /// (taken at 10% of the duration to skip the usual dark beginning)
+    /// rotated as displayed.
+    pub(crate) fn video_frame(&self) -> Result<DynamicImage, AnyError> {
+        let mut input = FFMPEG.input(self.path())?;
+        let stream = input
+            .streams()
+            .best(media::Type::Video)
+            .ok_or("no video stream")?;
+        let index = stream.index();
+        let rotation = stream_info(&stream).rotation;
+        let mut decoder = codec::context::Context::from_parameters(stream.parameters())?
+            .decoder()
+            .video()?;
+
+        let duration = input.duration();
+        if duration > 0 {
+            let target = duration / 10;
+            if let Err(err) = input.seek(target, ..target) {
+                debug!("{}: failed to seek: {}", self, err);
+            }
+        }
+
+        let mut decoded = frame::Video::empty();
+        let mut found = false;
+        for (stream, packet) in input.packets() {
+            if stream.index() != index {
+                continue;
+            }
+            decoder.send_packet(&packet)?;
+            if decoder.receive_frame(&mut decoded).is_ok() {
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            decoder.send_eof()?;
+            decoder.receive_frame(&mut decoded)?;
+        }
+
+        let (width, height) = (decoded.width(), decoded.height());
+        let mut scaler = scaling::Context::get(
+            decoded.format(),
+            width,
+            height,
+            Pixel::RGB24,
+            width,
+            height,
+            scaling::Flags::BILINEAR,
+        )?;
+        let mut rgb = frame::Video::empty();
+        scaler.run(&decoded, &mut rgb)?;
+
+        // the lines of the frame could be padded
+        let row = width as usize * 3;
+        let pixels = rgb
+            .data(0)
+            .chunks(rgb.stride(0))
+            .take(height as usize)
+            .flat_map(|line| &line[..row])
+            .copied()
+            .collect();
+        let image = RgbImage::from_raw(width, height, pixels).ok_or("invalid frame size")?;
+        let orientation = rotation
+            .and_then(Orientation::from_rotation)
+            .unwrap_or(Orientation::Normal);
+        Ok(orientation.apply(image.into()))
+    }
+}
+
+fn stream_info(stream: &Stream<'_>) -> StreamInfo {
+    let parameters = stream.parameters();
+    let kind = StreamKind::from(parameters.medium());
+    let mut info = StreamInfo {
+        index: stream.index(),
+        kind,
+        codec: parameters.id().name().to_owned(),
+        duration: stream_duration(stream.duration(), stream.time_base()),
+        bit_rate: None,
+        creation_time: stream
+            .metadata()
+            .get("creation_time")
+            .and_then(|time| TagValue::from_text(time).as_datetime()),
+        width: None,
+        height: None,
+        frame_rate: None,
+        rotation: None,
+        channels: None,
+        sample_rate: None,
+    };
+
+    let decoder = codec::context::Context::from_parameters(parameters).map(|ctx| ctx.decoder());
+    match kind {
+        StreamKind::Video => {
+            if let Ok(video) = decoder.and_then(|d| d.video()) {
+                info.width = Some(video.width()).filter(|w| *w > 0);
+                info.height = Some(video.height()).filter(|h| *h > 0);
+                info.bit_rate = positive(video.bit_rate());
+            }
+            info.frame_rate = [stream.avg_frame_rate(), stream.rate()]
+                .into_iter()
+                .find_map(rational_to_f64);
+            info.rotation = stream
+                .side_data()
+                .find(|data| data.kind() == side_data::Type::DisplayMatrix)
+                .and_then(|data| display_matrix_rotation(data.data()))
+                .or_else(|| {
+                    let rotate = stream.metadata().get("rotate")?.parse::<i32>().ok()?;
+                    Some(rotate.rem_euclid(360))
+                });
+        }
+        StreamKind::Audio => {
+            if let Ok(audio) = decoder.and_then(|d| d.audio()) {
+                info.channels = Some(audio.channels()).filter(|c| *c > 0);
+                info.sample_rate = Some(audio.rate()).filter(|r| *r > 0);
+                info.bit_rate = positive(audio.bit_rate());
+            }
+        }
+        StreamKind::Subtitle | StreamKind::Data | StreamKind::Other => {}
+    }
+    info
+}
+
+fn positive(value: usize) -> Option<u64> {
+    u64::try_from(value).ok().filter(|v| *v > 0)
+}
+
+fn rational_to_f64(value: Rational) -> Option<f64> {
+    (value.numerator() > 0 && value.denominator() > 0).then(|| f64::from(value))
+}
+
+/// Convert the duration measured in the `time_base` units.
+fn stream_duration(duration: i64, time_base: Rational) -> Option<Duration> {
+    let time_base = rational_to_f64(time_base)?;
+    // the unknown duration is a large negative number
+    if duration <= 0 {
+        return None;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    Some(Duration::from_secs_f64(duration as f64 * time_base))
+}
+
+/// Calculate the rotation from the 3x3 display transformation matrix
+/// (the same way as the `ffprobe` calculates the legacy `rotate` tag).
+fn display_matrix_rotation(matrix: &[u8]) -> Option<i32> {
+    let value = |i: usize| -> Option<f64> {
+        let bytes = matrix.get(i * 4..i * 4 + 4)?.try_into().ok()?;
+        Some(f64::from(i32::from_ne_bytes(bytes)))
+    };
+    let (cos, sin) = (value(0)?, value(1)?);
+    if cos == 0.0 && sin == 0.0 {
+        return None;
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    let degrees = sin.atan2(cos).to_degrees().round() as i32;
+    Some(degrees.rem_euclid(360))
+}